@@ -1,13 +1,23 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fmt;
-use reqwest::Client;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use futures_util::stream::{self, StreamExt};
+use rand::Rng;
+use reqwest::{Certificate, Client, Proxy, RequestBuilder, Response, StatusCode};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::model_info::{ModelInfo, ModelMetadata};
 
 #[derive(Debug)]
 pub enum AIError {
     HttpError(reqwest::Error),
     SerdeError(serde_json::Error),
-    APIError(String),
+    Provider(ProviderError),
+    InvalidConfig(String),
 }
 
 impl fmt::Display for AIError {
@@ -15,7 +25,8 @@ impl fmt::Display for AIError {
         match self {
             AIError::HttpError(e) => write!(f, "HTTP request error: {}", e),
             AIError::SerdeError(e) => write!(f, "JSON serialization error: {}", e),
-            AIError::APIError(e) => write!(f, "API error: {}", e),
+            AIError::Provider(e) => write!(f, "API error: {}", e),
+            AIError::InvalidConfig(e) => write!(f, "Invalid connection settings: {}", e),
         }
     }
 }
@@ -24,7 +35,8 @@ impl Error for AIError {}
 
 impl From<reqwest::Error> for AIError {
     fn from(error: reqwest::Error) -> Self {
-        AIError::HttpError(error)
+        // Strip the URL so query-string API keys (Gemini) never end up in messages
+        AIError::HttpError(error.without_url())
     }
 }
 
@@ -34,6 +46,207 @@ impl From<serde_json::Error> for AIError {
     }
 }
 
+impl From<ProviderError> for AIError {
+    fn from(error: ProviderError) -> Self {
+        AIError::Provider(error)
+    }
+}
+
+impl AIError {
+    // Describe any error in the structured form reported to the frontend
+    pub fn to_provider_error(&self) -> ProviderError {
+        match self {
+            AIError::Provider(e) => e.clone(),
+            AIError::HttpError(e) => {
+                let kind = if e.is_timeout() {
+                    ErrorKind::Timeout
+                } else if e.is_decode() {
+                    ErrorKind::InvalidResponse
+                } else if e.is_connect() || e.is_request() {
+                    ErrorKind::Network
+                } else {
+                    ErrorKind::Unknown
+                };
+                ProviderError::new(kind, e.status().map(|s| s.as_u16()), e.to_string())
+            }
+            AIError::SerdeError(e) => ProviderError::new(ErrorKind::InvalidResponse, None, e.to_string()),
+            AIError::InvalidConfig(_) => ProviderError::new(ErrorKind::InvalidConfig, None, self.to_string()),
+        }
+    }
+}
+
+// Category of a failed provider call, independent of the provider's own error format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    InvalidApiKey,
+    PermissionDenied,
+    QuotaExceeded,
+    RateLimited,
+    ModelNotFound,
+    ContextTooLong,
+    ContentFiltered,
+    InvalidRequest,
+    Overloaded,
+    ServerError,
+    Timeout,
+    Network,
+    EmptyResponse,
+    InvalidResponse,
+    InvalidConfig,
+    Unknown,
+}
+
+impl ErrorKind {
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorKind::RateLimited | ErrorKind::Overloaded | ErrorKind::ServerError | ErrorKind::Timeout | ErrorKind::Network
+        )
+    }
+}
+
+// Longest provider message passed on to the frontend
+const MAX_ERROR_MESSAGE_LEN: usize = 300;
+
+// A provider error reduced to the fields the UI needs. The raw response body is
+// never kept, since providers may echo parts of the request back in it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderError {
+    pub kind: ErrorKind,
+    pub status: Option<u16>,
+    pub provider_code: Option<String>,
+    pub message: String,
+    pub retryable: bool,
+    pub retry_after_ms: Option<u64>,
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Some(status) => write!(f, "{} (HTTP {})", self.message, status),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error for ProviderError {}
+
+impl ProviderError {
+    pub fn new(kind: ErrorKind, status: Option<u16>, message: impl Into<String>) -> Self {
+        ProviderError {
+            kind,
+            status,
+            provider_code: None,
+            message: message.into(),
+            retryable: kind.is_retryable(),
+            retry_after_ms: None,
+        }
+    }
+
+    pub fn missing_api_key() -> Self {
+        ProviderError::new(
+            ErrorKind::InvalidApiKey,
+            None,
+            "API key not set for this provider. Please set an API key in the Providers page.",
+        )
+    }
+
+    pub fn empty_response(message: &str) -> Self {
+        ProviderError::new(ErrorKind::EmptyResponse, None, message)
+    }
+
+    // Build an error from a failed HTTP response, understanding the OpenAI
+    // (`{"error": {"message", "type", "code"}}`) and Google
+    // (`{"error": {"code", "message", "status"}}`) envelopes.
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        Self::from_body(Some(status), body)
+    }
+
+    // Build an error from an error event received in the middle of a stream
+    pub fn from_stream_event(data: &str) -> Self {
+        Self::from_body(None, data)
+    }
+
+    fn from_body(status: Option<StatusCode>, body: &str) -> Self {
+        let envelope: serde_json::Value = serde_json::from_str(body).unwrap_or(serde_json::Value::Null);
+        let error = envelope.get("error").unwrap_or(&envelope);
+
+        let text = |key: &str| error.get(key).and_then(|v| v.as_str()).map(|v| v.to_string());
+        let message = match error {
+            serde_json::Value::String(message) => Some(message.clone()),
+            _ => text("message").or_else(|| text("detail")),
+        };
+        // OpenAI puts a symbolic code in `code` (or `type`); Google uses `status`
+        let provider_code = text("code")
+            .or_else(|| text("status"))
+            .or_else(|| text("type"));
+
+        let kind = classify(status, provider_code.as_deref(), text("type").as_deref(), message.as_deref());
+        let message = message
+            .map(|m| truncate_message(m.trim()))
+            .filter(|m| !m.is_empty())
+            .unwrap_or_else(|| status.and_then(|s| s.canonical_reason()).unwrap_or("Request failed").to_string());
+
+        ProviderError {
+            kind,
+            status: status.map(|s| s.as_u16()),
+            provider_code,
+            message,
+            retryable: kind.is_retryable(),
+            retry_after_ms: None,
+        }
+    }
+}
+
+fn truncate_message(message: &str) -> String {
+    if message.chars().count() <= MAX_ERROR_MESSAGE_LEN {
+        return message.to_string();
+    }
+    let truncated: String = message.chars().take(MAX_ERROR_MESSAGE_LEN).collect();
+    format!("{}…", truncated)
+}
+
+fn classify(status: Option<StatusCode>, code: Option<&str>, error_type: Option<&str>, message: Option<&str>) -> ErrorKind {
+    let code = code.unwrap_or_default().to_lowercase();
+    let error_type = error_type.unwrap_or_default().to_lowercase();
+    let message = message.unwrap_or_default().to_lowercase();
+    let mentions = |needles: &[&str]| needles.iter().any(|n| code.contains(n) || error_type.contains(n) || message.contains(n));
+
+    if mentions(&["context_length", "maximum context", "context window", "too many tokens", "prompt is too long"]) {
+        return ErrorKind::ContextTooLong;
+    }
+    if mentions(&["insufficient_quota", "insufficient balance", "billing", "quota exceeded", "exceeded your current quota"]) {
+        return ErrorKind::QuotaExceeded;
+    }
+    if mentions(&["content_filter", "content_policy", "safety"]) {
+        return ErrorKind::ContentFiltered;
+    }
+    if mentions(&["model_not_found", "does not exist", "no such model", "model not found"]) {
+        return ErrorKind::ModelNotFound;
+    }
+    if mentions(&["invalid_api_key", "api_key_invalid", "api key not valid", "incorrect api key", "unauthenticated"]) {
+        return ErrorKind::InvalidApiKey;
+    }
+    if mentions(&["rate_limit", "rate limit"]) {
+        return ErrorKind::RateLimited;
+    }
+
+    match status.map(|s| s.as_u16()).unwrap_or_default() {
+        401 => ErrorKind::InvalidApiKey,
+        402 => ErrorKind::QuotaExceeded,
+        403 => ErrorKind::PermissionDenied,
+        404 => ErrorKind::ModelNotFound,
+        408 | 504 => ErrorKind::Timeout,
+        413 => ErrorKind::ContextTooLong,
+        429 => ErrorKind::RateLimited,
+        503 | 529 => ErrorKind::Overloaded,
+        400 | 422 => ErrorKind::InvalidRequest,
+        s if s >= 500 => ErrorKind::ServerError,
+        _ => ErrorKind::Unknown,
+    }
+}
+
 // Common chat message structure used across all providers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
@@ -46,6 +259,132 @@ pub struct ChatMessage {
 pub struct AIResponse {
     pub content: String,
     pub reasoning: Option<String>,
+    // Id of the stored response (Responses API), used to continue the conversation
+    #[serde(default)]
+    pub response_id: Option<String>,
+    // Tokens billed for the request, when the provider reports them
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+}
+
+// Token counts reported by a provider, in the OpenAI chat completions shape
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+}
+
+// A function the model may call, with its parameters described as JSON schema
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+// A function call requested by the model; `arguments` is a JSON object string
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+}
+
+// Rounds of tool calls answered before the model has to reply with text
+const MAX_TOOL_ROUNDS: usize = 4;
+
+// Incremental output reported while a response is streamed
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StreamDelta {
+    pub content: Option<String>,
+    pub reasoning: Option<String>,
+}
+
+// Which OpenAI API a model is called through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiType {
+    #[default]
+    ChatCompletions,
+    Responses,
+}
+
+impl ApiType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiType::ChatCompletions => "chat_completions",
+            ApiType::Responses => "responses",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "chat_completions" => Some(ApiType::ChatCompletions),
+            "responses" => Some(ApiType::Responses),
+            _ => None,
+        }
+    }
+}
+
+// A single server-sent event
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+// Incremental parser for `text/event-stream` bodies received in arbitrary chunks
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: String,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Feed a chunk of the body and return the events it completed
+    pub fn push(&mut self, chunk: &str) -> Vec<SseEvent> {
+        self.buffer.push_str(&chunk.replace("\r\n", "\n"));
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.find("\n\n") {
+            let block: String = self.buffer.drain(..end + 2).collect();
+            if let Some(event) = Self::parse_block(&block) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    // Flush a trailing event that was not terminated by a blank line
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let block = std::mem::take(&mut self.buffer);
+        Self::parse_block(&block)
+    }
+
+    fn parse_block(block: &str) -> Option<SseEvent> {
+        let mut event = None;
+        let mut data: Vec<&str> = Vec::new();
+
+        for line in block.lines() {
+            if let Some(value) = line.strip_prefix("event:") {
+                event = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data.push(value.strip_prefix(' ').unwrap_or(value));
+            }
+        }
+
+        if data.is_empty() && event.is_none() {
+            return None;
+        }
+        Some(SseEvent { event, data: data.join("\n") })
+    }
 }
 
 // OpenAI specific structures
@@ -57,6 +396,14 @@ mod openai {
         pub model: String,
         pub messages: Vec<super::ChatMessage>,
         pub stream: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub stream_options: Option<StreamOptions>,
+    }
+
+    // Asks for a final chunk carrying the token usage of a streamed completion
+    #[derive(Debug, Serialize)]
+    pub struct StreamOptions {
+        pub include_usage: bool,
     }
     
     #[derive(Debug, Deserialize)]
@@ -64,6 +411,7 @@ mod openai {
         #[allow(dead_code)]
         pub id: String,
         pub choices: Vec<Choice>,
+        pub usage: Option<super::TokenUsage>,
     }
     
     #[derive(Debug, Deserialize)]
@@ -71,22 +419,95 @@ mod openai {
         pub message: super::ChatMessage,
     }
     
+    // Chat completion request offering tools. Messages are raw JSON since tool
+    // calls and their results do not fit the common message structure.
+    #[derive(Debug, Serialize)]
+    pub struct ToolChatRequest<'a> {
+        pub model: &'a str,
+        pub messages: &'a [serde_json::Value],
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub tools: Vec<Tool<'a>>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Tool<'a> {
+        #[serde(rename = "type")]
+        pub kind: &'static str,
+        pub function: &'a super::ToolDefinition,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ToolChatResponse {
+        pub choices: Vec<ToolChoice>,
+        pub usage: Option<super::TokenUsage>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ToolChoice {
+        pub message: ToolMessage,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ToolMessage {
+        pub content: Option<String>,
+        #[serde(default)]
+        pub tool_calls: Vec<WireToolCall>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct WireToolCall {
+        pub id: String,
+        #[serde(rename = "type", default = "function_kind")]
+        pub kind: String,
+        pub function: WireFunction,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct WireFunction {
+        pub name: String,
+        #[serde(default)]
+        pub arguments: String,
+    }
+
+    fn function_kind() -> String {
+        "function".to_string()
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct EmbeddingRequest<'a> {
+        pub model: &'a str,
+        pub input: &'a [String],
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct EmbeddingResponse {
+        pub data: Vec<Embedding>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Embedding {
+        #[serde(default)]
+        pub index: usize,
+        pub embedding: Vec<f32>,
+    }
+
     #[derive(Debug, Deserialize)]
-    #[allow(dead_code)]
     pub struct StreamResponse {
+        #[serde(default)]
         pub choices: Vec<StreamChoice>,
+        pub usage: Option<super::TokenUsage>,
     }
 
     #[derive(Debug, Deserialize)]
-    #[allow(dead_code)]
     pub struct StreamChoice {
         pub delta: StreamDelta,
     }
 
     #[derive(Debug, Deserialize)]
-    #[allow(dead_code)]
     pub struct StreamDelta {
         pub content: Option<String>,
+        // Reasoning text streamed by DeepSeek and other compatible providers
+        pub reasoning_content: Option<String>,
     }
     
     #[derive(Debug, Deserialize)]
@@ -94,12 +515,375 @@ mod openai {
         pub data: Vec<Model>,
     }
     
+    // OpenRouter adds limits, prices and capabilities to the OpenAI listing
     #[derive(Debug, Deserialize)]
     pub struct Model {
         pub id: String,
+        #[serde(default)]
+        pub context_length: Option<i64>,
+        #[serde(default)]
+        pub pricing: Option<Pricing>,
+        #[serde(default)]
+        pub architecture: Option<Architecture>,
+        #[serde(default)]
+        pub top_provider: Option<TopProvider>,
+        #[serde(default)]
+        pub supported_parameters: Option<Vec<String>>,
+    }
+
+    // USD per token, as decimal strings
+    #[derive(Debug, Deserialize)]
+    pub struct Pricing {
+        pub prompt: Option<String>,
+        pub completion: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Architecture {
+        #[serde(default)]
+        pub input_modalities: Vec<String>,
+        #[serde(default)]
+        pub output_modalities: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct TopProvider {
+        pub max_completion_tokens: Option<i64>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct DeploymentListResponse {
+        pub data: Vec<Deployment>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Deployment {
+        pub id: String,
+        pub model: Option<String>,
+    }
+}
+
+// OpenAI Responses API structures
+mod responses {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize)]
+    pub struct CreateRequest {
+        pub model: String,
+        pub input: Vec<InputMessage>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub instructions: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub previous_response_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub reasoning: Option<ReasoningOptions>,
+        pub stream: bool,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct InputMessage {
+        pub role: String,
+        pub content: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct ReasoningOptions {
+        pub summary: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Response {
+        pub id: String,
+        #[serde(default)]
+        pub output: Vec<OutputItem>,
+        pub error: Option<serde_json::Value>,
+        pub usage: Option<Usage>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Usage {
+        #[serde(default)]
+        pub input_tokens: u32,
+        #[serde(default)]
+        pub output_tokens: u32,
+        #[serde(default)]
+        pub total_tokens: u32,
+    }
+
+    impl From<&Usage> for super::TokenUsage {
+        fn from(usage: &Usage) -> Self {
+            super::TokenUsage {
+                prompt_tokens: usage.input_tokens,
+                completion_tokens: usage.output_tokens,
+                total_tokens: usage.total_tokens,
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct OutputItem {
+        #[serde(rename = "type")]
+        pub item_type: String,
+        #[serde(default)]
+        pub content: Vec<TextPart>,
+        #[serde(default)]
+        pub summary: Vec<TextPart>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct TextPart {
+        #[serde(default)]
+        pub text: String,
+    }
+
+    impl Response {
+        // Concatenated text of all output items of the given type
+        pub fn text_of(&self, item_type: &str) -> String {
+            self.output.iter()
+                .filter(|item| item.item_type == item_type)
+                .flat_map(|item| if item_type == "reasoning" { &item.summary } else { &item.content })
+                .map(|part| part.text.as_str())
+                .collect::<Vec<_>>()
+                .join("")
+        }
+    }
+}
+
+pub const DEFAULT_RESPONSES_PATH: &str = "/v1/responses";
+
+// Reasoning summaries are only accepted by reasoning models
+fn supports_reasoning_summary(model: &str) -> bool {
+    let model = model.to_lowercase();
+    let mut chars = model.chars();
+    (chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit())) || model.starts_with("gpt-5")
+}
+
+// Chat completion body asking for a single token. Reasoning models only
+// accept the newer name of the limit.
+fn one_token_chat(model: &str) -> serde_json::Value {
+    let limit = if supports_reasoning_summary(model) { "max_completion_tokens" } else { "max_tokens" };
+    let mut body = serde_json::json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": "ping" }],
+    });
+    body[limit] = serde_json::json!(1);
+    body
+}
+
+// Accumulates a streamed chat completion
+#[derive(Debug, Default)]
+struct ChatStreamState {
+    content: String,
+    reasoning: String,
+    usage: Option<TokenUsage>,
+}
+
+impl ChatStreamState {
+    fn handle(&mut self, event: &SseEvent, on_delta: &mut (dyn FnMut(StreamDelta) + Send)) -> Result<(), AIError> {
+        if event.data.is_empty() || event.data == "[DONE]" {
+            return Ok(());
+        }
+        let data: serde_json::Value = serde_json::from_str(&event.data)?;
+        if data.get("error").is_some() {
+            return Err(ProviderError::from_stream_event(&event.data).into());
+        }
+
+        let chunk: openai::StreamResponse = serde_json::from_value(data)?;
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage;
+        }
+        for choice in chunk.choices {
+            let content = choice.delta.content.filter(|c| !c.is_empty());
+            let reasoning = choice.delta.reasoning_content.filter(|r| !r.is_empty());
+            if content.is_none() && reasoning.is_none() {
+                continue;
+            }
+            if let Some(text) = &content {
+                self.content.push_str(text);
+            }
+            if let Some(text) = &reasoning {
+                self.reasoning.push_str(text);
+            }
+            on_delta(StreamDelta { content, reasoning });
+        }
+        Ok(())
+    }
+
+    fn into_response(self) -> Result<AIResponse, AIError> {
+        if self.content.is_empty() && self.reasoning.is_empty() {
+            return Err(ProviderError::empty_response("No response generated").into());
+        }
+        Ok(AIResponse {
+            content: self.content,
+            reasoning: (!self.reasoning.is_empty()).then_some(self.reasoning),
+            response_id: None,
+            usage: self.usage,
+        })
+    }
+}
+
+// Accumulates a streamed Responses API result
+#[derive(Debug, Default)]
+struct ResponsesStreamState {
+    response_id: Option<String>,
+    content: String,
+    reasoning: String,
+    completed: Option<responses::Response>,
+}
+
+impl ResponsesStreamState {
+    fn handle(&mut self, event: &SseEvent, on_delta: &mut (dyn FnMut(StreamDelta) + Send)) -> Result<(), AIError> {
+        if event.data.is_empty() || event.data == "[DONE]" {
+            return Ok(());
+        }
+        let data: serde_json::Value = serde_json::from_str(&event.data)?;
+        let event_type = event.event.as_deref()
+            .or_else(|| data.get("type").and_then(|t| t.as_str()))
+            .unwrap_or_default();
+        let delta = || data.get("delta").and_then(|d| d.as_str()).unwrap_or_default().to_string();
+
+        match event_type {
+            "response.created" | "response.in_progress" => {
+                if let Some(id) = data.pointer("/response/id").and_then(|v| v.as_str()) {
+                    self.response_id = Some(id.to_string());
+                }
+            }
+            "response.output_text.delta" => {
+                let text = delta();
+                self.content.push_str(&text);
+                on_delta(StreamDelta { content: Some(text), reasoning: None });
+            }
+            "response.reasoning_summary_text.delta" => {
+                let text = delta();
+                self.reasoning.push_str(&text);
+                on_delta(StreamDelta { content: None, reasoning: Some(text) });
+            }
+            // Separate consecutive summary parts
+            "response.reasoning_summary_part.done" if !self.reasoning.is_empty() => {
+                self.reasoning.push_str("\n\n");
+            }
+            "response.completed" => {
+                if let Some(response) = data.get("response") {
+                    self.completed = Some(serde_json::from_value(response.clone())?);
+                }
+            }
+            "response.failed" | "response.incomplete" => {
+                let error = data.pointer("/response/error")
+                    .or_else(|| data.pointer("/response/incomplete_details"))
+                    .map(|e| serde_json::json!({ "error": e }).to_string())
+                    .unwrap_or_default();
+                return Err(ProviderError::from_stream_event(&error).into());
+            }
+            "error" => {
+                return Err(ProviderError::from_stream_event(&event.data).into());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn into_response(self) -> Result<AIResponse, AIError> {
+        let mut content = self.content;
+        let mut reasoning = self.reasoning.trim_end().to_string();
+        let mut response_id = self.response_id;
+        let mut usage = None;
+
+        // Fall back to the final response object if no deltas were streamed
+        if let Some(completed) = &self.completed {
+            response_id = Some(completed.id.clone());
+            usage = completed.usage.as_ref().map(TokenUsage::from);
+            if content.is_empty() {
+                content = completed.text_of("message");
+            }
+            if reasoning.is_empty() {
+                reasoning = completed.text_of("reasoning");
+            }
+            if let Some(error) = completed.error.as_ref().filter(|e| !e.is_null()) {
+                return Err(ProviderError::from_stream_event(&serde_json::json!({ "error": error }).to_string()).into());
+            }
+        }
+
+        if content.is_empty() && response_id.is_none() {
+            return Err(ProviderError::empty_response("No response generated").into());
+        }
+
+        Ok(AIResponse {
+            content,
+            reasoning: (!reasoning.is_empty()).then_some(reasoning),
+            response_id,
+            usage,
+        })
+    }
+}
+
+// API version used to list Azure deployments (not available in newer versions)
+// Model details fetched from an Ollama server at a time
+const OLLAMA_SHOW_CONCURRENCY: usize = 4;
+
+const AZURE_DEPLOYMENTS_API_VERSION: &str = "2022-12-01";
+
+// Default API version for Azure OpenAI chat completions
+pub const AZURE_DEFAULT_API_VERSION: &str = "2024-06-01";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AzureAuthType {
+    // `api-key` header with the resource key
+    #[default]
+    ApiKey,
+    // `Authorization: Bearer` with a Microsoft Entra ID token
+    BearerToken,
+}
+
+impl AzureAuthType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AzureAuthType::ApiKey => "api_key",
+            AzureAuthType::BearerToken => "bearer_token",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "api_key" => Some(AzureAuthType::ApiKey),
+            "bearer_token" => Some(AzureAuthType::BearerToken),
+            _ => None,
+        }
+    }
+}
+
+// Azure OpenAI specific provider settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AzureSettings {
+    pub api_version: String,
+    pub auth_type: AzureAuthType,
+    // Model name -> deployment name; unmapped models are used as deployment names
+    pub deployments: BTreeMap<String, String>,
+}
+
+impl Default for AzureSettings {
+    fn default() -> Self {
+        AzureSettings {
+            api_version: AZURE_DEFAULT_API_VERSION.to_string(),
+            auth_type: AzureAuthType::default(),
+            deployments: BTreeMap::new(),
+        }
+    }
+}
+
+impl AzureSettings {
+    pub fn deployment_for<'a>(&'a self, model: &'a str) -> &'a str {
+        self.deployments.get(model).map(String::as_str).unwrap_or(model)
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct AzureDeployment {
+    pub id: String,
+    pub model: Option<String>,
+}
+
 // Gemini specific structures
 mod gemini {
     use serde::{Deserialize, Serialize};
@@ -121,26 +905,670 @@ mod gemini {
     }
     
     #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
     pub struct ChatResponse {
         pub candidates: Vec<Candidate>,
+        pub usage_metadata: Option<UsageMetadata>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct UsageMetadata {
+        #[serde(default)]
+        pub prompt_token_count: u32,
+        #[serde(default)]
+        pub candidates_token_count: u32,
+        #[serde(default)]
+        pub total_token_count: u32,
+    }
+
+    impl From<&UsageMetadata> for super::TokenUsage {
+        fn from(usage: &UsageMetadata) -> Self {
+            super::TokenUsage {
+                prompt_tokens: usage.prompt_token_count,
+                completion_tokens: usage.candidates_token_count,
+                total_tokens: usage.total_token_count,
+            }
+        }
     }
     
     #[derive(Debug, Deserialize)]
     pub struct Candidate {
         pub content: Content,
     }
+
+    #[derive(Debug, Serialize)]
+    pub struct BatchEmbedRequest {
+        pub requests: Vec<EmbedRequest>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct EmbedRequest {
+        pub model: String,
+        pub content: EmbedContent,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct EmbedContent {
+        pub parts: Vec<Part>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct BatchEmbedResponse {
+        pub embeddings: Vec<ContentEmbedding>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ContentEmbedding {
+        pub values: Vec<f32>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct ModelListResponse {
+        #[serde(default)]
+        pub models: Vec<Model>,
+        pub next_page_token: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Model {
+        // "models/gemini-1.5-pro"
+        pub name: String,
+        pub input_token_limit: Option<i64>,
+        pub output_token_limit: Option<i64>,
+        #[serde(default)]
+        pub supported_generation_methods: Vec<String>,
+        pub thinking: Option<bool>,
+    }
+}
+
+// Ollama native API structures
+mod ollama {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize)]
+    pub struct EmbedRequest<'a> {
+        pub model: &'a str,
+        pub input: &'a [String],
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct EmbedResponse {
+        pub embeddings: Vec<Vec<f32>>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct TagsResponse {
+        #[serde(default)]
+        pub models: Vec<Tag>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct Tag {
+        pub name: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct ShowRequest<'a> {
+        pub model: &'a str,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct ShowResponse {
+        // e.g. ["completion", "vision", "tools", "thinking"]
+        #[serde(default)]
+        pub capabilities: Vec<String>,
+        // Architecture details keyed like "llama.context_length"
+        #[serde(default)]
+        pub model_info: serde_json::Map<String, serde_json::Value>,
+    }
+}
+
+// Metadata from an OpenAI-compatible listing; only OpenRouter fills it in
+fn openai_model_info(model: openai::Model) -> ModelInfo {
+    let per_million = |price: Option<String>| price.and_then(|p| p.parse::<f64>().ok()).map(|p| p * 1_000_000.0);
+    let (input_price, output_price) = match model.pricing {
+        Some(pricing) => (per_million(pricing.prompt), per_million(pricing.completion)),
+        None => (None, None),
+    };
+    let (input_modalities, output_modalities) = match model.architecture {
+        Some(architecture) => (architecture.input_modalities, architecture.output_modalities),
+        None => (Vec::new(), Vec::new()),
+    };
+    let parameters = model.supported_parameters;
+    let supports = |names: &[&str]| parameters.as_ref().map(|p| p.iter().any(|n| names.contains(&n.as_str())));
+
+    ModelInfo {
+        metadata: ModelMetadata {
+            context_length: model.context_length,
+            max_output_tokens: model.top_provider.and_then(|p| p.max_completion_tokens),
+            input_price,
+            output_price,
+            supports_vision: (!input_modalities.is_empty()).then(|| input_modalities.iter().any(|m| m == "image")),
+            supports_tools: supports(&["tools"]),
+            supports_streaming: None,
+            supports_reasoning: supports(&["reasoning", "include_reasoning"]),
+            input_modalities,
+            output_modalities,
+        },
+        id: model.id,
+    }
+}
+
+// Metadata from Gemini's listing, or None for models that cannot chat
+fn gemini_model_info(model: gemini::Model) -> Option<ModelInfo> {
+    let methods = &model.supported_generation_methods;
+    if !methods.iter().any(|m| m == "generateContent") {
+        return None;
+    }
+    let id = model.name.strip_prefix("models/").unwrap_or(&model.name).to_string();
+    Some(ModelInfo {
+        id,
+        metadata: ModelMetadata {
+            context_length: model.input_token_limit,
+            max_output_tokens: model.output_token_limit,
+            supports_streaming: Some(methods.iter().any(|m| m == "streamGenerateContent")),
+            supports_reasoning: model.thinking,
+            ..ModelMetadata::default()
+        },
+    })
+}
+
+// Metadata from Ollama's model details; local models cost nothing
+fn ollama_model_info(name: String, details: ollama::ShowResponse) -> ModelInfo {
+    let has = |capability: &str| details.capabilities.iter().any(|c| c == capability);
+    let known_capabilities = !details.capabilities.is_empty();
+    let context_length = details
+        .model_info
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .and_then(|(_, value)| value.as_i64());
+    let mut input_modalities = vec!["text".to_string()];
+    if has("vision") {
+        input_modalities.push("image".to_string());
+    }
+
+    ModelInfo {
+        id: name,
+        metadata: ModelMetadata {
+            context_length,
+            input_price: Some(0.0),
+            output_price: Some(0.0),
+            supports_vision: known_capabilities.then(|| has("vision")),
+            supports_tools: known_capabilities.then(|| has("tools")),
+            supports_streaming: Some(true),
+            supports_reasoning: known_capabilities.then(|| has("thinking")),
+            input_modalities: if known_capabilities { input_modalities } else { Vec::new() },
+            ..ModelMetadata::default()
+        },
+    }
+}
+
+// Check that a provider returned one embedding per input
+fn check_embedding_count(embeddings: Vec<Vec<f32>>, expected: usize) -> Result<Vec<Vec<f32>>, AIError> {
+    if embeddings.len() != expected {
+        return Err(ProviderError::empty_response(
+            &format!("Expected {} embeddings, received {}", expected, embeddings.len())
+        ).into());
+    }
+    Ok(embeddings)
+}
+
+// Retry policy applied to every provider request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    // Number of retries after the first attempt (0 disables retrying)
+    pub max_retries: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub backoff_multiplier: f64,
+    // Fraction of the computed delay that is randomized (0.0 - 1.0)
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_delay_ms: 1000,
+            max_delay_ms: 30_000,
+            backoff_multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Exponential backoff delay for the given retry number (1-based), with jitter
+    fn backoff_delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1) as i32;
+        let base = (self.initial_delay_ms as f64) * self.backoff_multiplier.max(1.0).powi(exponent);
+        let base = base.min(self.max_delay_ms as f64);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            rand::thread_rng().gen_range((1.0 - jitter)..=(1.0 + jitter))
+        } else {
+            1.0
+        };
+
+        Duration::from_millis((base * factor).min(self.max_delay_ms as f64) as u64)
+    }
+}
+
+// Reported to the listener before each retry is attempted
+#[derive(Debug, Clone, Serialize)]
+pub struct RetryAttempt {
+    // What is being retried, e.g. the model name (never contains URLs or keys)
+    pub label: String,
+    pub attempt: u32,
+    pub max_retries: u32,
+    pub delay_ms: u64,
+    pub reason: String,
+}
+
+pub type RetryListener = Arc<dyn Fn(&RetryAttempt) + Send + Sync>;
+
+// Parse durations used by provider rate-limit reset headers, e.g. "20ms", "1.5s", "6m0s"
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds >= 0.0).then(|| Duration::from_secs_f64(seconds));
+    }
+
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut chars = value.chars().peekable();
+    let mut matched = false;
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let amount: f64 = number.parse().ok()?;
+        number.clear();
+        let unit_seconds = match c {
+            'h' => 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                0.001
+            }
+            'm' => 60.0,
+            's' => 1.0,
+            _ => return None,
+        };
+        total += amount * unit_seconds;
+        matched = true;
+    }
+
+    (matched && number.is_empty()).then(|| Duration::from_secs_f64(total))
+}
+
+// Delay requested by the server through `Retry-After` or provider rate-limit headers
+fn server_requested_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.trim().parse::<u64>().ok()) {
+        return Some(Duration::from_millis(ms));
+    }
+    // `Retry-After` is either delta-seconds or an HTTP-date
+    if let Some(value) = header("retry-after").map(str::trim) {
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(Duration::from_secs(seconds));
+        }
+        if let Ok(date) = httpdate::parse_http_date(value) {
+            // A date in the past means retry now
+            return Some(date.duration_since(SystemTime::now()).unwrap_or_default());
+        }
+    }
+
+    // OpenAI-compatible providers report when the exhausted limit resets
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .iter()
+        .filter_map(|name| header(name).and_then(parse_reset_duration))
+        .max()
+}
+
+// Default endpoint paths of OpenAI-compatible providers
+pub const DEFAULT_CHAT_PATH: &str = "/v1/chat/completions";
+pub const DEFAULT_MODELS_PATH: &str = "/v1/models";
+pub const DEFAULT_EMBEDDINGS_PATH: &str = "/v1/embeddings";
+
+// Endpoint paths of an OpenAI-compatible provider, relative to its base URL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenAIEndpoints {
+    pub chat_path: String,
+    pub models_path: String,
+}
+
+impl Default for OpenAIEndpoints {
+    fn default() -> Self {
+        OpenAIEndpoints {
+            chat_path: DEFAULT_CHAT_PATH.to_string(),
+            models_path: DEFAULT_MODELS_PATH.to_string(),
+        }
+    }
+}
+
+impl OpenAIEndpoints {
+    // Use the configured paths, falling back to the defaults for unset ones
+    pub fn new(chat_path: Option<&str>, models_path: Option<&str>) -> Self {
+        let configured = |path: Option<&str>, default: &str| {
+            path.map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(normalize_path)
+                .unwrap_or_else(|| default.to_string())
+        };
+
+        OpenAIEndpoints {
+            chat_path: configured(chat_path, DEFAULT_CHAT_PATH),
+            models_path: configured(models_path, DEFAULT_MODELS_PATH),
+        }
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    let path = path.trim().trim_end_matches('/');
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+fn is_version_segment(segment: &str) -> bool {
+    let mut chars = segment.chars();
+    chars.next() == Some('v') && chars.next().is_some_and(|c| c.is_ascii_digit())
+}
+
+// Join a base URL and an endpoint path. When the base URL already ends with a
+// version segment, the path's leading one is dropped, so `https://openrouter.ai/api/v1`
+// and `…/api/paas/v4` both keep their own version for `/v1/chat/completions`.
+pub fn join_endpoint(base_url: &str, path: &str) -> String {
+    let base = base_url.trim().trim_end_matches('/');
+    let path = normalize_path(path);
+
+    if base.rsplit('/').next().is_some_and(is_version_segment) {
+        if let Some((first_segment, rest)) = path[1..].split_once('/') {
+            if is_version_segment(first_segment) {
+                return format!("{}/{}", base, rest);
+            }
+        }
+    }
+
+    format!("{}{}", base, path)
+}
+
+// Endpoint suffixes users commonly paste together with the base URL
+const ENDPOINT_SUFFIXES: [&str; 4] = ["/chat/completions", "/completions", "/models", "/responses"];
+
+// Normalize a provider base URL before it is stored: trims whitespace, adds a
+// missing scheme, drops trailing slashes and pasted endpoint paths.
+pub fn normalize_base_url(url: &str) -> String {
+    let mut url = url.trim().to_string();
+    if !url.is_empty() && !url.contains("://") {
+        // Local servers are usually plain HTTP
+        let scheme = if url.starts_with("localhost") || url.starts_with("127.0.0.1") { "http" } else { "https" };
+        url = format!("{}://{}", scheme, url);
+    }
+
+    let mut url = url.trim_end_matches('/').to_string();
+    for suffix in ENDPOINT_SUFFIXES {
+        let start = url.len().saturating_sub(suffix.len());
+        if url.get(start..).is_some_and(|tail| tail.eq_ignore_ascii_case(suffix)) {
+            url.truncate(start);
+            break;
+        }
+    }
+
+    url.trim_end_matches('/').to_string()
+}
+
+// Result of probing one endpoint of a provider
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointProbe {
+    // "chat" or "models"
+    pub endpoint: String,
+    pub path: String,
+    pub url: String,
+    pub status: Option<u16>,
+    // The server answered and the route exists (any status except 404/405)
+    pub available: bool,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+// Per-provider HTTP connection settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectionSettings {
+    // http://, https://, socks5:// or socks5h:// proxy for all requests to the provider
+    pub proxy_url: Option<String>,
+    pub connect_timeout_secs: Option<u64>,
+    // Longest wait for data from the provider: for a response to start, then
+    // between chunks of its body. Long replies are not cut off while data keeps
+    // arriving.
+    pub read_timeout_secs: Option<u64>,
+    // Sent with every request, e.g. gateway routing or organization headers
+    pub extra_headers: BTreeMap<String, String>,
+    // PEM or DER file with an additional trusted root certificate
+    pub ca_cert_path: Option<String>,
+    // Disables TLS certificate verification; only meant for local testing
+    pub accept_invalid_certs: bool,
+}
+
+impl ConnectionSettings {
+    fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout_secs.map(Duration::from_secs)
+    }
+
+    // Build an HTTP client honouring these settings
+    pub fn build_client(&self) -> Result<Client, AIError> {
+        let mut builder = Client::builder();
+
+        if let Some(proxy_url) = self.proxy_url.as_deref().map(str::trim).filter(|u| !u.is_empty()) {
+            let proxy = Proxy::all(proxy_url).map_err(|e| AIError::InvalidConfig(format!("proxy URL: {}", e)))?;
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(secs) = self.connect_timeout_secs {
+            builder = builder.connect_timeout(Duration::from_secs(secs));
+        }
+
+        if !self.extra_headers.is_empty() {
+            let mut headers = HeaderMap::new();
+            for (name, value) in &self.extra_headers {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| AIError::InvalidConfig(format!("invalid header name '{}'", name)))?;
+                let value = HeaderValue::from_str(value)
+                    .map_err(|_| AIError::InvalidConfig(format!("invalid value for header '{}'", name)))?;
+                headers.insert(name, value);
+            }
+            builder = builder.default_headers(headers);
+        }
+
+        if let Some(path) = self.ca_cert_path.as_deref().filter(|p| !p.is_empty()) {
+            let bytes = fs::read(path)
+                .map_err(|e| AIError::InvalidConfig(format!("could not read CA certificate '{}': {}", path, e)))?;
+            let certificate = Certificate::from_pem(&bytes)
+                .or_else(|_| Certificate::from_der(&bytes))
+                .map_err(|e| AIError::InvalidConfig(format!("invalid CA certificate '{}': {}", path, e)))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        if self.accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        builder
+            .build()
+            .map_err(|e| AIError::InvalidConfig(e.to_string()))
+    }
 }
 
 // Main API client
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct AIClient {
     http_client: Client,
+    read_timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    retry_listener: Option<RetryListener>,
+    endpoints: OpenAIEndpoints,
+    // HTTP clients built for providers with custom connection settings, shared by all clones
+    provider_clients: Arc<Mutex<HashMap<String, (ConnectionSettings, Client)>>>,
+}
+
+impl fmt::Debug for AIClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AIClient")
+            .field("read_timeout", &self.read_timeout)
+            .field("endpoints", &self.endpoints)
+            .field("retry_policy", &self.retry_policy)
+            .field("retry_listener", &self.retry_listener.is_some())
+            .finish()
+    }
+}
+
+impl Default for AIClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AIClient {
     pub fn new() -> Self {
         AIClient {
             http_client: Client::new(),
+            read_timeout: None,
+            endpoints: OpenAIEndpoints::default(),
+            retry_policy: RetryPolicy::default(),
+            retry_listener: None,
+            provider_clients: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // Get a client that uses the given provider's connection settings. Clients are
+    // built once per provider and rebuilt only when its settings change.
+    pub fn for_provider(&self, provider_id: &str, settings: &ConnectionSettings) -> Result<AIClient, AIError> {
+        if *settings == ConnectionSettings::default() {
+            return Ok(self.clone());
+        }
+
+        let mut cache = self.provider_clients
+            .lock()
+            .map_err(|e| AIError::InvalidConfig(e.to_string()))?;
+
+        let http_client = match cache.get(provider_id) {
+            Some((cached_settings, client)) if cached_settings == settings => client.clone(),
+            _ => {
+                let client = settings.build_client()?;
+                cache.insert(provider_id.to_string(), (settings.clone(), client.clone()));
+                client
+            }
+        };
+
+        Ok(AIClient {
+            http_client,
+            read_timeout: settings.read_timeout(),
+            ..self.clone()
+        })
+    }
+
+    // Use the given endpoint paths for OpenAI-compatible requests
+    pub fn with_endpoints(mut self, endpoints: OpenAIEndpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    // Drop the cached client of a provider, e.g. after it was deleted
+    pub fn forget_provider(&self, provider_id: &str) {
+        if let Ok(mut cache) = self.provider_clients.lock() {
+            cache.remove(provider_id);
+        }
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    // Register a callback that is notified before every retry
+    pub fn set_retry_listener(&mut self, listener: RetryListener) {
+        self.retry_listener = Some(listener);
+    }
+
+    // Send a request, retrying on connection failures, read timeouts and retryable
+    // status codes. Retries only happen before any of the response body has been
+    // read, so a partially consumed (streamed) response is never replayed. The read
+    // timeout bounds the wait for the response to start; read its body with
+    // `read_json` or `next_chunk` to bound the waits between chunks too.
+    async fn send_with_retry<F>(&self, label: &str, build: F) -> Result<Response, AIError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let policy = &self.retry_policy;
+        let mut retry = 0;
+
+        loop {
+            // None when the read timeout elapsed before the response started
+            let sent = match self.read_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, build().send()).await.ok(),
+                None => Some(build().send().await),
+            };
+
+            let (reason, requested_delay) = match sent {
+                Some(Ok(response)) if response.status().is_success() => return Ok(response),
+                Some(Ok(response)) => {
+                    let status = response.status();
+                    let requested_delay = server_requested_delay(response.headers());
+                    let body = self.read_body(response).await
+                        .map(|body| String::from_utf8_lossy(&body).into_owned())
+                        .unwrap_or_default();
+                    let mut error = ProviderError::from_response(status, &body);
+                    error.retry_after_ms = requested_delay.map(|d| d.as_millis() as u64);
+
+                    // Give up instead of blocking the user for longer than the policy allows
+                    let too_long = requested_delay.is_some_and(|d| d > Duration::from_millis(policy.max_delay_ms));
+                    if !error.retryable || retry >= policy.max_retries || too_long {
+                        return Err(AIError::Provider(error));
+                    }
+                    (format!("HTTP {}", status.as_u16()), requested_delay)
+                }
+                Some(Err(e)) => {
+                    if !(e.is_connect() || e.is_timeout()) || retry >= policy.max_retries {
+                        return Err(AIError::from(e));
+                    }
+                    let reason = if e.is_timeout() { "timeout" } else { "connection failed" };
+                    (reason.to_string(), None)
+                }
+                None => {
+                    if retry >= policy.max_retries {
+                        return Err(ProviderError::new(ErrorKind::Timeout, None, "Timed out waiting for the response").into());
+                    }
+                    ("timeout".to_string(), None)
+                }
+            };
+
+            retry += 1;
+            let delay = requested_delay.unwrap_or_else(|| policy.backoff_delay(retry));
+
+            if let Some(listener) = &self.retry_listener {
+                listener(&RetryAttempt {
+                    label: label.to_string(),
+                    attempt: retry,
+                    max_retries: policy.max_retries,
+                    delay_ms: delay.as_millis() as u64,
+                    reason,
+                });
+            }
+
+            tokio::time::sleep(delay).await;
         }
     }
     
@@ -157,65 +1585,369 @@ impl AIClient {
             model: model.to_string(),
             messages,
             stream: false,
+            stream_options: None,
         };
         
         // Send the request
-        let response = self.http_client
-            .post(format!("{}/v1/chat/completions", api_url))
-            .header("Authorization", format!("Bearer {}", api_key))
-            .json(&request)
-            .send()
-            .await?;
+        let response = self.send_with_retry(model, || {
+            self.http_client
+                .post(join_endpoint(api_url, &self.endpoints.chat_path))
+                .header("Authorization", format!("Bearer {}", api_key))
+                .json(&request)
+        }).await?;
+        
+        // Parse the response
+        self.parse_chat_completion(response).await
+    }
+
+    // Send a chat request to an OpenAI-compatible API offering `tools`. Calls
+    // requested by the model are answered with the output of `on_tool_call`
+    // until it replies with text.
+    pub async fn openai_chat_with_tools(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDefinition],
+        on_tool_call: &mut (dyn FnMut(&ToolCall) -> String + Send)
+    ) -> Result<AIResponse, AIError> {
+        let mut wire_messages: Vec<serde_json::Value> = messages.iter()
+            .map(|m| serde_json::json!({ "role": m.role, "content": m.content }))
+            .collect();
+        
+        for round in 0..=MAX_TOOL_ROUNDS {
+            // Stop offering tools on the last round so the model has to answer
+            let offered = if round < MAX_TOOL_ROUNDS { tools } else { &[] };
+            let request = openai::ToolChatRequest {
+                model,
+                messages: &wire_messages,
+                tools: offered.iter().map(|function| openai::Tool { kind: "function", function }).collect(),
+            };
             
-        // Check for errors
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(AIError::APIError(error_text));
+            let response = self.send_with_retry(model, || {
+                self.http_client
+                    .post(join_endpoint(api_url, &self.endpoints.chat_path))
+                    .header("Authorization", format!("Bearer {}", api_key))
+                    .json(&request)
+            }).await?;
+            
+            let completion: openai::ToolChatResponse = self.read_json(response).await?;
+            let usage = completion.usage;
+            let message = completion.choices.into_iter().next()
+                .ok_or_else(|| ProviderError::empty_response("No response generated"))?
+                .message;
+            if message.tool_calls.is_empty() {
+                return Ok(AIResponse {
+                    content: message.content.unwrap_or_default(),
+                    reasoning: None,
+                    response_id: None,
+                    usage,
+                });
+            }
+            
+            wire_messages.push(serde_json::json!({
+                "role": "assistant",
+                "content": message.content,
+                "tool_calls": message.tool_calls,
+            }));
+            for call in message.tool_calls {
+                let output = on_tool_call(&ToolCall {
+                    id: call.id.clone(),
+                    name: call.function.name,
+                    arguments: call.function.arguments,
+                });
+                wire_messages.push(serde_json::json!({
+                    "role": "tool",
+                    "tool_call_id": call.id,
+                    "content": output,
+                }));
+            }
         }
         
-        // Parse the response
-        let completion: openai::ChatCompletionResponse = response.json().await?;
+        Err(ProviderError::empty_response("The model kept calling tools without replying").into())
+    }
+
+    // Send a chat request to an OpenAI-compatible API, streaming the output to `on_delta`
+    pub async fn openai_chat_stream(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        on_delta: &mut (dyn FnMut(StreamDelta) + Send)
+    ) -> Result<AIResponse, AIError> {
+        let request = openai::ChatCompletionRequest {
+            model: model.to_string(),
+            messages,
+            stream: true,
+            stream_options: Some(openai::StreamOptions { include_usage: true }),
+        };
+
+        let mut response = self.send_with_retry(model, || {
+            self.http_client
+                .post(join_endpoint(api_url, &self.endpoints.chat_path))
+                .header("Authorization", format!("Bearer {}", api_key))
+                .json(&request)
+        }).await?;
+
+        let mut state = ChatStreamState::default();
+        self.read_events(&mut response, &mut |event| state.handle(event, on_delta)).await?;
+        state.into_response()
+    }
+
+    async fn parse_chat_completion(&self, response: Response) -> Result<AIResponse, AIError> {
+        let completion: openai::ChatCompletionResponse = self.read_json(response).await?;
         
         if let Some(choice) = completion.choices.first() {
             Ok(AIResponse {
                 content: choice.message.content.clone(),
                 reasoning: None,
+                response_id: None,
+                usage: completion.usage,
             })
         } else {
-            Err(AIError::APIError("No response generated".to_string()))
+            Err(ProviderError::empty_response("No response generated").into())
         }
     }
     
-    // Fetch models from OpenAI
+    // Fetch models from OpenAI or a compatible provider, with the metadata
+    // OpenRouter includes in its listing
     pub async fn fetch_openai_models(
         &self,
         api_url: &str,
         api_key: &str
-    ) -> Result<Vec<String>, AIError> {
+    ) -> Result<Vec<ModelInfo>, AIError> {
         // Send the request
-        let response = self.http_client
-            .get(format!("{}/v1/models", api_url))
-            .header("Authorization", format!("Bearer {}", api_key))
-            .send()
-            .await?;
-            
-        // Check for errors
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(AIError::APIError(error_text));
-        }
+        let response = self.send_with_retry("models", || {
+            self.http_client
+                .get(join_endpoint(api_url, &self.endpoints.models_path))
+                .header("Authorization", format!("Bearer {}", api_key))
+        }).await?;
         
         // Parse the response
-        let model_list: openai::ModelListResponse = response.json().await?;
+        let model_list: openai::ModelListResponse = self.read_json(response).await?;
         
-        // Extract model IDs
-        let model_ids = model_list.data.into_iter()
-            .map(|model| model.id)
-            .collect();
-            
-        Ok(model_ids)
+        Ok(model_list.data.into_iter().map(openai_model_info).collect())
     }
     
+    // Probe the chat and models endpoints of an OpenAI-compatible provider, trying
+    // the configured paths as well as common alternatives. No tokens are spent:
+    // the chat endpoint receives an empty body, which existing routes reject with
+    // a validation error rather than 404.
+    pub async fn diagnose_openai_endpoints(&self, api_url: &str, api_key: &str) -> Vec<EndpointProbe> {
+        let candidates = [
+            ("models", self.endpoints.models_path.as_str()),
+            ("models", DEFAULT_MODELS_PATH),
+            ("models", "/models"),
+            ("chat", self.endpoints.chat_path.as_str()),
+            ("chat", DEFAULT_CHAT_PATH),
+            ("chat", "/chat/completions"),
+        ];
+
+        let mut probes: Vec<EndpointProbe> = Vec::new();
+        for (endpoint, path) in candidates {
+            let url = join_endpoint(api_url, path);
+            if probes.iter().any(|p| p.url == url) {
+                continue;
+            }
+
+            let request = if endpoint == "chat" {
+                self.http_client.post(&url).json(&serde_json::json!({}))
+            } else {
+                self.http_client.get(&url)
+            };
+            let request = request
+                .header("Authorization", format!("Bearer {}", api_key))
+                .timeout(self.read_timeout.unwrap_or(Duration::from_secs(15)));
+
+            let started = std::time::Instant::now();
+            let result = request.send().await;
+            let latency_ms = started.elapsed().as_millis() as u64;
+
+            let (status, available, error) = match result {
+                Ok(response) => {
+                    let status = response.status();
+                    let available = !matches!(status.as_u16(), 404 | 405);
+                    (Some(status.as_u16()), available, None)
+                }
+                Err(e) => (None, false, Some(AIError::from(e).to_string())),
+            };
+
+            probes.push(EndpointProbe {
+                endpoint: endpoint.to_string(),
+                path: normalize_path(path),
+                url,
+                status,
+                available,
+                latency_ms,
+                error,
+            });
+        }
+
+        probes
+    }
+
+    // Read a whole response body, honouring the read timeout between chunks
+    async fn read_body(&self, mut response: Response) -> Result<Vec<u8>, AIError> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.next_chunk(&mut response).await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    async fn read_json<T: serde::de::DeserializeOwned>(&self, response: Response) -> Result<T, AIError> {
+        Ok(serde_json::from_slice(&self.read_body(response).await?)?)
+    }
+
+    // Read the next chunk of a streamed body, honouring the read timeout between chunks
+    async fn next_chunk(&self, response: &mut Response) -> Result<Option<Vec<u8>>, AIError> {
+        let chunk = match self.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, response.chunk())
+                .await
+                .map_err(|_| AIError::Provider(ProviderError::new(ErrorKind::Timeout, None, "Timed out waiting for the stream")))??,
+            None => response.chunk().await?,
+        };
+        Ok(chunk.map(|bytes| bytes.to_vec()))
+    }
+
+    // Send a request through the OpenAI Responses API and stream the result.
+    // System messages become `instructions`; with `previous_response_id` only the
+    // messages after that response need to be passed.
+    pub async fn openai_responses(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        previous_response_id: Option<String>,
+        on_delta: &mut (dyn FnMut(StreamDelta) + Send)
+    ) -> Result<AIResponse, AIError> {
+        let mut instructions = Vec::new();
+        let mut input = Vec::new();
+        for message in messages {
+            if message.role == "system" {
+                instructions.push(message.content);
+            } else {
+                input.push(responses::InputMessage { role: message.role, content: message.content });
+            }
+        }
+
+        let request = responses::CreateRequest {
+            model: model.to_string(),
+            input,
+            instructions: (!instructions.is_empty()).then(|| instructions.join("\n\n")),
+            previous_response_id,
+            reasoning: supports_reasoning_summary(model).then(|| responses::ReasoningOptions {
+                summary: "auto".to_string(),
+            }),
+            stream: true,
+        };
+
+        let mut response = self.send_with_retry(model, || {
+            self.http_client
+                .post(join_endpoint(api_url, DEFAULT_RESPONSES_PATH))
+                .header("Authorization", format!("Bearer {}", api_key))
+                .json(&request)
+        }).await?;
+
+        let mut state = ResponsesStreamState::default();
+        self.read_events(&mut response, &mut |event| state.handle(event, on_delta)).await?;
+        state.into_response()
+    }
+
+    // Read a streamed body as server-sent events, passing each one to `handle`
+    async fn read_events(
+        &self,
+        response: &mut Response,
+        handle: &mut (dyn FnMut(&SseEvent) -> Result<(), AIError> + Send)
+    ) -> Result<(), AIError> {
+        let mut parser = SseParser::new();
+        let mut pending = Vec::new();
+
+        while let Some(chunk) = self.next_chunk(response).await? {
+            // Keep incomplete UTF-8 sequences until the rest of the bytes arrive
+            pending.extend_from_slice(&chunk);
+            let valid_up_to = match std::str::from_utf8(&pending) {
+                Ok(text) => text.len(),
+                Err(e) => e.valid_up_to(),
+            };
+            let text = String::from_utf8_lossy(&pending[..valid_up_to]).into_owned();
+            pending.drain(..valid_up_to);
+
+            for event in parser.push(&text) {
+                handle(&event)?;
+            }
+        }
+        if let Some(event) = parser.finish() {
+            handle(&event)?;
+        }
+        Ok(())
+    }
+
+    // Add the Azure authentication header selected in the settings
+    fn azure_auth(request: RequestBuilder, api_key: &str, settings: &AzureSettings) -> RequestBuilder {
+        match settings.auth_type {
+            AzureAuthType::ApiKey => request.header("api-key", api_key),
+            AzureAuthType::BearerToken => request.header("Authorization", format!("Bearer {}", api_key)),
+        }
+    }
+
+    // Send a chat request to an Azure OpenAI deployment
+    pub async fn azure_chat(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        settings: &AzureSettings,
+        model: &str,
+        messages: Vec<ChatMessage>
+    ) -> Result<AIResponse, AIError> {
+        let deployment = settings.deployment_for(model);
+        let request = openai::ChatCompletionRequest {
+            model: model.to_string(),
+            messages,
+            stream: false,
+            stream_options: None,
+        };
+
+        let response = self.send_with_retry(model, || {
+            let url = format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                api_url.trim_end_matches('/'),
+                deployment,
+                settings.api_version
+            );
+            Self::azure_auth(self.http_client.post(url), api_key, settings).json(&request)
+        }).await?;
+
+        self.parse_chat_completion(response).await
+    }
+
+    // List the deployments of an Azure OpenAI resource
+    pub async fn fetch_azure_deployments(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        settings: &AzureSettings
+    ) -> Result<Vec<AzureDeployment>, AIError> {
+        let response = self.send_with_retry("deployments", || {
+            // The deployments listing is only served by older data-plane API versions
+            let url = format!(
+                "{}/openai/deployments?api-version={}",
+                api_url.trim_end_matches('/'),
+                AZURE_DEPLOYMENTS_API_VERSION
+            );
+            Self::azure_auth(self.http_client.get(url), api_key, settings)
+        }).await?;
+
+        let deployments: openai::DeploymentListResponse = self.read_json(response).await?;
+
+        Ok(deployments.data.into_iter()
+            .map(|d| AzureDeployment { id: d.id, model: d.model })
+            .collect())
+    }
+
     // Send a chat request to Gemini
     pub async fn gemini_chat(
         &self,
@@ -245,48 +1977,579 @@ impl AIClient {
         };
 
         // Send the request
-        let response = self.http_client
-            .post(format!("{}/v1beta/models/{}/generateContent?key={}", api_url, model, api_key))
-            .json(&request)
-            .send()
-            .await?;
-
-        // Check for errors
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(AIError::APIError(error_text));
-        }
+        let response = self.send_with_retry(model, || {
+            self.http_client
+                .post(format!("{}/v1beta/models/{}/generateContent?key={}", api_url, model, api_key))
+                .json(&request)
+        }).await?;
 
         // Parse the response
-        let gemini_response: gemini::ChatResponse = response.json().await?;
+        let gemini_response: gemini::ChatResponse = self.read_json(response).await?;
 
         if let Some(candidate) = gemini_response.candidates.first() {
             if let Some(part) = candidate.content.parts.first() {
                 Ok(AIResponse {
                     content: part.text.clone(),
                     reasoning: None,
+                    response_id: None,
+                    usage: gemini_response.usage_metadata.as_ref().map(TokenUsage::from),
                 })
             } else {
-                Err(AIError::APIError("No content parts in response".to_string()))
+                Err(ProviderError::empty_response("No content parts in response").into())
             }
         } else {
-            Err(AIError::APIError("No candidates in response".to_string()))
+            Err(ProviderError::empty_response("No candidates in response").into())
         }
     }
 
-    // Fetch models from Gemini (placeholder for future implementation)
-    #[allow(dead_code)]
+    // Fetch embeddings for `inputs` from an OpenAI-compatible API
+    pub async fn openai_embeddings(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        model: &str,
+        inputs: &[String]
+    ) -> Result<Vec<Vec<f32>>, AIError> {
+        let request = openai::EmbeddingRequest { model, input: inputs };
+        
+        let response = self.send_with_retry(model, || {
+            self.http_client
+                .post(join_endpoint(api_url, DEFAULT_EMBEDDINGS_PATH))
+                .header("Authorization", format!("Bearer {}", api_key))
+                .json(&request)
+        }).await?;
+        
+        let mut data = self.read_json::<openai::EmbeddingResponse>(response).await?.data;
+        data.sort_by_key(|e| e.index);
+        check_embedding_count(data.into_iter().map(|e| e.embedding).collect(), inputs.len())
+    }
+
+    // Fetch embeddings for `inputs` from Gemini
+    pub async fn gemini_embeddings(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        model: &str,
+        inputs: &[String]
+    ) -> Result<Vec<Vec<f32>>, AIError> {
+        let request = gemini::BatchEmbedRequest {
+            requests: inputs.iter()
+                .map(|text| gemini::EmbedRequest {
+                    model: format!("models/{}", model),
+                    content: gemini::EmbedContent { parts: vec![gemini::Part { text: text.clone() }] },
+                })
+                .collect(),
+        };
+        
+        let response = self.send_with_retry(model, || {
+            self.http_client
+                .post(format!("{}/v1beta/models/{}:batchEmbedContents?key={}", api_url, model, api_key))
+                .json(&request)
+        }).await?;
+        
+        let embeddings = self.read_json::<gemini::BatchEmbedResponse>(response).await?.embeddings;
+        check_embedding_count(embeddings.into_iter().map(|e| e.values).collect(), inputs.len())
+    }
+
+    // Fetch embeddings for `inputs` from Ollama's native API
+    pub async fn ollama_embeddings(
+        &self,
+        api_url: &str,
+        model: &str,
+        inputs: &[String]
+    ) -> Result<Vec<Vec<f32>>, AIError> {
+        // The native API lives next to the OpenAI-compatible /v1 routes
+        let base = api_url.trim().trim_end_matches('/');
+        let base = base.strip_suffix("/v1").unwrap_or(base);
+        let request = ollama::EmbedRequest { model, input: inputs };
+        
+        let response = self.send_with_retry(model, || {
+            self.http_client
+                .post(format!("{}/api/embed", base))
+                .json(&request)
+        }).await?;
+        
+        let embeddings = self.read_json::<ollama::EmbedResponse>(response).await?.embeddings;
+        check_embedding_count(embeddings, inputs.len())
+    }
+
+    // Fetch the models Gemini can chat with, following result pages
     pub async fn fetch_gemini_models(
         &self,
-        _api_url: &str,
-        _api_key: &str
-    ) -> Result<Vec<String>, AIError> {
-        // TODO: Implement actual Gemini models API call
-        // For now, return common Gemini models
-        Ok(vec![
-            "gemini-1.5-pro".to_string(),
-            "gemini-1.5-flash".to_string(),
-            "gemini-1.0-pro".to_string(),
-        ])
-    }
-} 
\ No newline at end of file
+        api_url: &str,
+        api_key: &str
+    ) -> Result<Vec<ModelInfo>, AIError> {
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut url = format!("{}/v1beta/models?pageSize=1000&key={}", api_url, api_key);
+            if let Some(token) = &page_token {
+                url.push_str(&format!("&pageToken={}", token));
+            }
+            let response = self.send_with_retry("models", || self.http_client.get(&url)).await?;
+            let page: gemini::ModelListResponse = self.read_json(response).await?;
+            models.extend(page.models.into_iter().filter_map(gemini_model_info));
+
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => return Ok(models),
+            }
+        }
+    }
+
+    // Fetch the models pulled into Ollama, with the details of each
+    pub async fn fetch_ollama_models(&self, api_url: &str) -> Result<Vec<ModelInfo>, AIError> {
+        // The native API lives next to the OpenAI-compatible /v1 routes
+        let base = api_url.trim().trim_end_matches('/');
+        let base = base.strip_suffix("/v1").unwrap_or(base);
+
+        let response = self.send_with_retry("models", || self.http_client.get(format!("{}/api/tags", base))).await?;
+        let tags: ollama::TagsResponse = self.read_json(response).await?;
+
+        let show_url = format!("{}/api/show", base);
+        let model_info = |name: String| {
+            let show_url = &show_url;
+            async move {
+                let request = ollama::ShowRequest { model: &name };
+                // Details are optional; older servers may not have them
+                let details = match self.send_with_retry("models", || self.http_client.post(show_url).json(&request)).await {
+                    Ok(response) => self.read_json::<ollama::ShowResponse>(response).await.ok(),
+                    Err(_) => None,
+                };
+                match details {
+                    Some(details) => ollama_model_info(name, details),
+                    None => ModelInfo::named(name),
+                }
+            }
+        };
+        let models = stream::iter(tags.models)
+            .map(|tag| model_info(tag.name))
+            .buffered(OLLAMA_SHOW_CONCURRENCY)
+            .collect()
+            .await;
+        Ok(models)
+    }
+
+    // Check that a model answers, with the cheapest chat request it accepts: a
+    // one-word prompt and a one-token reply. The reply itself is discarded.
+    pub async fn ping_openai_model(&self, api_url: &str, api_key: &str, model: &str) -> Result<(), AIError> {
+        let body = one_token_chat(model);
+        self.send_with_retry(model, || {
+            self.http_client
+                .post(join_endpoint(api_url, &self.endpoints.chat_path))
+                .header("Authorization", format!("Bearer {}", api_key))
+                .json(&body)
+        }).await?;
+        Ok(())
+    }
+
+    pub async fn ping_azure_model(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        settings: &AzureSettings,
+        model: &str
+    ) -> Result<(), AIError> {
+        let body = one_token_chat(model);
+        self.send_with_retry(model, || {
+            let url = format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                api_url.trim_end_matches('/'),
+                settings.deployment_for(model),
+                settings.api_version
+            );
+            Self::azure_auth(self.http_client.post(url), api_key, settings).json(&body)
+        }).await?;
+        Ok(())
+    }
+
+    pub async fn ping_gemini_model(&self, api_url: &str, api_key: &str, model: &str) -> Result<(), AIError> {
+        let body = serde_json::json!({
+            "contents": [{ "role": "user", "parts": [{ "text": "ping" }] }],
+            "generationConfig": { "maxOutputTokens": 1 },
+        });
+        self.send_with_retry(model, || {
+            self.http_client
+                .post(format!("{}/v1beta/models/{}:generateContent?key={}", api_url, model, api_key))
+                .json(&body)
+        }).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_model_listing_metadata() {
+        let body = r#"{"data": [{"id": "openai/gpt-4o", "context_length": 128000,
+            "pricing": {"prompt": "0.0000025", "completion": "0.00001"},
+            "architecture": {"input_modalities": ["text", "image"], "output_modalities": ["text"]},
+            "top_provider": {"max_completion_tokens": 16384},
+            "supported_parameters": ["max_tokens", "tools"]}, {"id": "gpt-4o"}]}"#;
+        let list: openai::ModelListResponse = serde_json::from_str(body).unwrap();
+        let mut models = list.data.into_iter().map(openai_model_info);
+        let routed = models.next().unwrap().metadata;
+        assert_eq!(routed.context_length, Some(128000));
+        assert_eq!(routed.max_output_tokens, Some(16384));
+        assert!((routed.input_price.unwrap() - 2.5).abs() < 1e-9);
+        assert_eq!((routed.supports_vision, routed.supports_tools, routed.supports_reasoning), (Some(true), Some(true), Some(false)));
+        // Plain OpenAI listings carry no metadata
+        assert!(models.next().unwrap().metadata.is_empty());
+
+        let body = r#"{"models": [{"name": "models/gemini-2.5-flash", "inputTokenLimit": 1048576, "outputTokenLimit": 65536,
+            "supportedGenerationMethods": ["generateContent", "streamGenerateContent"], "thinking": true},
+            {"name": "models/text-embedding-004", "supportedGenerationMethods": ["embedContent"]}]}"#;
+        let list: gemini::ModelListResponse = serde_json::from_str(body).unwrap();
+        let models: Vec<ModelInfo> = list.models.into_iter().filter_map(gemini_model_info).collect();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].id, "gemini-2.5-flash");
+        assert_eq!(models[0].metadata.max_output_tokens, Some(65536));
+        assert_eq!(models[0].metadata.supports_reasoning, Some(true));
+
+        let body = r#"{"capabilities": ["completion", "vision"], "model_info": {"gemma3.context_length": 131072}}"#;
+        let info = ollama_model_info("gemma3:4b".to_string(), serde_json::from_str(body).unwrap());
+        assert_eq!(info.metadata.context_length, Some(131072));
+        assert_eq!((info.metadata.supports_vision, info.metadata.supports_tools), (Some(true), Some(false)));
+        assert_eq!(info.metadata.input_modalities, vec!["text", "image"]);
+    }
+
+    #[test]
+    fn test_parse_reset_duration() {
+        assert_eq!(parse_reset_duration("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_reset_duration("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_reset_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_reset_duration("1h2m"), Some(Duration::from_secs(3720)));
+        assert_eq!(parse_reset_duration("soon"), None);
+        assert_eq!(parse_reset_duration("12"), Some(Duration::from_secs(12)));
+    }
+
+    #[test]
+    fn test_server_requested_delay() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(server_requested_delay(&headers), None);
+
+        headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static("1s"));
+        headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("6s"));
+        assert_eq!(server_requested_delay(&headers), Some(Duration::from_secs(6)));
+
+        // Retry-After takes precedence over rate-limit reset headers
+        headers.insert("retry-after", HeaderValue::from_static("3"));
+        assert_eq!(server_requested_delay(&headers), Some(Duration::from_secs(3)));
+
+        let later = SystemTime::now() + Duration::from_secs(120);
+        headers.insert("retry-after", HeaderValue::from_str(&httpdate::fmt_http_date(later)).unwrap());
+        let delay = server_requested_delay(&headers).unwrap();
+        assert!(delay > Duration::from_secs(110) && delay <= Duration::from_secs(120));
+
+        headers.insert("retry-after", HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(server_requested_delay(&headers), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_parse_openai_error_envelope() {
+        let body = r#"{"error": {"message": "Incorrect API key provided: sk-abc***", "type": "invalid_request_error", "param": null, "code": "invalid_api_key"}}"#;
+        let error = ProviderError::from_response(StatusCode::UNAUTHORIZED, body);
+        assert_eq!(error.kind, ErrorKind::InvalidApiKey);
+        assert_eq!(error.status, Some(401));
+        assert_eq!(error.provider_code.as_deref(), Some("invalid_api_key"));
+        assert!(!error.retryable);
+
+        let body = r#"{"error": {"message": "You exceeded your current quota", "type": "insufficient_quota", "code": "insufficient_quota"}}"#;
+        let error = ProviderError::from_response(StatusCode::TOO_MANY_REQUESTS, body);
+        assert_eq!(error.kind, ErrorKind::QuotaExceeded);
+        assert!(!error.retryable);
+
+        let body = r#"{"error": {"message": "Rate limit reached for requests", "type": "requests", "code": "rate_limit_exceeded"}}"#;
+        let error = ProviderError::from_response(StatusCode::TOO_MANY_REQUESTS, body);
+        assert_eq!(error.kind, ErrorKind::RateLimited);
+        assert!(error.retryable);
+
+        let body = r#"{"error": {"message": "This model's maximum context length is 8192 tokens.", "type": "invalid_request_error", "code": "context_length_exceeded"}}"#;
+        let error = ProviderError::from_response(StatusCode::BAD_REQUEST, body);
+        assert_eq!(error.kind, ErrorKind::ContextTooLong);
+    }
+
+    #[test]
+    fn test_parse_gemini_error_envelope() {
+        let body = r#"{"error": {"code": 400, "message": "API key not valid. Please pass a valid API key.", "status": "INVALID_ARGUMENT"}}"#;
+        let error = ProviderError::from_response(StatusCode::BAD_REQUEST, body);
+        assert_eq!(error.kind, ErrorKind::InvalidApiKey);
+        assert_eq!(error.provider_code.as_deref(), Some("INVALID_ARGUMENT"));
+
+        let body = r#"{"error": {"code": 404, "message": "models/gemini-9 is not found for API version v1beta", "status": "NOT_FOUND"}}"#;
+        let error = ProviderError::from_response(StatusCode::NOT_FOUND, body);
+        assert_eq!(error.kind, ErrorKind::ModelNotFound);
+    }
+
+    #[test]
+    fn test_unparseable_body_is_not_leaked() {
+        let body = "<html>upstream echoed: my secret prompt</html>";
+        let error = ProviderError::from_response(StatusCode::BAD_GATEWAY, body);
+        assert_eq!(error.kind, ErrorKind::ServerError);
+        assert_eq!(error.message, "Bad Gateway");
+        assert!(error.retryable);
+
+        let long = format!(r#"{{"error": {{"message": "{}"}}}}"#, "x".repeat(1000));
+        let error = ProviderError::from_response(StatusCode::BAD_REQUEST, &long);
+        assert!(error.message.chars().count() <= MAX_ERROR_MESSAGE_LEN + 1);
+    }
+
+    #[test]
+    fn test_connection_settings_validation() {
+        assert!(ConnectionSettings::default().build_client().is_ok());
+
+        let mut settings = ConnectionSettings {
+            proxy_url: Some("socks5h://127.0.0.1:1080".to_string()),
+            connect_timeout_secs: Some(5),
+            read_timeout_secs: Some(60),
+            ..Default::default()
+        };
+        settings.extra_headers.insert("X-Gateway-Route".to_string(), "team-a".to_string());
+        assert!(settings.build_client().is_ok());
+
+        settings.extra_headers.insert("bad header".to_string(), "x".to_string());
+        assert!(matches!(settings.build_client(), Err(AIError::InvalidConfig(_))));
+
+        let settings = ConnectionSettings {
+            ca_cert_path: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(settings.build_client(), Err(AIError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_provider_clients_are_cached_per_settings() {
+        let client = AIClient::new();
+        let settings = ConnectionSettings {
+            read_timeout_secs: Some(30),
+            ..Default::default()
+        };
+
+        let provider_client = client.for_provider("p1", &settings).unwrap();
+        assert_eq!(provider_client.read_timeout, Some(Duration::from_secs(30)));
+        assert_eq!(client.provider_clients.lock().unwrap().len(), 1);
+
+        // Default settings use the shared client and are not cached
+        client.for_provider("p2", &ConnectionSettings::default()).unwrap();
+        assert_eq!(client.provider_clients.lock().unwrap().len(), 1);
+
+        client.forget_provider("p1");
+        assert!(client.provider_clients.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_bounds_wait_for_response() {
+        // Accepted by the kernel but never answered
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let settings = ConnectionSettings {
+            read_timeout_secs: Some(1),
+            ..Default::default()
+        };
+        let mut client = AIClient::new().for_provider("p1", &settings).unwrap();
+        client.set_retry_policy(RetryPolicy { max_retries: 0, ..RetryPolicy::default() });
+
+        let error = client.fetch_ollama_models(&url).await.unwrap_err();
+        assert_eq!(error.to_provider_error().kind, ErrorKind::Timeout);
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn test_ollama_models_keep_listing_order_with_details() {
+        let url = crate::test_support::serve(3, |request| {
+            if request.starts_with("GET /api/tags") {
+                ("200 OK", r#"{"models":[{"name":"llama3.2"},{"name":"qwen3"}]}"#.to_string())
+            } else {
+                ("200 OK", r#"{"capabilities":["completion","vision"]}"#.to_string())
+            }
+        });
+
+        let models = AIClient::new().fetch_ollama_models(&format!("{}/v1", url)).await.unwrap();
+        let names: Vec<_> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(names, vec!["llama3.2", "qwen3"]);
+        assert!(models.iter().all(|m| m.metadata.supports_vision == Some(true)));
+    }
+
+    #[test]
+    fn test_azure_deployment_mapping() {
+        let mut settings = AzureSettings::default();
+        settings.deployments.insert("gpt-4o".to_string(), "prod-gpt4o".to_string());
+
+        assert_eq!(settings.deployment_for("gpt-4o"), "prod-gpt4o");
+        assert_eq!(settings.deployment_for("my-deployment"), "my-deployment");
+
+        let parsed: AzureSettings = serde_json::from_str(r#"{"auth_type": "bearer_token"}"#).unwrap();
+        assert_eq!(parsed.auth_type, AzureAuthType::BearerToken);
+        assert_eq!(parsed.api_version, AZURE_DEFAULT_API_VERSION);
+    }
+
+    #[test]
+    fn test_normalize_base_url() {
+        assert_eq!(normalize_base_url(" https://api.openai.com/ "), "https://api.openai.com");
+        assert_eq!(normalize_base_url("https://openrouter.ai/api/v1/chat/completions"), "https://openrouter.ai/api/v1");
+        assert_eq!(normalize_base_url("https://gateway.corp/v1/models/"), "https://gateway.corp/v1");
+        assert_eq!(normalize_base_url("localhost:1234/v1"), "http://localhost:1234/v1");
+        assert_eq!(normalize_base_url("api.deepseek.com"), "https://api.deepseek.com");
+    }
+
+    #[test]
+    fn test_join_endpoint_avoids_doubled_version() {
+        assert_eq!(join_endpoint("https://api.openai.com", DEFAULT_CHAT_PATH), "https://api.openai.com/v1/chat/completions");
+        assert_eq!(join_endpoint("https://openrouter.ai/api/v1", DEFAULT_CHAT_PATH), "https://openrouter.ai/api/v1/chat/completions");
+        assert_eq!(join_endpoint("http://localhost:1234/v1/", DEFAULT_MODELS_PATH), "http://localhost:1234/v1/models");
+        assert_eq!(join_endpoint("https://open.bigmodel.cn/api/paas/v4", "/chat/completions"), "https://open.bigmodel.cn/api/paas/v4/chat/completions");
+        assert_eq!(join_endpoint("https://open.bigmodel.cn/api/paas/v4", DEFAULT_CHAT_PATH), "https://open.bigmodel.cn/api/paas/v4/chat/completions");
+        assert_eq!(join_endpoint("https://gateway.corp/llm", "v1/models"), "https://gateway.corp/llm/v1/models");
+    }
+
+    #[test]
+    fn test_openai_endpoints_fall_back_to_defaults() {
+        let endpoints = OpenAIEndpoints::new(Some(" chat/completions/ "), Some(""));
+        assert_eq!(endpoints.chat_path, "/chat/completions");
+        assert_eq!(endpoints.models_path, DEFAULT_MODELS_PATH);
+    }
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::new();
+        assert!(parser.push("event: response.output_text.delta\ndata: {\"del").is_empty());
+
+        let events = parser.push("ta\": \"Hi\"}\n\ndata: [DONE]\r\n\r\n");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event.as_deref(), Some("response.output_text.delta"));
+        assert_eq!(events[0].data, r#"{"delta": "Hi"}"#);
+        assert_eq!(events[1], SseEvent { event: None, data: "[DONE]".to_string() });
+
+        parser.push("data: tail");
+        assert_eq!(parser.finish().map(|e| e.data), Some("tail".to_string()));
+    }
+
+    #[test]
+    fn test_responses_stream_collects_text_reasoning_and_id() {
+        let stream = concat!(
+            "event: response.created\ndata: {\"type\":\"response.created\",\"response\":{\"id\":\"resp_1\"}}\n\n",
+            "event: response.reasoning_summary_text.delta\ndata: {\"delta\":\"Thinking\"}\n\n",
+            "event: response.reasoning_summary_part.done\ndata: {}\n\n",
+            "event: response.output_text.delta\ndata: {\"delta\":\"Hello\"}\n\n",
+            "event: response.output_text.delta\ndata: {\"delta\":\" world\"}\n\n",
+            "event: response.completed\ndata: {\"response\":{\"id\":\"resp_1\",\"output\":[],\"usage\":{\"input_tokens\":7,\"output_tokens\":2,\"total_tokens\":9}}}\n\n",
+        );
+
+        let mut parser = SseParser::new();
+        let mut state = ResponsesStreamState::default();
+        let mut deltas = Vec::new();
+        for event in parser.push(stream) {
+            state.handle(&event, &mut |d| deltas.push(d)).unwrap();
+        }
+
+        let response = state.into_response().unwrap();
+        assert_eq!(response.content, "Hello world");
+        assert_eq!(response.reasoning.as_deref(), Some("Thinking"));
+        assert_eq!(response.response_id.as_deref(), Some("resp_1"));
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(response.usage.map(|u| u.completion_tokens), Some(2));
+    }
+
+    #[test]
+    fn test_chat_stream_collects_content_and_reasoning() {
+        let stream = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"reasoning_content\":\"Thinking\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":3,\"total_tokens\":15}}\n\n",
+            "data: [DONE]\n\n",
+        );
+
+        let mut parser = SseParser::new();
+        let mut state = ChatStreamState::default();
+        let mut deltas = Vec::new();
+        for event in parser.push(stream) {
+            state.handle(&event, &mut |d| deltas.push(d)).unwrap();
+        }
+
+        let response = state.into_response().unwrap();
+        assert_eq!(response.content, "Hello");
+        assert_eq!(response.reasoning.as_deref(), Some("Thinking"));
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(response.usage, Some(TokenUsage { prompt_tokens: 12, completion_tokens: 3, total_tokens: 15 }));
+
+        let mut state = ChatStreamState::default();
+        let error = SseEvent { event: None, data: r#"{"error":{"message":"Rate limit reached","type":"requests","code":"rate_limit_exceeded"}}"#.to_string() };
+        match state.handle(&error, &mut |_| {}) {
+            Err(AIError::Provider(e)) => assert_eq!(e.kind, ErrorKind::RateLimited),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_responses_stream_error_event() {
+        let event = SseEvent {
+            event: Some("error".to_string()),
+            data: r#"{"type":"error","code":"context_length_exceeded","message":"Input too long"}"#.to_string(),
+        };
+        let mut state = ResponsesStreamState::default();
+        match state.handle(&event, &mut |_| {}) {
+            Err(AIError::Provider(e)) => {
+                assert_eq!(e.kind, ErrorKind::ContextTooLong);
+                assert_eq!(e.status, None);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_backoff_delay_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_delay_ms: 100,
+            max_delay_ms: 1000,
+            backoff_multiplier: 2.0,
+            jitter: 0.0,
+        };
+        assert_eq!(policy.backoff_delay(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff_delay(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff_delay(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff_delay(6), Duration::from_millis(1000));
+
+        let jittered = RetryPolicy { jitter: 0.5, ..policy };
+        for _ in 0..20 {
+            let delay = jittered.backoff_delay(2).as_millis();
+            assert!((100..=300).contains(&delay));
+        }
+    }
+
+    #[test]
+    fn test_tool_call_response_round_trips() {
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":null,
+            "tool_calls":[{"id":"call_1","type":"function","function":{"name":"save_memory","arguments":"{\"content\":\"Uses Postgres\"}"}}]}}]}"#;
+        let response: openai::ToolChatResponse = serde_json::from_str(body).unwrap();
+        let message = &response.choices[0].message;
+        assert!(message.content.is_none());
+        assert_eq!(message.tool_calls[0].function.name, "save_memory");
+
+        // Calls are sent back unchanged in the follow-up request
+        let echoed = serde_json::to_value(&message.tool_calls).unwrap();
+        assert_eq!(echoed[0]["type"], "function");
+        assert_eq!(echoed[0]["function"]["arguments"], "{\"content\":\"Uses Postgres\"}");
+
+        let tool = ToolDefinition {
+            name: "save_memory".to_string(),
+            description: "Save a memory".to_string(),
+            parameters: serde_json::json!({ "type": "object" }),
+        };
+        let request = openai::ToolChatRequest {
+            model: "gpt-4o",
+            messages: &[],
+            tools: vec![openai::Tool { kind: "function", function: &tool }],
+        };
+        let request = serde_json::to_value(&request).unwrap();
+        assert_eq!(request["tools"][0]["function"]["name"], "save_memory");
+        let request = openai::ToolChatRequest { model: "gpt-4o", messages: &[], tools: Vec::new() };
+        assert!(serde_json::to_value(&request).unwrap().get("tools").is_none());
+    }
+}
+
@@ -0,0 +1,486 @@
+use std::net::{Ipv4Addr, SocketAddr, TcpListener as StdTcpListener};
+use std::time::Instant;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State as RouterState;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager};
+use tokio::sync::oneshot;
+
+use crate::error::CommandError;
+use crate::{ai, chat, db, AppState};
+pub use crate::api_server_settings::{
+    access_token, load_settings, regenerate_token, ENABLED_SETTING, PORT_SETTING,
+};
+
+// A running server; dropping the handle without calling `stop` leaves it running
+pub struct ServerHandle {
+    addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+}
+
+impl ServerHandle {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stop(self) {
+        let _ = self.shutdown.send(());
+    }
+}
+
+// Start the server on localhost. The port is bound before returning so that
+// conflicts are reported to the caller instead of failing in the background.
+pub fn start(app_handle: AppHandle, port: u16) -> std::io::Result<ServerHandle> {
+    let listener = StdTcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let router = Router::new()
+        .route("/v1/models", get(list_models))
+        .route("/v1/chat/completions", post(chat_completions))
+        .with_state(app_handle);
+
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+    tauri::async_runtime::spawn(async move {
+        let result = match tokio::net::TcpListener::from_std(listener) {
+            Ok(listener) => {
+                axum::serve(listener, router)
+                    .with_graceful_shutdown(async {
+                        let _ = shutdown_rx.await;
+                    })
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!("Local API server stopped: {}", e);
+        }
+    });
+
+    Ok(ServerHandle { addr, shutdown })
+}
+
+// Error body in the OpenAI format, so existing client libraries can surface it
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    error_type: &'static str,
+    code: Option<String>,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, error_type: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            error_type,
+            code: None,
+            message: message.into(),
+        }
+    }
+
+    fn unauthorized() -> Self {
+        ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "authentication_error",
+            "Invalid or missing access token. Use the token shown in AIChat Pro's settings.",
+        )
+    }
+}
+
+impl From<CommandError> for ApiError {
+    fn from(error: CommandError) -> Self {
+        match error {
+            CommandError::Provider(e) => {
+                // Upstream auth failures are a server-side configuration problem,
+                // not a bad local token, so they are reported as a gateway error
+                let status = e
+                    .status
+                    .filter(|s| (400..500).contains(s) && *s != 401 && *s != 403)
+                    .and_then(|s| StatusCode::from_u16(s).ok())
+                    .unwrap_or(StatusCode::BAD_GATEWAY);
+                let code = serde_json::to_value(e.kind)
+                    .ok()
+                    .and_then(|v| v.as_str().map(str::to_string));
+                ApiError {
+                    status,
+                    error_type: "upstream_error",
+                    code,
+                    message: e.message,
+                }
+            }
+            CommandError::NotFound { message } => {
+                ApiError::new(StatusCode::NOT_FOUND, "invalid_request_error", message)
+            }
+            CommandError::InvalidInput { message } => {
+                ApiError::new(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+            }
+            CommandError::Locked { message } => {
+                ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "server_error", message)
+            }
+            other => ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "server_error", other.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "error": {
+                "message": self.message,
+                "type": self.error_type,
+                "code": self.code,
+            }
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<RequestMessage>,
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct RequestMessage {
+    role: String,
+    content: MessageContent,
+}
+
+// Message content: a string, or a list of parts as sent by many OpenAI clients
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Deserialize)]
+struct ContentPart {
+    #[serde(rename = "type")]
+    part_type: String,
+    #[serde(default)]
+    text: String,
+}
+
+impl RequestMessage {
+    // Join the text parts of the content; other parts (images, audio) are rejected
+    fn into_chat_message(self) -> Result<ai::ChatMessage, ApiError> {
+        let content = match self.content {
+            MessageContent::Text(text) => text,
+            MessageContent::Parts(parts) => {
+                let mut texts = Vec::new();
+                for part in parts {
+                    if part.part_type != "text" {
+                        return Err(ApiError::new(
+                            StatusCode::BAD_REQUEST,
+                            "invalid_request_error",
+                            format!("Content parts of type '{}' are not supported; only text is", part.part_type),
+                        ));
+                    }
+                    texts.push(part.text);
+                }
+                texts.join("\n")
+            }
+        };
+        Ok(ai::ChatMessage { role: self.role, content })
+    }
+}
+
+// Provider and model a request was routed to, recorded in the request log
+#[derive(Default)]
+struct Route {
+    model: Option<String>,
+    model_id: Option<String>,
+    provider_id: Option<String>,
+}
+
+async fn list_models(RouterState(app_handle): RouterState<AppHandle>, headers: HeaderMap) -> Response {
+    let started = Instant::now();
+    let app_state = app_handle.state::<AppState>();
+
+    let result = match authorize(&app_state, &headers).await {
+        Ok(()) => model_list(&app_state).await.map_err(ApiError::from),
+        Err(e) => Err(e),
+    };
+    finish(&app_state, "GET", "/v1/models", &Route::default(), started, result).await
+}
+
+async fn chat_completions(
+    RouterState(app_handle): RouterState<AppHandle>,
+    headers: HeaderMap,
+    body: Result<Json<ChatCompletionRequest>, JsonRejection>,
+) -> Response {
+    let started = Instant::now();
+    let app_state = app_handle.state::<AppState>();
+    let mut route = Route::default();
+
+    let result = complete(&app_state, &headers, body, &mut route).await;
+    finish(&app_state, "POST", "/v1/chat/completions", &route, started, result).await
+}
+
+async fn complete(
+    app_state: &AppState,
+    headers: &HeaderMap,
+    body: Result<Json<ChatCompletionRequest>, JsonRejection>,
+    route: &mut Route,
+) -> Result<Response, ApiError> {
+    authorize(app_state, headers).await?;
+    let Json(request) = body.map_err(|e| ApiError::new(e.status(), "invalid_request_error", e.body_text()))?;
+    route.model = Some(request.model.clone());
+    let messages = request.messages
+        .into_iter()
+        .map(RequestMessage::into_chat_message)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let resolved = resolve_model(app_state, &request.model).await?;
+    route.provider_id = Some(resolved.provider.id.clone());
+    route.model_id = resolved.model_id.clone();
+
+    let response = crate::dispatch_chat(
+        app_state,
+        resolved.provider,
+        &resolved.model_name,
+        resolved.api_type,
+        messages,
+        None,
+        &mut |_| {},
+    )
+    .await?;
+
+    if request.stream {
+        Ok((
+            [(header::CONTENT_TYPE, "text/event-stream"), (header::CACHE_CONTROL, "no-cache")],
+            completion_events(&request.model, &response),
+        )
+            .into_response())
+    } else {
+        Ok(Json(completion_body(&request.model, &response)).into_response())
+    }
+}
+
+// Check the bearer token against the configured access token
+async fn authorize(app_state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let expected = app_state.with_db(|conn| access_token(conn).map_err(CommandError::from)).await?;
+
+    match bearer_token(headers) {
+        Some(provided) if constant_time_eq(provided.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(ApiError::unauthorized()),
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn resolve_model(app_state: &AppState, requested: &str) -> Result<chat::ResolvedModel, CommandError> {
+    let requested = requested.to_string();
+    app_state
+        .with_db(move |conn| {
+            chat::resolve_model(conn, &requested)?.ok_or_else(|| {
+                CommandError::not_found(format!(
+                    "The model '{}' is not configured for any provider that can be reached",
+                    requested
+                ))
+            })
+        })
+        .await
+}
+
+// Models reachable through the server, named as `chat::resolve_model` expects them
+async fn model_list(app_state: &AppState) -> Result<Response, CommandError> {
+    let data = app_state.with_db(list_model_entries).await?;
+    Ok(Json(json!({ "object": "list", "data": data })).into_response())
+}
+
+fn list_model_entries(conn: &mut rusqlite::Connection) -> Result<Vec<serde_json::Value>, CommandError> {
+    let mut data = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+
+    for provider in db::get_all_providers(conn)? {
+        if !chat::is_reachable(&provider) {
+            continue;
+        }
+        for model in db::get_models_by_provider(conn, &provider.id)? {
+            if seen.insert(model.name.clone()) {
+                data.push(json!({
+                    "id": model.name,
+                    "object": "model",
+                    "created": model.created_at,
+                    "owned_by": provider.name,
+                }));
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+fn completion_body(model: &str, response: &ai::AIResponse) -> serde_json::Value {
+    let mut message = json!({ "role": "assistant", "content": response.content });
+    if let Some(reasoning) = &response.reasoning {
+        message["reasoning_content"] = json!(reasoning);
+    }
+
+    json!({
+        "id": format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+        "object": "chat.completion",
+        "created": db::get_current_timestamp(),
+        "model": model,
+        "choices": [{ "index": 0, "message": message, "finish_reason": "stop" }],
+    })
+}
+
+// Providers are called without streaming, so a streaming request receives the
+// whole completion as a single chunk followed by the terminating event
+fn completion_events(model: &str, response: &ai::AIResponse) -> String {
+    let mut delta = json!({ "role": "assistant", "content": response.content });
+    if let Some(reasoning) = &response.reasoning {
+        delta["reasoning_content"] = json!(reasoning);
+    }
+
+    let chunk = json!({
+        "id": format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+        "object": "chat.completion.chunk",
+        "created": db::get_current_timestamp(),
+        "model": model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": "stop" }],
+    });
+    format!("data: {}\n\ndata: [DONE]\n\n", chunk)
+}
+
+// Record the request in the log and turn the result into a response
+async fn finish(
+    app_state: &AppState,
+    method: &str,
+    path: &str,
+    route: &Route,
+    started: Instant,
+    result: Result<Response, ApiError>,
+) -> Response {
+    let (status, error, response) = match result {
+        Ok(response) => (response.status(), None, response),
+        Err(e) => (e.status, Some(e.message.clone()), e.into_response()),
+    };
+
+    let latency_ms = started.elapsed().as_millis() as i64;
+    let (method, path) = (method.to_string(), path.to_string());
+    let (model, model_id, provider_id) = (route.model.clone(), route.model_id.clone(), route.provider_id.clone());
+    let logged = app_state
+        .with_db(move |conn| {
+            db::add_api_server_log(
+                conn,
+                &method,
+                &path,
+                model.as_deref(),
+                model_id.as_deref(),
+                provider_id.as_deref(),
+                status.as_u16(),
+                latency_ms,
+                error.as_deref(),
+            )
+            .map_err(CommandError::from)
+        })
+        .await;
+    if let Err(e) = logged {
+        eprintln!("Could not record local API server request: {}", e);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer aichat-abc "));
+        assert_eq!(bearer_token(&headers), Some("aichat-abc"));
+
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic dXNlcjpwYXNz"));
+        assert_eq!(bearer_token(&headers), None);
+
+        assert!(constant_time_eq(b"aichat-abc", b"aichat-abc"));
+        assert!(!constant_time_eq(b"aichat-abc", b"aichat-abd"));
+        assert!(!constant_time_eq(b"aichat-abc", b"aichat-ab"));
+    }
+
+    #[test]
+    fn test_provider_errors_map_to_gateway_statuses() {
+        let status_for = |status: Option<u16>| {
+            let error = ai::ProviderError::new(ai::ErrorKind::Unknown, status, "failed");
+            ApiError::from(CommandError::Provider(error)).status
+        };
+
+        assert_eq!(status_for(Some(429)), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status_for(Some(401)), StatusCode::BAD_GATEWAY);
+        assert_eq!(status_for(Some(500)), StatusCode::BAD_GATEWAY);
+        assert_eq!(status_for(None), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            ApiError::from(CommandError::not_found("missing")).status,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_message_content_accepts_text_parts() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "Be brief" },
+                { "role": "user", "content": [{ "type": "text", "text": "Hello" }, { "type": "text", "text": "there" }] },
+            ],
+        }))
+        .unwrap();
+        let messages: Vec<_> = request.messages
+            .into_iter()
+            .map(|m| m.into_chat_message().ok().unwrap())
+            .collect();
+        assert_eq!(messages[0].content, "Be brief");
+        assert_eq!((messages[1].role.as_str(), messages[1].content.as_str()), ("user", "Hello\nthere"));
+
+        let image: RequestMessage = serde_json::from_value(json!({
+            "role": "user",
+            "content": [{ "type": "image_url", "image_url": { "url": "https://example.com/a.png" } }],
+        }))
+        .unwrap();
+        assert_eq!(image.into_chat_message().err().unwrap().status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_completion_events_format() {
+        let response = ai::AIResponse {
+            content: "Hello".to_string(),
+            reasoning: None,
+            response_id: None,
+            usage: None,
+        };
+
+        let events = completion_events("gpt-4o", &response);
+        let mut lines = events.lines().filter(|l| !l.is_empty());
+        let chunk: serde_json::Value = serde_json::from_str(lines.next().unwrap().strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(chunk["object"], "chat.completion.chunk");
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "Hello");
+        assert_eq!(lines.next(), Some("data: [DONE]"));
+    }
+}
@@ -0,0 +1,50 @@
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rusqlite::Connection;
+
+use crate::db;
+
+// Settings of the local API server, kept apart from the server itself so the
+// rest of the app can refer to them without the server's dependencies
+
+// app_settings keys controlling the local OpenAI-compatible API server
+pub const ENABLED_SETTING: &str = "api_server_enabled";
+pub const PORT_SETTING: &str = "api_server_port";
+pub const TOKEN_SETTING: &str = "api_server_token";
+
+pub const DEFAULT_PORT: u16 = 11435;
+
+// Saved server configuration
+pub struct ServerSettings {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+// Load the server settings, falling back to disabled on the default port
+pub fn load_settings(conn: &Connection) -> rusqlite::Result<ServerSettings> {
+    let enabled = db::get_setting(conn, ENABLED_SETTING)?.as_deref() == Some("true");
+    let port = db::get_setting(conn, PORT_SETTING)?
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(DEFAULT_PORT);
+    Ok(ServerSettings { enabled, port })
+}
+
+// Get the access token clients must send, generating one on first use
+pub fn access_token(conn: &Connection) -> rusqlite::Result<String> {
+    match db::get_setting(conn, TOKEN_SETTING)? {
+        Some(token) if !token.is_empty() => Ok(token),
+        _ => regenerate_token(conn),
+    }
+}
+
+// Replace the access token, invalidating the previous one
+pub fn regenerate_token(conn: &Connection) -> rusqlite::Result<String> {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .map(char::from)
+        .collect();
+    let token = format!("aichat-{}", random);
+    db::set_setting(conn, TOKEN_SETTING, &token)?;
+    Ok(token)
+}
@@ -0,0 +1,300 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::db;
+
+// App lock: commands are refused until the user enters the passphrase, whose
+// Argon2 hash is kept in the settings table. The app locks at startup and
+// after the configured idle time.
+
+pub const PASSPHRASE_HASH_SETTING: &str = "app_lock_hash";
+pub const IDLE_MINUTES_SETTING: &str = "app_lock_idle_minutes";
+
+// Idle minutes before locking when none are configured; 0 never locks
+pub const DEFAULT_IDLE_MINUTES: u32 = 15;
+
+// Commands accepted while locked
+pub const UNLOCK_COMMANDS: &[&str] = &["unlock_app", "get_app_lock_status"];
+
+// Commands the frontend polls, which do not count as activity
+const PASSIVE_COMMANDS: &[&str] = &["get_app_lock_status"];
+
+// Wrong passphrases accepted before unlock attempts are delayed
+const FREE_UNLOCK_ATTEMPTS: u32 = 3;
+
+// Longest wait between unlock attempts
+const MAX_UNLOCK_DELAY: Duration = Duration::from_secs(5 * 60);
+
+// Saved lock settings
+pub struct LockSettings {
+    pub passphrase_hash: Option<String>,
+    pub idle_minutes: u32,
+}
+
+// Load the lock settings; the lock is off without a passphrase hash
+pub fn load_settings(conn: &Connection) -> rusqlite::Result<LockSettings> {
+    let passphrase_hash = db::get_setting(conn, PASSPHRASE_HASH_SETTING)?.filter(|h| !h.is_empty());
+    let idle_minutes = db::get_setting(conn, IDLE_MINUTES_SETTING)?
+        .and_then(|m| m.parse().ok())
+        .unwrap_or(DEFAULT_IDLE_MINUTES);
+    Ok(LockSettings { passphrase_hash, idle_minutes })
+}
+
+// Hash a passphrase with Argon2id and a random salt, in PHC string format
+pub fn hash_passphrase(passphrase: &str) -> Result<String, String> {
+    let salt_bytes: [u8; 16] = rand::random();
+    let salt = SaltString::encode_b64(&salt_bytes).map_err(|e| e.to_string())?;
+    Argon2::default()
+        .hash_password(passphrase.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+// Check a passphrase against a stored hash; malformed hashes never match
+pub fn verify_passphrase(passphrase: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| Argon2::default().verify_password(passphrase.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LockStatus {
+    pub enabled: bool,
+    pub locked: bool,
+    pub idle_minutes: u32,
+}
+
+// Why an unlock attempt cannot be checked now
+#[derive(Debug, PartialEq)]
+pub enum UnlockRefused {
+    // Another attempt is being checked
+    InProgress,
+    // Too many wrong passphrases; wait this long
+    Delayed(Duration),
+}
+
+// Lock state kept in memory for the running app
+pub struct AppLock {
+    enabled: bool,
+    locked: bool,
+    idle_minutes: u32,
+    last_activity: Instant,
+    // Wrong passphrases entered since the last unlock
+    failed_unlocks: u32,
+    // No unlock attempt is checked before this time
+    next_unlock_at: Option<Instant>,
+    // An unlock attempt is being checked
+    unlock_in_progress: bool,
+}
+
+impl Default for AppLock {
+    fn default() -> Self {
+        AppLock {
+            enabled: false,
+            locked: false,
+            idle_minutes: DEFAULT_IDLE_MINUTES,
+            last_activity: Instant::now(),
+            failed_unlocks: 0,
+            next_unlock_at: None,
+            unlock_in_progress: false,
+        }
+    }
+}
+
+impl AppLock {
+    // Apply saved settings; turning the lock off also unlocks
+    pub fn configure(&mut self, settings: &LockSettings) {
+        self.enabled = settings.passphrase_hash.is_some();
+        self.idle_minutes = settings.idle_minutes;
+        if !self.enabled {
+            self.locked = false;
+        }
+    }
+
+    pub fn status(&self) -> LockStatus {
+        LockStatus {
+            enabled: self.enabled,
+            locked: self.locked,
+            idle_minutes: self.idle_minutes,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    // Lock now, if a passphrase is set
+    pub fn lock(&mut self) {
+        self.locked = self.enabled;
+    }
+
+    pub fn unlock(&mut self, now: Instant) {
+        self.locked = false;
+        self.last_activity = now;
+        self.failed_unlocks = 0;
+        self.next_unlock_at = None;
+        self.unlock_in_progress = false;
+    }
+
+    // Time left before the next unlock attempt may be checked, if any
+    pub fn unlock_delay(&self, now: Instant) -> Option<Duration> {
+        self.next_unlock_at
+            .map(|at| at.saturating_duration_since(now))
+            .filter(|delay| !delay.is_zero())
+    }
+
+    // Reserve the right to check an unlock attempt. Only one attempt is checked
+    // at a time, so parallel attempts cannot all slip in before a failure is
+    // counted. End it with `unlock`, `record_failed_unlock` or `cancel_unlock`.
+    pub fn begin_unlock(&mut self, now: Instant) -> Result<(), UnlockRefused> {
+        if self.unlock_in_progress {
+            return Err(UnlockRefused::InProgress);
+        }
+        if let Some(delay) = self.unlock_delay(now) {
+            return Err(UnlockRefused::Delayed(delay));
+        }
+        self.unlock_in_progress = true;
+        Ok(())
+    }
+
+    // End an attempt that could not be checked
+    pub fn cancel_unlock(&mut self) {
+        self.unlock_in_progress = false;
+    }
+
+    // Count a wrong passphrase. After a few, each attempt doubles the wait
+    // before the next one, up to MAX_UNLOCK_DELAY.
+    pub fn record_failed_unlock(&mut self, now: Instant) {
+        self.unlock_in_progress = false;
+        self.failed_unlocks += 1;
+        if self.failed_unlocks >= FREE_UNLOCK_ATTEMPTS {
+            let doublings = (self.failed_unlocks - FREE_UNLOCK_ATTEMPTS).min(16);
+            let delay = Duration::from_secs(1 << doublings).min(MAX_UNLOCK_DELAY);
+            self.next_unlock_at = Some(now + delay);
+        }
+    }
+
+    // Lock once the app has been idle for the configured time. Returns true
+    // when this call locked it.
+    pub fn lock_if_idle(&mut self, now: Instant) -> bool {
+        if !self.enabled || self.locked || self.idle_minutes == 0 {
+            return false;
+        }
+        let idle_limit = Duration::from_secs(u64::from(self.idle_minutes) * 60);
+        if now.duration_since(self.last_activity) < idle_limit {
+            return false;
+        }
+        self.locked = true;
+        true
+    }
+
+    // Whether a command may run, recording it as activity when it does
+    pub fn allow(&mut self, command: &str, now: Instant) -> bool {
+        self.lock_if_idle(now);
+        if self.locked {
+            return UNLOCK_COMMANDS.contains(&command);
+        }
+        if !PASSIVE_COMMANDS.contains(&command) {
+            self.last_activity = now;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_lock(idle_minutes: u32) -> AppLock {
+        let mut lock = AppLock::default();
+        lock.configure(&LockSettings {
+            passphrase_hash: Some("hash".to_string()),
+            idle_minutes,
+        });
+        lock
+    }
+
+    #[test]
+    fn test_hash_and_verify_passphrase() {
+        let hash = hash_passphrase("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_passphrase("correct horse", &hash));
+        assert!(!verify_passphrase("wrong horse", &hash));
+        assert!(!verify_passphrase("correct horse", "not a hash"));
+        // Salts differ between hashes of the same passphrase
+        assert_ne!(hash, hash_passphrase("correct horse").unwrap());
+    }
+
+    #[test]
+    fn test_locked_app_only_allows_unlock_commands() {
+        let mut lock = enabled_lock(15);
+        let now = Instant::now();
+        lock.lock();
+
+        assert!(!lock.allow("get_chat_sessions", now));
+        assert!(lock.allow("get_app_lock_status", now));
+        assert!(lock.allow("unlock_app", now));
+
+        lock.unlock(now);
+        assert!(lock.allow("get_chat_sessions", now));
+
+        // Without a passphrase the app never locks
+        let mut open = AppLock::default();
+        open.lock();
+        assert!(!open.is_locked());
+    }
+
+    #[test]
+    fn test_wrong_passphrases_delay_unlock_attempts() {
+        let mut lock = enabled_lock(15);
+        let now = Instant::now();
+        lock.lock();
+
+        lock.record_failed_unlock(now);
+        lock.record_failed_unlock(now);
+        assert_eq!(lock.unlock_delay(now), None);
+
+        // Overlapping attempts are refused until the first one is settled
+        assert_eq!(lock.begin_unlock(now), Ok(()));
+        assert_eq!(lock.begin_unlock(now), Err(UnlockRefused::InProgress));
+        lock.record_failed_unlock(now);
+        assert_eq!(lock.begin_unlock(now), Err(UnlockRefused::Delayed(Duration::from_secs(1))));
+        assert_eq!(lock.unlock_delay(now), Some(Duration::from_secs(1)));
+        lock.record_failed_unlock(now);
+        assert_eq!(lock.unlock_delay(now), Some(Duration::from_secs(2)));
+        assert_eq!(lock.unlock_delay(now + Duration::from_secs(2)), None);
+
+        for _ in 0..20 {
+            lock.record_failed_unlock(now);
+        }
+        assert_eq!(lock.unlock_delay(now), Some(MAX_UNLOCK_DELAY));
+
+        // Unlocking resets the count
+        lock.unlock(now);
+        lock.record_failed_unlock(now);
+        assert_eq!(lock.unlock_delay(now), None);
+    }
+
+    #[test]
+    fn test_lock_after_idle_time() {
+        let mut lock = enabled_lock(5);
+        let start = Instant::now();
+        lock.unlock(start);
+
+        assert!(!lock.lock_if_idle(start + Duration::from_secs(4 * 60)));
+        // Activity resets the idle timer, status polling does not
+        assert!(lock.allow("get_chat_sessions", start + Duration::from_secs(4 * 60)));
+        assert!(lock.allow("get_app_lock_status", start + Duration::from_secs(8 * 60)));
+        assert!(!lock.lock_if_idle(start + Duration::from_secs(8 * 60)));
+        assert!(lock.lock_if_idle(start + Duration::from_secs(9 * 60)));
+        assert!(!lock.allow("get_chat_sessions", start + Duration::from_secs(9 * 60)));
+
+        // An idle time of 0 never locks
+        let mut never = enabled_lock(0);
+        never.unlock(start);
+        assert!(!never.lock_if_idle(start + Duration::from_secs(24 * 60 * 60)));
+    }
+}
@@ -0,0 +1,398 @@
+// Command-line client that shares the desktop app's database, so providers,
+// API keys and chat history configured in the GUI are available from scripts
+// and SSH sessions.
+
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+use aichat_pro_lib::{ai, chat, credentials, db, profiles};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rusqlite::Connection;
+
+#[derive(Parser)]
+#[command(name = "aichat-cli", version, about = "Use AIChat Pro providers and chat history from the terminal")]
+struct Cli {
+    /// Data directory to use instead of the app's (also AICHAT_DATA_DIR)
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+    /// Profile to use instead of the app's current one (also AICHAT_PROFILE)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List configured providers
+    Providers,
+    /// List models, optionally for a single provider
+    Models {
+        /// Provider id
+        #[arg(short, long)]
+        provider: Option<String>,
+    },
+    /// List chat sessions, most recently updated first
+    Sessions {
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Send a prompt and stream the reply to stdout
+    Ask(AskArgs),
+    /// Export a session and its messages
+    Export {
+        /// Session id (a unique prefix is enough)
+        session: String,
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+        /// Write to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct AskArgs {
+    /// Prompt text; piped stdin is appended to it as context
+    prompt: Vec<String>,
+    /// Continue an existing session (a unique id prefix is enough)
+    #[arg(short, long)]
+    session: Option<String>,
+    /// Model name or `<provider>/<model>`; defaults to the session's model
+    #[arg(short, long)]
+    model: Option<String>,
+    /// System prompt for a new session
+    #[arg(long, conflicts_with = "session")]
+    system: Option<String>,
+    /// Name of the new session; defaults to the start of the prompt
+    #[arg(long, conflicts_with = "session")]
+    name: Option<String>,
+    /// Do not save the exchange to the chat history
+    #[arg(long)]
+    no_save: bool,
+    /// Wait for the whole reply instead of streaming it
+    #[arg(long)]
+    no_stream: bool,
+    /// Also print the model's reasoning, to stderr
+    #[arg(long)]
+    reasoning: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+// Length of generated session names
+const SESSION_NAME_LEN: usize = 50;
+
+// Environment variable holding the passphrase of an encrypted database
+const PASSPHRASE_ENV: &str = "AICHAT_DB_PASSPHRASE";
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        // Output piped into e.g. `head` was closed early; that is not an error
+        if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) {
+            return;
+        }
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let location = profiles::resolve(cli.data_dir, cli.profile).map_err(|e| anyhow!(e))?;
+    credentials::set_profile(&location.profile);
+    let key = database_key(&location.dir)?;
+    let conn = db::init_db(&location.dir, key.as_ref()).context("Could not open the AIChat Pro database")?;
+
+    match cli.command {
+        Command::Providers => list_providers(&conn),
+        Command::Models { provider } => list_models(&conn, provider.as_deref()),
+        Command::Sessions { limit } => list_sessions(&conn, limit),
+        Command::Ask(args) => ask(&conn, args).await,
+        Command::Export { session, format, output } => export_session(&conn, &session, format, output),
+    }
+}
+
+// Key of an encrypted database, from the OS keyring or the passphrase variable
+fn database_key(data_dir: &Path) -> Result<Option<db::DatabaseKey>> {
+    if !db::is_encrypted(data_dir) {
+        return Ok(None);
+    }
+    if let Ok(key) = credentials::get_database_key() {
+        return Ok(Some(db::DatabaseKey::Raw(key)));
+    }
+    match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => Ok(Some(db::DatabaseKey::Passphrase(passphrase))),
+        Err(_) => bail!("The database is encrypted; set {} to its passphrase", PASSPHRASE_ENV),
+    }
+}
+
+fn list_providers(conn: &Connection) -> Result<()> {
+    let mut out = io::stdout().lock();
+    for provider in db::get_all_providers(conn)? {
+        let key = if provider.api_key.is_some() { "key set" } else { "no key" };
+        writeln!(out, "{:<24} {:<24} {:<8} {}", provider.id, provider.name, key, provider.api_url)?;
+    }
+    Ok(())
+}
+
+fn list_models(conn: &Connection, provider_id: Option<&str>) -> Result<()> {
+    let providers = match provider_id {
+        Some(id) => vec![db::get_provider_by_id(conn, id)?.ok_or_else(|| anyhow!("Provider '{}' not found", id))?],
+        None => db::get_all_providers(conn)?,
+    };
+
+    let mut out = io::stdout().lock();
+    for provider in providers {
+        for model in db::get_models_by_provider(conn, &provider.id)? {
+            let favorite = if model.is_favorite { "*" } else { " " };
+            writeln!(out, "{} {:<24} {:<40} {}", favorite, provider.id, model.name, model.api_type)?;
+        }
+    }
+    Ok(())
+}
+
+fn list_sessions(conn: &Connection, limit: usize) -> Result<()> {
+    let mut out = io::stdout().lock();
+    for session in db::get_all_chat_sessions(conn)?.into_iter().take(limit) {
+        let model = match &session.model_id {
+            Some(id) => db::get_model_by_id(conn, id)?.map(|m| m.name).unwrap_or_default(),
+            None => String::new(),
+        };
+        writeln!(out, "{}  {:<40} {}", session.id, session.name, model)?;
+    }
+    Ok(())
+}
+
+// Find a session by id or unique id prefix
+fn find_session(conn: &Connection, id: &str) -> Result<db::ChatSession> {
+    if let Some(session) = db::get_chat_session_by_id(conn, id)? {
+        return Ok(session);
+    }
+
+    let mut matches: Vec<db::ChatSession> = db::get_all_chat_sessions(conn)?
+        .into_iter()
+        .filter(|s| s.id.starts_with(id))
+        .collect();
+    match matches.len() {
+        0 => bail!("Session '{}' not found", id),
+        1 => Ok(matches.remove(0)),
+        n => bail!("Session prefix '{}' matches {} sessions", id, n),
+    }
+}
+
+// Build the prompt from the arguments and any piped stdin
+fn read_prompt(args: &[String]) -> Result<String> {
+    let mut prompt = args.join(" ");
+
+    let stdin = io::stdin();
+    if !stdin.is_terminal() {
+        let mut context = String::new();
+        stdin.lock().read_to_string(&mut context).context("Could not read stdin")?;
+        let context = context.trim_end();
+        if !context.is_empty() {
+            prompt = if prompt.is_empty() {
+                context.to_string()
+            } else {
+                format!("{}\n\n{}", prompt, context)
+            };
+        }
+    }
+
+    if prompt.trim().is_empty() {
+        bail!("No prompt given; pass it as an argument or pipe it through stdin");
+    }
+    Ok(prompt)
+}
+
+// Resolve the model to use, from the arguments or the session
+fn resolve_model(conn: &Connection, requested: Option<&str>, session: Option<&db::ChatSession>) -> Result<chat::ResolvedModel> {
+    if let Some(name) = requested {
+        return chat::resolve_model(conn, name)?
+            .ok_or_else(|| anyhow!("The model '{}' is not configured for any provider with an API key", name));
+    }
+
+    let model_id = session
+        .and_then(|s| s.model_id.as_deref())
+        .ok_or_else(|| anyhow!("No model given; use --model"))?;
+    let model = db::get_model_by_id(conn, model_id)?
+        .ok_or_else(|| anyhow!("The session's model no longer exists; use --model"))?;
+    let provider = db::get_provider_by_id(conn, &model.provider_id)?
+        .ok_or_else(|| anyhow!("Provider '{}' not found", model.provider_id))?;
+
+    Ok(chat::ResolvedModel {
+        provider,
+        api_type: ai::ApiType::parse(&model.api_type).unwrap_or_default(),
+        model_id: Some(model.id),
+        model_name: model.name,
+    })
+}
+
+// Get an AI client configured like the app's for this provider
+fn provider_client(conn: &Connection, provider: &db::AIProvider) -> Result<ai::AIClient> {
+    let mut client = ai::AIClient::new();
+    if let Some(value) = db::get_setting(conn, chat::RETRY_POLICY_SETTING)? {
+        match serde_json::from_str::<ai::RetryPolicy>(&value) {
+            Ok(policy) => client.set_retry_policy(policy),
+            Err(e) => eprintln!("Ignoring invalid retry policy setting: {}", e),
+        }
+    }
+
+    let settings = db::get_connection_settings(conn, &provider.id)?;
+    let endpoints = ai::OpenAIEndpoints::new(provider.chat_path.as_deref(), provider.models_path.as_deref());
+    Ok(client.for_provider(&provider.id, &settings)?.with_endpoints(endpoints))
+}
+
+async fn ask(conn: &Connection, args: AskArgs) -> Result<()> {
+    let prompt = read_prompt(&args.prompt)?;
+    let session = args.session.as_deref().map(|id| find_session(conn, id)).transpose()?;
+    let resolved = resolve_model(conn, args.model.as_deref(), session.as_ref())?;
+
+    // Rebuild the conversation the same way the app sends it
+    let mut messages = Vec::new();
+    let system_prompt = session.as_ref().and_then(|s| s.system_prompt.clone()).or(args.system.clone());
+    if let Some(system) = system_prompt.as_ref().filter(|s| !s.trim().is_empty()) {
+        messages.push(ai::ChatMessage { role: "system".to_string(), content: system.clone() });
+    }
+    if let Some(session) = &session {
+        for message in db::get_messages_by_session(conn, &session.id)? {
+            messages.push(ai::ChatMessage { role: message.role, content: message.content });
+        }
+    }
+    messages.push(ai::ChatMessage { role: "user".to_string(), content: prompt.clone() });
+    if let Some(session) = &session {
+        messages = chat::with_session_summary(conn, &session.id, messages)?;
+    }
+    if db::get_setting(conn, chat::MEMORY_ENABLED_SETTING)?.as_deref() != Some("false") {
+        let folder_id = session.as_ref().and_then(|s| s.folder_id.as_deref());
+        let memories = db::get_memories_for_context(conn, None, folder_id)?;
+        messages = chat::inject_memories(messages, &chat::select_memories(memories, &prompt, chat::MAX_CONTEXT_MEMORIES));
+    }
+
+    let previous_response_id = match (&session, resolved.api_type) {
+        (Some(session), ai::ApiType::Responses) => db::get_latest_response_id(conn, &session.id, resolved.model_id.as_deref())?,
+        _ => None,
+    };
+
+    let client = provider_client(conn, &resolved.provider)?;
+    let azure_settings = db::get_azure_settings(conn, &resolved.provider.id)?;
+
+    let show_reasoning = args.reasoning;
+    let mut streamed = false;
+    let mut on_delta = |delta: ai::StreamDelta| {
+        if let Some(reasoning) = delta.reasoning.filter(|_| show_reasoning) {
+            eprint!("{}", reasoning);
+        }
+        if let Some(content) = delta.content {
+            streamed = true;
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(content.as_bytes());
+            let _ = stdout.flush();
+        }
+    };
+
+    let params = chat::ChatParams {
+        model_name: &resolved.model_name,
+        api_type: resolved.api_type,
+        messages,
+        previous_response_id,
+        stream: !args.no_stream,
+        tools: Vec::new(),
+        on_tool_call: None,
+    };
+    let response = chat::dispatch_chat(&client, &resolved.provider, &azure_settings, params, &mut on_delta).await?;
+
+    // Providers that do not stream deliver the reply in one piece
+    if !streamed {
+        if let Some(reasoning) = response.reasoning.as_ref().filter(|_| show_reasoning) {
+            eprintln!("{}", reasoning);
+        }
+        write!(io::stdout().lock(), "{}", response.content)?;
+    }
+    writeln!(io::stdout().lock())?;
+
+    if args.no_save {
+        return Ok(());
+    }
+
+    let session_id = match &session {
+        Some(session) => session.id.clone(),
+        None => {
+            let name = args.name.clone().unwrap_or_else(|| session_name(&prompt));
+            let id = db::create_chat_session(conn, &name, resolved.model_id.as_deref(), args.system.as_deref())?;
+            eprintln!("Saved to new session {}", id);
+            id
+        }
+    };
+    db::add_message(conn, &session_id, "user", &prompt, None)?;
+    let message_id = db::add_message(conn, &session_id, "assistant", &response.content, response.reasoning.as_deref())?;
+    if let Some(response_id) = &response.response_id {
+        db::set_message_response_id(conn, &message_id, response_id)?;
+    }
+    if let Some(model_id) = &resolved.model_id {
+        db::set_message_model(conn, &message_id, model_id)?;
+    }
+
+    Ok(())
+}
+
+// Name a new session after the first line of its prompt
+fn session_name(prompt: &str) -> String {
+    let first_line = prompt.lines().find(|l| !l.trim().is_empty()).unwrap_or_default().trim();
+    let mut name: String = first_line.chars().take(SESSION_NAME_LEN).collect();
+    if first_line.chars().count() > SESSION_NAME_LEN {
+        name.push('…');
+    }
+    name
+}
+
+fn export_session(conn: &Connection, id: &str, format: ExportFormat, output: Option<PathBuf>) -> Result<()> {
+    let session = find_session(conn, id)?;
+    let messages = db::get_messages_by_session(conn, &session.id)?;
+
+    let exported = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&serde_json::json!({
+            "session": session,
+            "messages": messages,
+        }))?,
+        ExportFormat::Markdown => session_markdown(&session, &messages),
+    };
+
+    match output {
+        Some(path) => fs::write(&path, exported).with_context(|| format!("Could not write {}", path.display()))?,
+        None => writeln!(io::stdout().lock(), "{}", exported)?,
+    }
+    Ok(())
+}
+
+fn session_markdown(session: &db::ChatSession, messages: &[db::ChatMessage]) -> String {
+    let mut markdown = format!("# {}\n", session.name);
+    if let Some(system) = session.system_prompt.as_ref().filter(|s| !s.trim().is_empty()) {
+        markdown.push_str(&format!("\n## System\n\n{}\n", system));
+    }
+
+    for message in messages {
+        let heading = match message.role.as_str() {
+            "user" => "User",
+            "assistant" => "Assistant",
+            _ => "System",
+        };
+        markdown.push_str(&format!("\n## {}\n\n", heading));
+        if let Some(reasoning) = message.reasoning.as_ref().filter(|r| !r.trim().is_empty()) {
+            for line in reasoning.lines() {
+                markdown.push_str(&format!("> {}\n", line));
+            }
+            markdown.push('\n');
+        }
+        markdown.push_str(&message.content);
+        markdown.push('\n');
+    }
+    markdown
+}
@@ -0,0 +1,330 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use crate::ai::{self, ApiType};
+use crate::db;
+use crate::model_info::ModelMetadata;
+
+// Team catalog: a JSON or TOML file, on disk or behind a URL, listing the
+// providers and models a team has approved. Syncing merges it into the
+// provider and model tables as managed entries, which the app keeps
+// read-only apart from API keys and favorites.
+
+pub const SOURCE_SETTING: &str = "catalog_source";
+pub const SYNC_MINUTES_SETTING: &str = "catalog_sync_minutes";
+pub const LAST_SYNCED_SETTING: &str = "catalog_last_synced_at";
+pub const LAST_ERROR_SETTING: &str = "catalog_last_error";
+
+// Minutes between syncs when none are configured; 0 only syncs on request
+pub const DEFAULT_SYNC_MINUTES: u32 = 60;
+
+// Current version of the catalog file format
+pub const CATALOG_VERSION: u32 = 1;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum CatalogError {
+    UnsupportedVersion(u32),
+    Fetch(String),
+    Parse(String),
+    Invalid(String),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogError::UnsupportedVersion(v) => write!(f, "Unsupported catalog version: {}", v),
+            CatalogError::Fetch(e) => write!(f, "Could not load catalog: {}", e),
+            CatalogError::Parse(e) => write!(f, "Invalid catalog file: {}", e),
+            CatalogError::Invalid(e) => write!(f, "Invalid catalog: {}", e),
+            CatalogError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl Error for CatalogError {}
+
+impl From<rusqlite::Error> for CatalogError {
+    fn from(error: rusqlite::Error) -> Self {
+        CatalogError::Database(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Catalog {
+    pub version: u32,
+    #[serde(default)]
+    pub providers: Vec<CatalogProvider>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogProvider {
+    pub id: String,
+    pub name: String,
+    pub api_url: String,
+    // Defaults to `<id>_api_key`
+    #[serde(default)]
+    pub api_key_name: Option<String>,
+    #[serde(default)]
+    pub chat_path: Option<String>,
+    #[serde(default)]
+    pub models_path: Option<String>,
+    #[serde(default)]
+    pub models: Vec<CatalogModel>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogModel {
+    pub name: String,
+    // Left as stored when unset
+    #[serde(default)]
+    pub api_type: Option<ApiType>,
+    #[serde(flatten)]
+    pub metadata: ModelMetadata,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CatalogSyncSummary {
+    pub providers_added: usize,
+    pub providers_updated: usize,
+    pub models_added: usize,
+    pub models_updated: usize,
+}
+
+// Saved catalog settings; syncing is off without a source
+pub struct CatalogSettings {
+    pub source: Option<String>,
+    pub sync_minutes: u32,
+}
+
+pub fn load_settings(conn: &Connection) -> rusqlite::Result<CatalogSettings> {
+    let source = db::get_setting(conn, SOURCE_SETTING)?.filter(|s| !s.trim().is_empty());
+    let sync_minutes = db::get_setting(conn, SYNC_MINUTES_SETTING)?
+        .and_then(|m| m.parse().ok())
+        .unwrap_or(DEFAULT_SYNC_MINUTES);
+    Ok(CatalogSettings { source, sync_minutes })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogStatus {
+    pub source: Option<String>,
+    pub sync_minutes: u32,
+    pub last_synced_at: Option<i64>,
+    // Error of the last sync attempt, cleared by a successful one
+    pub last_error: Option<String>,
+}
+
+pub fn load_status(conn: &Connection) -> rusqlite::Result<CatalogStatus> {
+    let settings = load_settings(conn)?;
+    Ok(CatalogStatus {
+        source: settings.source,
+        sync_minutes: settings.sync_minutes,
+        last_synced_at: db::get_setting(conn, LAST_SYNCED_SETTING)?.and_then(|t| t.parse().ok()),
+        last_error: db::get_setting(conn, LAST_ERROR_SETTING)?.filter(|e| !e.is_empty()),
+    })
+}
+
+// Record a sync attempt. Failed attempts count too, so an unreachable source
+// is retried at the sync interval rather than on every check.
+pub fn record_sync(conn: &Connection, now: i64, error: Option<&str>) -> rusqlite::Result<()> {
+    db::set_setting(conn, LAST_SYNCED_SETTING, &now.to_string())?;
+    db::set_setting(conn, LAST_ERROR_SETTING, error.unwrap_or(""))
+}
+
+// Whether a periodic sync is due
+pub fn sync_due(status: &CatalogStatus, now: i64) -> bool {
+    if status.source.is_none() || status.sync_minutes == 0 {
+        return false;
+    }
+    match status.last_synced_at {
+        Some(last) => now - last >= i64::from(status.sync_minutes) * 60,
+        None => true,
+    }
+}
+
+// Read a catalog from an http(s) URL, a `file://` URL or a local path
+pub async fn load_catalog_text(source: &str) -> Result<String, CatalogError> {
+    let source = source.trim();
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .map_err(|e| CatalogError::Fetch(e.to_string()))?;
+        let response = client
+            .get(source)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| CatalogError::Fetch(e.to_string()))?;
+        return response.text().await.map_err(|e| CatalogError::Fetch(e.to_string()));
+    }
+
+    let path = source.strip_prefix("file://").unwrap_or(source);
+    tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| CatalogError::Fetch(format!("{}: {}", path, e)))
+}
+
+// Parse a catalog, telling JSON from TOML by its first character
+pub fn parse_catalog(content: &str) -> Result<Catalog, CatalogError> {
+    let catalog: Catalog = if content.trim_start().starts_with('{') {
+        serde_json::from_str(content).map_err(|e| CatalogError::Parse(e.to_string()))?
+    } else {
+        toml::from_str(content).map_err(|e| CatalogError::Parse(e.to_string()))?
+    };
+    if catalog.version > CATALOG_VERSION {
+        return Err(CatalogError::UnsupportedVersion(catalog.version));
+    }
+
+    let mut ids = HashSet::new();
+    for provider in &catalog.providers {
+        if provider.id.trim().is_empty() || provider.api_url.trim().is_empty() {
+            return Err(CatalogError::Invalid("every provider needs an id and an api_url".to_string()));
+        }
+        if !ids.insert(provider.id.as_str()) {
+            return Err(CatalogError::Invalid(format!("provider {} is listed twice", provider.id)));
+        }
+    }
+    Ok(catalog)
+}
+
+// Merge a catalog in one transaction. Entries that were managed but are no
+// longer in the catalog are kept and become ordinary, editable ones.
+pub fn apply_catalog(conn: &mut Connection, catalog: &Catalog) -> Result<CatalogSyncSummary, CatalogError> {
+    let tx = conn.transaction()?;
+    db::clear_managed_entries(&tx)?;
+
+    let mut summary = CatalogSyncSummary::default();
+    for provider in &catalog.providers {
+        let api_key_name = provider
+            .api_key_name
+            .clone()
+            .unwrap_or_else(|| format!("{}_api_key", provider.id));
+        let added = db::save_managed_provider(
+            &tx,
+            &provider.id,
+            &provider.name,
+            &ai::normalize_base_url(&provider.api_url),
+            &api_key_name,
+            provider.chat_path.as_deref(),
+            provider.models_path.as_deref(),
+        )?;
+        if added {
+            summary.providers_added += 1;
+        } else {
+            summary.providers_updated += 1;
+        }
+
+        for model in &provider.models {
+            if db::save_managed_model(&tx, &provider.id, &model.name, model.api_type, &model.metadata)? {
+                summary.models_added += 1;
+            } else {
+                summary.models_updated += 1;
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const CATALOG_TOML: &str = r#"
+version = 1
+
+[[providers]]
+id = "team-openai"
+name = "Team OpenAI"
+api_url = "api.openai.com/v1/"
+
+[[providers.models]]
+name = "gpt-4o"
+api_type = "responses"
+context_length = 128000
+input_price = 2.5
+output_price = 10
+"#;
+
+    // Serve one HTTP response with `body` and return the URL
+    fn serve_once(body: &'static str) -> String {
+        let url = test_support::serve(1, move |_| ("200 OK", body.to_string()));
+        format!("{}/catalog.json", url)
+    }
+
+    #[tokio::test]
+    async fn test_sync_from_local_file() {
+        let path = std::env::temp_dir().join(format!("aichat-pro-catalog-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, CATALOG_TOML).unwrap();
+        let text = load_catalog_text(&format!("file://{}", path.display())).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut conn = db::create_test_db().unwrap();
+        let summary = apply_catalog(&mut conn, &parse_catalog(&text).unwrap()).unwrap();
+        assert_eq!((summary.providers_added, summary.models_added), (1, 1));
+
+        let provider = db::get_provider_by_id(&conn, "team-openai").unwrap().unwrap();
+        assert!(provider.is_managed);
+        assert_eq!(provider.api_url, "https://api.openai.com/v1");
+        assert_eq!(provider.api_key_name, "team-openai_api_key");
+        let model = &db::get_models_by_provider(&conn, "team-openai").unwrap()[0];
+        assert!(model.is_managed);
+        assert_eq!(model.api_type, "responses");
+        assert_eq!(model.metadata.context_length, Some(128000));
+        assert_eq!(model.metadata.output_price, Some(10.0));
+
+        // Resyncing keeps the model's id and favorite flag
+        db::toggle_model_favorite(&conn, &model.id, true).unwrap();
+        let summary = apply_catalog(&mut conn, &parse_catalog(&text).unwrap()).unwrap();
+        assert_eq!((summary.providers_updated, summary.models_updated), (1, 1));
+        let resynced = &db::get_models_by_provider(&conn, "team-openai").unwrap()[0];
+        assert_eq!(resynced.id, model.id);
+        assert!(resynced.is_favorite);
+    }
+
+    #[tokio::test]
+    async fn test_sync_from_http_and_release_removed_entries() {
+        let mut conn = db::create_test_db().unwrap();
+        apply_catalog(&mut conn, &parse_catalog(CATALOG_TOML).unwrap()).unwrap();
+
+        let url = serve_once(r#"{"version": 1, "providers": [{"id": "team-local", "name": "Local", "api_url": "http://localhost:11434/v1", "models": [{"name": "llama3", "context_length": 8192}]}]}"#);
+        let text = load_catalog_text(&url).await.unwrap();
+        let summary = apply_catalog(&mut conn, &parse_catalog(&text).unwrap()).unwrap();
+        assert_eq!((summary.providers_added, summary.models_added), (1, 1));
+
+        assert!(db::get_provider_by_id(&conn, "team-local").unwrap().unwrap().is_managed);
+        // Dropped from the catalog, so no longer managed
+        let released = db::get_provider_by_id(&conn, "team-openai").unwrap().unwrap();
+        assert!(!released.is_managed);
+        assert!(!db::get_models_by_provider(&conn, "team-openai").unwrap()[0].is_managed);
+
+        let duplicate = r#"{"version": 1, "providers": [{"id": "a", "name": "A", "api_url": "x"}, {"id": "a", "name": "B", "api_url": "y"}]}"#;
+        assert!(matches!(parse_catalog(duplicate), Err(CatalogError::Invalid(_))));
+        assert!(matches!(parse_catalog(r#"{"version": 9}"#), Err(CatalogError::UnsupportedVersion(9))));
+    }
+
+    #[test]
+    fn test_sync_due() {
+        let mut status = CatalogStatus {
+            source: Some("catalog.toml".to_string()),
+            sync_minutes: 60,
+            last_synced_at: None,
+            last_error: None,
+        };
+        assert!(sync_due(&status, 1000));
+        status.last_synced_at = Some(1000);
+        assert!(!sync_due(&status, 1000 + 59 * 60));
+        assert!(sync_due(&status, 1000 + 60 * 60));
+        status.sync_minutes = 0;
+        assert!(!sync_due(&status, 1000 + 24 * 60 * 60));
+    }
+}
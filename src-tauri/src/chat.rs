@@ -0,0 +1,818 @@
+use crate::ai::{self, AIClient, AIError, AIResponse, ApiType, AzureSettings, ChatMessage, StreamDelta, ToolCall, ToolDefinition};
+use crate::db::{self, AIProvider};
+use crate::model_info::ModelInfo;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::Range;
+
+// Provider routing shared by the app commands, the local API server and the CLI
+
+// Settings key holding the JSON-encoded retry policy
+pub const RETRY_POLICY_SETTING: &str = "retry_policy";
+
+// Settings key disabling automatic session titles when set to "false"
+pub const AUTO_TITLE_SETTING: &str = "auto_title_enabled";
+
+// Settings key holding the id of the model used to generate session titles.
+// Sessions fall back to their own model when it is not set.
+pub const TITLE_MODEL_SETTING: &str = "title_model_id";
+
+// Longest title kept from a model reply, in characters
+const MAX_TITLE_CHARS: usize = 60;
+
+// Longest part of each message included in a title request, in characters
+const TITLE_EXCERPT_CHARS: usize = 2000;
+
+// Settings key holding the JSON-encoded summary policy
+pub const SUMMARY_POLICY_SETTING: &str = "summary_policy";
+
+// Longest part of each message included in a summary request, in characters
+const SUMMARY_EXCERPT_CHARS: usize = 4000;
+
+// Settings key disabling memory injection when set to "false"
+pub const MEMORY_ENABLED_SETTING: &str = "memory_enabled";
+
+// Settings key offering the save_memory tool to models when set to "true"
+pub const MEMORY_TOOL_SETTING: &str = "memory_tool_enabled";
+
+// Name of the tool models call to save a memory
+pub const SAVE_MEMORY_TOOL: &str = "save_memory";
+
+// Most memories added to a request
+pub const MAX_CONTEXT_MEMORIES: usize = 20;
+
+// Helper function to determine provider type
+pub fn determine_provider_type(provider_id: &str, api_url: &str, provider_name: &str) -> String {
+    // Check ID prefix first (most reliable)
+    if provider_id.starts_with("azure") {
+        return "azure".to_string();
+    }
+    if provider_id.starts_with("openai") {
+        return "openai".to_string();
+    }
+    if provider_id.starts_with("deepseek") {
+        return "deepseek".to_string();
+    }
+    if provider_id.starts_with("grok") {
+        return "grok".to_string();
+    }
+    if provider_id.starts_with("gemini") {
+        return "gemini".to_string();
+    }
+    if provider_id.starts_with("ollama") {
+        return "ollama".to_string();
+    }
+    if provider_id.starts_with("custom") {
+        return "custom".to_string();
+    }
+
+    // Check API URL (second priority)
+    let api_url_lower = api_url.to_lowercase();
+    if api_url_lower.contains("openai.azure.com") || api_url_lower.contains("cognitiveservices.azure.com") {
+        return "azure".to_string();
+    }
+    if api_url_lower.contains("openai.com") {
+        return "openai".to_string();
+    }
+    if api_url_lower.contains("deepseek.com") {
+        return "deepseek".to_string();
+    }
+    if api_url_lower.contains("grok.x.ai") || api_url_lower.contains("x.ai") {
+        return "grok".to_string();
+    }
+    if api_url_lower.contains("googleapis.com") || api_url_lower.contains("generativelanguage") {
+        return "gemini".to_string();
+    }
+    if api_url_lower.contains(":11434") {
+        return "ollama".to_string();
+    }
+
+    // Check provider name as fallback (third priority)
+    let name_lower = provider_name.to_lowercase();
+    if name_lower.contains("azure") {
+        return "azure".to_string();
+    }
+    if name_lower.contains("openai") {
+        return "openai".to_string();
+    }
+    if name_lower.contains("deepseek") {
+        return "deepseek".to_string();
+    }
+    if name_lower.contains("grok") {
+        return "grok".to_string();
+    }
+    if name_lower.contains("gemini") {
+        return "gemini".to_string();
+    }
+    if name_lower.contains("ollama") {
+        return "ollama".to_string();
+    }
+
+    // Default to custom (which will use OpenAI-compatible API; so does Ollama)
+    "custom".to_string()
+}
+
+// Whether requests can be sent to a provider: it has an API key, or is a
+// local Ollama server, which needs none
+pub fn is_reachable(provider: &AIProvider) -> bool {
+    provider.api_key.is_some() || determine_provider_type(&provider.id, &provider.api_url, &provider.name) == "ollama"
+}
+
+// Messages to send through the Responses API. When continuing from a stored
+// response, the provider already has the earlier turns, so only system messages
+// (instructions are not carried over) and the turns after the last assistant
+// message are sent.
+pub fn responses_input(messages: Vec<ai::ChatMessage>, continuing: bool) -> Vec<ai::ChatMessage> {
+    if !continuing {
+        return messages;
+    }
+    
+    let last_assistant = messages.iter().rposition(|m| m.role == "assistant");
+    messages.into_iter()
+        .enumerate()
+        .filter(|(i, m)| m.role == "system" || last_assistant.is_none_or(|last| *i > last))
+        .map(|(_, m)| m)
+        .collect()
+}
+
+// A model name resolved to the provider that serves it
+pub struct ResolvedModel {
+    pub provider: AIProvider,
+    // Id of the configured model, if the name matched one
+    pub model_id: Option<String>,
+    pub model_name: String,
+    pub api_type: ApiType,
+}
+
+// Find the provider serving a model. Plain names match configured models,
+// favorites first; `<provider>/<model>` addresses a provider by id or name
+// directly, for models that have not been added to the model list. Providers
+// that cannot be sent to (see `is_reachable`) are skipped.
+pub fn resolve_model(conn: &Connection, requested: &str) -> rusqlite::Result<Option<ResolvedModel>> {
+    for model in db::find_models_by_name(conn, requested)? {
+        if let Some(provider) = db::get_provider_by_id(conn, &model.provider_id)? {
+            if is_reachable(&provider) {
+                return Ok(Some(ResolvedModel {
+                    provider,
+                    api_type: ApiType::parse(&model.api_type).unwrap_or_default(),
+                    model_id: Some(model.id),
+                    model_name: model.name,
+                }));
+            }
+        }
+    }
+
+    if let Some((prefix, name)) = requested.split_once('/') {
+        let provider = db::get_all_providers(conn)?
+            .into_iter()
+            .find(|p| is_reachable(p) && (p.id == prefix || p.name.eq_ignore_ascii_case(prefix)));
+        if let Some(provider) = provider {
+            let model = db::get_models_by_provider(conn, &provider.id)?
+                .into_iter()
+                .find(|m| m.name == name);
+            return Ok(Some(ResolvedModel {
+                provider,
+                api_type: model.as_ref().and_then(|m| ApiType::parse(&m.api_type)).unwrap_or_default(),
+                model_id: model.map(|m| m.id),
+                model_name: name.to_string(),
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+// What to send in a single chat request
+pub struct ChatParams<'a> {
+    pub model_name: &'a str,
+    pub api_type: ApiType,
+    pub messages: Vec<ChatMessage>,
+    // Response to continue from, for models using the Responses API
+    pub previous_response_id: Option<String>,
+    // Stream chat completions to `on_delta` (Responses API models always stream)
+    pub stream: bool,
+    // Functions offered to OpenAI-compatible chat completions models, and the
+    // handler answering their calls. Other APIs are sent without tools.
+    pub tools: Vec<ToolDefinition>,
+    pub on_tool_call: Option<&'a mut (dyn FnMut(&ToolCall) -> String + Send)>,
+}
+
+// Send a chat request to a provider using the API matching its type.
+// `client` must already be configured for the provider.
+pub async fn dispatch_chat(
+    client: &AIClient,
+    provider: &AIProvider,
+    azure_settings: &AzureSettings,
+    params: ChatParams<'_>,
+    on_delta: &mut (dyn FnMut(StreamDelta) + Send),
+) -> Result<AIResponse, AIError> {
+    let api_url = provider.api_url.as_str();
+    let ChatParams { model_name, api_type, messages, previous_response_id, stream, tools, on_tool_call } = params;
+    
+    // Determine provider type using the same logic as fetch_models_from_provider
+    let provider_type = determine_provider_type(&provider.id, api_url, &provider.name);
+    // Ollama runs locally and needs no API key
+    let api_key = match provider.api_key.as_deref() {
+        Some(key) => key,
+        None if provider_type == "ollama" => "",
+        None => return Err(AIError::Provider(ai::ProviderError::missing_api_key())),
+    };
+
+    // Models configured for the Responses API (OpenAI-compatible providers only)
+    if api_type == ApiType::Responses && !matches!(provider_type.as_str(), "gemini" | "azure") {
+        let messages = responses_input(messages, previous_response_id.is_some());
+        return client
+            .openai_responses(api_url, api_key, model_name, messages, previous_response_id, on_delta)
+            .await;
+    }
+
+    // Call appropriate API based on provider type
+    match provider_type.as_str() {
+        "azure" => {
+            client.azure_chat(api_url, api_key, azure_settings, model_name, messages).await
+        },
+        "gemini" => {
+            client.gemini_chat(api_url, api_key, model_name, messages).await
+        },
+        // OpenAI, DeepSeek, Grok and custom providers use the OpenAI-compatible API
+        _ => match on_tool_call.filter(|_| !tools.is_empty()) {
+            Some(on_tool_call) => {
+                client.openai_chat_with_tools(api_url, api_key, model_name, messages, &tools, on_tool_call).await
+            },
+            None if stream => {
+                client.openai_chat_stream(api_url, api_key, model_name, messages, on_delta).await
+            },
+            None => {
+                client.openai_chat(api_url, api_key, model_name, messages).await
+            }
+        }
+    }
+}
+
+// List a provider's models, with whatever metadata its listing offers, using
+// the API matching its type. `client` must already be configured for the provider.
+pub async fn list_models(
+    client: &AIClient,
+    provider: &AIProvider,
+    azure_settings: &AzureSettings,
+) -> Result<Vec<ModelInfo>, AIError> {
+    let api_url = provider.api_url.as_str();
+    let provider_type = determine_provider_type(&provider.id, api_url, &provider.name);
+    // Ollama runs locally and needs no API key
+    if provider_type == "ollama" {
+        return client.fetch_ollama_models(api_url).await;
+    }
+    let api_key = provider.api_key.as_deref()
+        .ok_or_else(|| AIError::Provider(ai::ProviderError::missing_api_key()))?;
+
+    match provider_type.as_str() {
+        "azure" => {
+            // Azure serves models through deployments, which are addressed by name
+            let deployments = client.fetch_azure_deployments(api_url, api_key, azure_settings).await?;
+            Ok(deployments.into_iter().map(|d| ModelInfo::named(d.id)).collect())
+        },
+        "gemini" => client.fetch_gemini_models(api_url, api_key).await,
+        // OpenAI, DeepSeek, Grok and custom providers share the OpenAI-compatible API
+        _ => client.fetch_openai_models(api_url, api_key).await,
+    }
+}
+
+// Check that a model answers with a one-token chat request, using the API
+// matching the provider's type
+pub async fn ping_model(
+    client: &AIClient,
+    provider: &AIProvider,
+    azure_settings: &AzureSettings,
+    model_name: &str,
+) -> Result<(), AIError> {
+    let api_url = provider.api_url.as_str();
+    let provider_type = determine_provider_type(&provider.id, api_url, &provider.name);
+    let api_key = match provider.api_key.as_deref() {
+        Some(key) => key,
+        None if provider_type == "ollama" => "",
+        None => return Err(AIError::Provider(ai::ProviderError::missing_api_key())),
+    };
+
+    match provider_type.as_str() {
+        "azure" => client.ping_azure_model(api_url, api_key, azure_settings, model_name).await,
+        "gemini" => client.ping_gemini_model(api_url, api_key, model_name).await,
+        _ => client.ping_openai_model(api_url, api_key, model_name).await,
+    }
+}
+
+// Fallback models of a chat request, in the order they are tried: the
+// session's chain, or the assistant's when the session has none. The requested
+// model, repeats, and models that cannot be sent to (removed, no longer listed,
+// or on a provider without an API key) are left out.
+pub fn fallback_models(
+    conn: &Connection,
+    model_id: &str,
+    session_id: Option<&str>,
+    assistant_id: Option<&str>,
+) -> rusqlite::Result<Vec<db::AIModel>> {
+    let mut chain = match session_id {
+        Some(session_id) => db::get_fallback_chain(conn, "session", session_id)?,
+        None => Vec::new(),
+    };
+    if let (true, Some(assistant_id)) = (chain.is_empty(), assistant_id) {
+        chain = db::get_fallback_chain(conn, "assistant", assistant_id)?;
+    }
+    
+    let mut seen = HashSet::from([model_id.to_string()]);
+    let mut models = Vec::new();
+    for id in chain {
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+        let Some(model) = db::get_model_by_id(conn, &id)?.filter(|m| m.is_available) else {
+            continue;
+        };
+        if db::get_provider_by_id(conn, &model.provider_id)?.is_some_and(|p| is_reachable(&p)) {
+            models.push(model);
+        }
+    }
+    Ok(models)
+}
+
+// The first user message and assistant reply of a conversation, or None
+// before the first exchange is complete
+pub fn first_exchange(messages: &[db::ChatMessage]) -> Option<(&str, &str)> {
+    let user = messages.iter().find(|m| m.role == "user")?;
+    let assistant = messages.iter().find(|m| m.role == "assistant")?;
+    Some((&user.content, &assistant.content))
+}
+
+// Messages asking a model for a short title for a conversation
+pub fn title_request(user: &str, assistant: &str) -> Vec<ChatMessage> {
+    let excerpt = |text: &str| text.chars().take(TITLE_EXCERPT_CHARS).collect::<String>();
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: "Write a short title (at most six words) for the conversation below. \
+                Use the language of the conversation. Reply with the title only, \
+                without quotes or trailing punctuation.".to_string(),
+        },
+        ChatMessage {
+            role: "user".to_string(),
+            content: format!("User: {}\n\nAssistant: {}", excerpt(user), excerpt(assistant)),
+        },
+    ]
+}
+
+// Turn a model reply into a session title: the first non-empty line, without
+// a "Title:" label, surrounding quotes or trailing punctuation
+pub fn clean_title(reply: &str) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.trim_start_matches('#').trim();
+    let line = match line.split_once([':', '：']) {
+        Some((label, rest)) if label.trim().eq_ignore_ascii_case("title") || label.trim() == "标题" => rest.trim(),
+        _ => line,
+    };
+    let quotes = ['"', '\'', '`', '*', '“', '”', '‘', '’', '「', '」', '《', '》'];
+    let punctuation = ['.', '。', '!', '！', ':', '：'];
+    let title = line
+        .trim_start_matches(quotes)
+        .trim_end_matches(|c| quotes.contains(&c) || punctuation.contains(&c))
+        .trim();
+
+    if title.is_empty() {
+        return None;
+    }
+    Some(title.chars().take(MAX_TITLE_CHARS).collect::<String>().trim_end().to_string())
+}
+
+// When older messages of a session are condensed into its summary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummaryPolicy {
+    pub enabled: bool,
+    // Summarize once this many messages are not covered by the summary
+    pub trigger_messages: usize,
+    // Number of most recent messages that are always sent in full
+    pub keep_recent: usize,
+    // Model that writes summaries; sessions fall back to their own model
+    pub model_id: Option<String>,
+}
+
+impl Default for SummaryPolicy {
+    fn default() -> Self {
+        SummaryPolicy {
+            enabled: true,
+            trigger_messages: 40,
+            keep_recent: 10,
+            model_id: None,
+        }
+    }
+}
+
+// Load the saved summary policy, falling back to defaults if it is missing or invalid
+pub fn load_summary_policy(conn: &Connection) -> rusqlite::Result<SummaryPolicy> {
+    Ok(db::get_setting(conn, SUMMARY_POLICY_SETTING)?
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default())
+}
+
+// Messages of a history of `len` messages to condense next, given that the
+// first `covered` are already summarized. Regenerating (`force`) starts over
+// from the first message and ignores the trigger. The most recent
+// `keep_recent` messages are never summarized.
+pub fn summary_range(len: usize, covered: usize, policy: &SummaryPolicy, force: bool) -> Option<Range<usize>> {
+    let start = if force { 0 } else { covered };
+    let end = len.saturating_sub(policy.keep_recent);
+    if start >= end || (!force && len - covered < policy.trigger_messages) {
+        return None;
+    }
+    Some(start..end)
+}
+
+// Messages asking a model to fold `messages` into the existing summary
+pub fn summary_request(previous: Option<&str>, messages: &[db::ChatMessage]) -> Vec<ChatMessage> {
+    let transcript = messages.iter()
+        .map(|m| {
+            let speaker = match m.role.as_str() {
+                "user" => "User",
+                "assistant" => "Assistant",
+                _ => "System",
+            };
+            format!("{}: {}", speaker, m.content.chars().take(SUMMARY_EXCERPT_CHARS).collect::<String>())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let content = match previous {
+        Some(summary) => format!("Current summary:\n{}\n\nNew messages:\n{}", summary, transcript),
+        None => format!("Messages:\n{}", transcript),
+    };
+
+    vec![
+        ChatMessage {
+            role: "system".to_string(),
+            content: "You keep a running summary of a conversation so it can continue \
+                without the full history. Combine the current summary, if any, with the \
+                new messages into one updated summary. Keep facts, decisions, names, \
+                preferences, code identifiers and open questions; drop small talk. \
+                Use the language of the conversation and reply with the summary only.".to_string(),
+        },
+        ChatMessage { role: "user".to_string(), content },
+    ]
+}
+
+// Replace the first `covered` conversation messages of a request with the
+// summary, placed after the leading system messages. Requests that do not
+// extend past the summarized history are returned unchanged.
+pub fn apply_summary(messages: Vec<ChatMessage>, summary: &str, covered: usize) -> Vec<ChatMessage> {
+    if messages.iter().filter(|m| m.role != "system").count() <= covered {
+        return messages;
+    }
+
+    let leading = messages.iter().take_while(|m| m.role == "system").count();
+    let mut skipped = 0;
+    let mut result = Vec::with_capacity(messages.len() + 1 - covered);
+    for (i, message) in messages.into_iter().enumerate() {
+        if i == leading {
+            result.push(ChatMessage {
+                role: "system".to_string(),
+                content: format!("Summary of the earlier conversation:\n{}", summary),
+            });
+        }
+        if message.role != "system" && skipped < covered {
+            skipped += 1;
+            continue;
+        }
+        result.push(message);
+    }
+    result
+}
+
+// Apply a session's stored summary to a request built from its history
+pub fn with_session_summary(conn: &Connection, session_id: &str, messages: Vec<ChatMessage>) -> rusqlite::Result<Vec<ChatMessage>> {
+    let Some(summary) = db::get_session_summary(conn, session_id)? else {
+        return Ok(messages);
+    };
+
+    // Ignore summaries whose messages were removed from the session
+    let history = db::get_messages_by_session(conn, session_id)?;
+    Ok(match history.iter().position(|m| m.id == summary.last_message_id) {
+        Some(last) => {
+            let covered = history[..=last].iter().filter(|m| m.role != "system").count();
+            apply_summary(messages, &summary.content, covered)
+        },
+        None => messages,
+    })
+}
+
+// A session's conversation as stored: its system prompt and messages
+pub fn session_history(conn: &Connection, session: &db::ChatSession) -> rusqlite::Result<Vec<ChatMessage>> {
+    let system_prompt = session.system_prompt.clone()
+        .filter(|prompt| !prompt.trim().is_empty())
+        .map(|prompt| ChatMessage { role: "system".to_string(), content: prompt });
+    Ok(system_prompt.into_iter()
+        .chain(db::get_messages_by_session(conn, &session.id)?
+            .into_iter()
+            .map(|m| ChatMessage { role: m.role, content: m.content }))
+        .collect())
+}
+
+// The messages to send for a request in a session, with the session's summary
+// applied. The conversation is rebuilt from the stored history, since the
+// client's list may hold entries that were never saved, such as error notices.
+// The request's system messages replace the session's system prompt, and its
+// last message is added when it is a user turn that was not saved yet.
+pub fn session_request(conn: &Connection, session_id: &str, request: Vec<ChatMessage>) -> rusqlite::Result<Vec<ChatMessage>> {
+    let Some(session) = db::get_chat_session_by_id(conn, session_id)? else {
+        return Ok(request);
+    };
+
+    let mut messages: Vec<ChatMessage> = request.iter().take_while(|m| m.role == "system").cloned().collect();
+    let history = session_history(conn, &session)?;
+    let stored = history.iter().skip_while(|m| m.role == "system");
+    if messages.is_empty() {
+        messages.extend(history.iter().take_while(|m| m.role == "system").cloned());
+    }
+    messages.extend(stored.cloned());
+
+    if let Some(last) = request.last().filter(|m| m.role == "user") {
+        let saved = messages.last().is_some_and(|m| m.role == last.role && m.content == last.content);
+        if !saved {
+            messages.push(last.clone());
+        }
+    }
+    with_session_summary(conn, session_id, messages)
+}
+
+// Lowercase words of at least three characters, for matching memories to a message
+fn keywords(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// Pick the memories most relevant to `query`: those sharing the most words
+// with it, ties keeping their order (most recently updated first)
+pub fn select_memories(memories: Vec<db::Memory>, query: &str, limit: usize) -> Vec<db::Memory> {
+    let query = keywords(query);
+    let mut scored: Vec<(usize, db::Memory)> = memories.into_iter()
+        .map(|memory| (keywords(&memory.content).intersection(&query).count(), memory))
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().take(limit).map(|(_, memory)| memory).collect()
+}
+
+// Add memories to the system prompt of a request, creating one if needed
+pub fn inject_memories(messages: Vec<ChatMessage>, memories: &[db::Memory]) -> Vec<ChatMessage> {
+    if memories.is_empty() {
+        return messages;
+    }
+
+    let list = memories.iter().map(|m| format!("- {}", m.content)).collect::<Vec<_>>().join("\n");
+    append_to_system_prompt(messages, &format!("Things you remember from earlier conversations:\n{}", list))
+}
+
+// Append a section to the leading system message of a request, creating one if needed
+pub fn append_to_system_prompt(mut messages: Vec<ChatMessage>, section: &str) -> Vec<ChatMessage> {
+    match messages.first_mut().filter(|m| m.role == "system") {
+        Some(system) => system.content = format!("{}\n\n{}", system.content, section),
+        None => messages.insert(0, ChatMessage { role: "system".to_string(), content: section.to_string() }),
+    }
+    messages
+}
+
+// Tool letting the model save a memory for later conversations
+pub fn save_memory_tool() -> ToolDefinition {
+    ToolDefinition {
+        name: SAVE_MEMORY_TOOL.to_string(),
+        description: "Remember a lasting fact about the user or their projects for future conversations. \
+            Only save information the user would expect you to remember.".to_string(),
+        parameters: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "content": {
+                    "type": "string",
+                    "description": "The fact to remember, as a short self-contained sentence"
+                },
+                "scope": {
+                    "type": "string",
+                    "enum": ["global", "assistant", "folder"],
+                    "description": "Where the memory applies: everywhere, to this assistant, or to this folder of conversations"
+                }
+            },
+            "required": ["content"]
+        }),
+    }
+}
+
+// Arguments of a save_memory call
+#[derive(Debug, Deserialize)]
+pub struct SaveMemoryArgs {
+    pub content: String,
+    #[serde(default)]
+    pub scope: Option<db::MemoryScope>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage { role: role.to_string(), content: content.to_string() }
+    }
+
+    #[test]
+    fn test_determine_provider_type() {
+        assert_eq!(determine_provider_type("openai", "https://api.openai.com", "OpenAI"), "openai");
+        assert_eq!(determine_provider_type("3f2a", "https://my-res.openai.azure.com", "Work"), "azure");
+        assert_eq!(determine_provider_type("3f2a", "https://example.com", "My Gemini proxy"), "gemini");
+        assert_eq!(determine_provider_type("3f2a", "http://localhost:8080", "Local"), "custom");
+        assert_eq!(determine_provider_type("3f2a", "http://localhost:11434/v1", "Local"), "ollama");
+    }
+
+    #[test]
+    fn test_resolve_model_by_name_and_provider_prefix() {
+        let conn = db::create_test_db().unwrap();
+        db::add_provider_with_id(&conn, "openai", "OpenAI", "https://api.openai.com", "openai_api_key", "sk-test").unwrap();
+        db::add_provider_with_id(&conn, "keyless", "Keyless", "https://api.keyless.com", "keyless_api_key", "").unwrap();
+        conn.execute("UPDATE ai_providers SET api_key = NULL WHERE id = 'keyless'", []).unwrap();
+        db::add_model(&conn, "keyless", "gpt-4o").unwrap();
+        let model_id = db::add_model(&conn, "openai", "gpt-4o").unwrap();
+        db::add_provider_with_id(&conn, "ollama", "Ollama", "http://localhost:11434", "ollama_api_key", "").unwrap();
+        conn.execute("UPDATE ai_providers SET api_key = NULL WHERE id = 'ollama'", []).unwrap();
+        let local_id = db::add_model(&conn, "ollama", "llama3.2").unwrap();
+
+        // Providers without a key are skipped, except local Ollama servers
+        let resolved = resolve_model(&conn, "gpt-4o").unwrap().unwrap();
+        assert_eq!(resolved.provider.id, "openai");
+        assert_eq!(resolved.model_id.as_deref(), Some(model_id.as_str()));
+
+        // Models not in the list can be addressed through their provider
+        let resolved = resolve_model(&conn, "OpenAI/o4-mini").unwrap().unwrap();
+        assert_eq!(resolved.provider.id, "openai");
+        assert_eq!(resolved.model_name, "o4-mini");
+        assert!(resolved.model_id.is_none());
+
+        assert!(resolve_model(&conn, "keyless/gpt-4o").unwrap().is_none());
+        let resolved = resolve_model(&conn, "llama3.2").unwrap().unwrap();
+        assert_eq!(resolved.model_id.as_deref(), Some(local_id.as_str()));
+        assert_eq!(resolve_model(&conn, "ollama/qwen3").unwrap().unwrap().provider.id, "ollama");
+        assert!(resolve_model(&conn, "missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_fallback_models_follow_session_then_assistant_chain() {
+        let conn = db::create_test_db().unwrap();
+        db::add_provider_with_id(&conn, "deepseek", "DeepSeek", "https://api.deepseek.com", "deepseek_api_key", "sk-test").unwrap();
+        db::add_provider_with_id(&conn, "ollama", "Ollama", "http://localhost:11434", "ollama_api_key", "").unwrap();
+        db::add_provider_with_id(&conn, "keyless", "Keyless", "https://api.keyless.com", "keyless_api_key", "").unwrap();
+        conn.execute("UPDATE ai_providers SET api_key = NULL WHERE id IN ('ollama', 'keyless')", []).unwrap();
+        let primary = db::add_model(&conn, "deepseek", "deepseek-chat").unwrap();
+        let local = db::add_model(&conn, "ollama", "llama3.2").unwrap();
+        let unreachable = db::add_model(&conn, "keyless", "gpt-4o").unwrap();
+
+        let chain = vec![primary.clone(), unreachable, "missing".to_string(), local.clone(), local.clone()];
+        db::set_fallback_chain(&conn, "assistant", "writer", &chain).unwrap();
+        let ids = |models: Vec<db::AIModel>| models.into_iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(ids(fallback_models(&conn, &primary, Some("s1"), Some("writer")).unwrap()), vec![local.clone()]);
+
+        // A session's own chain replaces the assistant's
+        db::set_fallback_chain(&conn, "session", "s1", &chain[..1]).unwrap();
+        assert!(fallback_models(&conn, &primary, Some("s1"), Some("writer")).unwrap().is_empty());
+        assert!(fallback_models(&conn, &primary, None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_responses_input_keeps_system_and_new_turns() {
+        let messages = vec![
+            message("system", "Be brief"),
+            message("user", "Hi"),
+            message("assistant", "Hello"),
+            message("user", "How are you?"),
+        ];
+
+        assert_eq!(responses_input(messages.clone(), false).len(), 4);
+
+        let continued = responses_input(messages, true);
+        let roles: Vec<&str> = continued.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["system", "user"]);
+        assert_eq!(continued[1].content, "How are you?");
+    }
+
+    #[test]
+    fn test_clean_title() {
+        assert_eq!(clean_title("\n  \"Rust Lifetimes Explained.\"\nExtra").as_deref(), Some("Rust Lifetimes Explained"));
+        assert_eq!(clean_title("Title: Borrow checker basics").as_deref(), Some("Borrow checker basics"));
+        assert_eq!(clean_title("标题：周末计划").as_deref(), Some("周末计划"));
+        assert_eq!(clean_title("「数据库迁移方案」。").as_deref(), Some("数据库迁移方案"));
+        assert_eq!(clean_title("**Weekend plans**").as_deref(), Some("Weekend plans"));
+        assert!(clean_title(" \n\"\"").is_none());
+        assert_eq!(clean_title(&"a".repeat(100)).unwrap().chars().count(), MAX_TITLE_CHARS);
+    }
+
+    #[test]
+    fn test_summary_range() {
+        let policy = SummaryPolicy { trigger_messages: 6, keep_recent: 2, ..SummaryPolicy::default() };
+
+        assert_eq!(summary_range(5, 0, &policy, false), None);
+        assert_eq!(summary_range(6, 0, &policy, false), Some(0..4));
+        // Only messages after the current summary are folded in
+        assert_eq!(summary_range(9, 4, &policy, false), None);
+        assert_eq!(summary_range(10, 4, &policy, false), Some(4..8));
+        // Regenerating starts over regardless of the trigger
+        assert_eq!(summary_range(5, 4, &policy, true), Some(0..3));
+        assert_eq!(summary_range(2, 0, &policy, true), None);
+    }
+
+    #[test]
+    fn test_apply_summary_replaces_covered_turns() {
+        let messages = vec![
+            message("system", "Be brief"),
+            message("user", "Hi"),
+            message("assistant", "Hello"),
+            message("user", "Plan a trip"),
+            message("assistant", "Where to?"),
+            message("user", "Lisbon"),
+        ];
+
+        let applied = apply_summary(messages.clone(), "Greetings, trip planning", 2);
+        let contents: Vec<&str> = applied.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec![
+            "Be brief",
+            "Summary of the earlier conversation:\nGreetings, trip planning",
+            "Plan a trip",
+            "Where to?",
+            "Lisbon",
+        ]);
+
+        // Requests that end within the summarized history are left alone
+        assert_eq!(apply_summary(messages, "Everything", 5).len(), 6);
+    }
+
+    #[test]
+    fn test_session_request_uses_stored_history() {
+        let conn = db::create_test_db().unwrap();
+        let session = db::create_chat_session(&conn, "Trip", None, Some("Be brief")).unwrap();
+        let first = db::add_message(&conn, &session, "user", "Hi", None).unwrap();
+        let second = db::add_message(&conn, &session, "assistant", "Hello", None).unwrap();
+        db::add_message(&conn, &session, "user", "Plan a trip", None).unwrap();
+        db::save_session_summary(&conn, &session, "Greetings", &first, &second, 2).unwrap();
+
+        // The client kept an unsaved error reply from a failed send
+        let request = vec![
+            message("user", "Hi"),
+            message("assistant", "Hello"),
+            message("assistant", "Error: rate limited"),
+            message("user", "Plan a trip"),
+        ];
+        let sent = session_request(&conn, &session, request.clone()).unwrap();
+        let contents: Vec<&str> = sent.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["Be brief", "Summary of the earlier conversation:\nGreetings", "Plan a trip"]);
+
+        // A user turn that was not saved yet is added
+        let mut unsaved = request;
+        unsaved.push(message("user", "Lisbon"));
+        let sent = session_request(&conn, &session, unsaved).unwrap();
+        assert_eq!(sent.last().unwrap().content, "Lisbon");
+        assert_eq!(sent.len(), 4);
+    }
+
+    fn memory(content: &str) -> db::Memory {
+        db::Memory {
+            id: content.to_string(),
+            content: content.to_string(),
+            scope: db::MemoryScope::Global,
+            scope_id: None,
+            source_message_id: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_select_memories_prefers_shared_words() {
+        let memories = vec![
+            memory("Prefers metric units"),
+            memory("The billing service runs on Postgres 15"),
+            memory("Deploys happen on Fridays"),
+        ];
+
+        let selected = select_memories(memories.clone(), "Why is the Postgres query in billing slow?", 2);
+        assert_eq!(selected[0].content, "The billing service runs on Postgres 15");
+        assert_eq!(selected[1].content, "Prefers metric units");
+        assert_eq!(select_memories(memories, "", 10).len(), 3);
+    }
+
+    #[test]
+    fn test_inject_memories_extends_system_prompt() {
+        let memories = vec![memory("Uses Postgres")];
+
+        let injected = inject_memories(vec![message("system", "Be brief"), message("user", "Hi")], &memories);
+        assert_eq!(injected.len(), 2);
+        assert_eq!(injected[0].content, "Be brief\n\nThings you remember from earlier conversations:\n- Uses Postgres");
+
+        let injected = inject_memories(vec![message("user", "Hi")], &memories);
+        assert_eq!(injected[0].role, "system");
+        assert_eq!(injected.len(), 2);
+
+        let args: SaveMemoryArgs = serde_json::from_str(r#"{"content":"Uses Postgres","scope":"folder"}"#).unwrap();
+        assert_eq!(args.scope, Some(db::MemoryScope::Folder));
+    }
+}
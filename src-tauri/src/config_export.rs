@@ -0,0 +1,473 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::ai::{ApiType, AzureSettings, ConnectionSettings};
+use crate::{api_server_settings, app_lock, catalog, db, health};
+
+// Portable config file: providers with their models and favorites, and app
+// settings, so a setup can be copied to another machine. API keys are left
+// out unless a passphrase is given, in which case they are encrypted with it.
+
+// Current version of the config file format
+pub const CONFIG_EXPORT_VERSION: u32 = 1;
+
+// Settings that only make sense on the machine they were made on: the app
+// lock passphrase hash, the local API server's switch, port and access token,
+// and when the catalog sync and health checks last ran
+pub const LOCAL_SETTINGS: &[&str] = &[
+    app_lock::PASSPHRASE_HASH_SETTING,
+    api_server_settings::ENABLED_SETTING,
+    api_server_settings::PORT_SETTING,
+    api_server_settings::TOKEN_SETTING,
+    catalog::LAST_SYNCED_SETTING,
+    catalog::LAST_ERROR_SETTING,
+    health::LAST_RUN_SETTING,
+];
+
+#[derive(Debug)]
+pub enum ConfigExportError {
+    UnsupportedVersion(u32),
+    Parse(String),
+    // The file has encrypted API keys and the passphrase is missing or wrong
+    Passphrase(String),
+    Database(rusqlite::Error),
+}
+
+impl fmt::Display for ConfigExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigExportError::UnsupportedVersion(v) => write!(f, "Unsupported config file version: {}", v),
+            ConfigExportError::Parse(e) => write!(f, "Invalid config file: {}", e),
+            ConfigExportError::Passphrase(e) => write!(f, "{}", e),
+            ConfigExportError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl Error for ConfigExportError {}
+
+impl From<rusqlite::Error> for ConfigExportError {
+    fn from(error: rusqlite::Error) -> Self {
+        ConfigExportError::Database(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    Toml,
+}
+
+// What to do with a provider, model or setting whose id already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    // Keep the existing one
+    #[default]
+    Skip,
+    // Replace it with the imported one
+    Overwrite,
+    // Import under a new id; settings have no other key, so they are skipped
+    Rename,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigExport {
+    pub version: u32,
+    pub exported_at: i64,
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<EncryptedSecrets>,
+    #[serde(default)]
+    pub providers: Vec<ExportedProvider>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedProvider {
+    pub id: String,
+    pub name: String,
+    pub api_url: String,
+    pub api_key_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub models_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection: Option<ConnectionSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub azure: Option<AzureSettings>,
+    #[serde(default)]
+    pub models: Vec<ExportedModel>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedModel {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub is_favorite: bool,
+    #[serde(default)]
+    pub api_type: ApiType,
+}
+
+// API keys by provider id, encrypted with AES-256-GCM under a key derived
+// from the passphrase with Argon2
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedSecrets {
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ImportSummary {
+    pub providers_added: usize,
+    pub providers_updated: usize,
+    pub providers_renamed: usize,
+    pub providers_skipped: usize,
+    pub models_imported: usize,
+    pub models_skipped: usize,
+    pub settings_imported: usize,
+    pub settings_skipped: usize,
+    pub api_keys_imported: usize,
+}
+
+// Collect the config of the database, with API keys when a passphrase is given
+pub fn export_config(conn: &Connection, passphrase: Option<&str>) -> Result<ConfigExport, ConfigExportError> {
+    let mut providers = Vec::new();
+    let mut api_keys = BTreeMap::new();
+    for provider in db::get_all_providers(conn)? {
+        let connection = db::get_connection_settings(conn, &provider.id)?;
+        let azure = db::get_azure_settings(conn, &provider.id)?;
+        let models = db::get_models_by_provider(conn, &provider.id)?
+            .into_iter()
+            .map(|m| ExportedModel {
+                id: m.id,
+                name: m.name,
+                is_favorite: m.is_favorite,
+                api_type: ApiType::parse(&m.api_type).unwrap_or_default(),
+            })
+            .collect();
+        if let Some(key) = provider.api_key.filter(|k| !k.is_empty()) {
+            api_keys.insert(provider.id.clone(), key);
+        }
+
+        providers.push(ExportedProvider {
+            id: provider.id,
+            name: provider.name,
+            api_url: provider.api_url,
+            api_key_name: provider.api_key_name,
+            chat_path: provider.chat_path,
+            models_path: provider.models_path,
+            connection: (connection != ConnectionSettings::default()).then_some(connection),
+            azure: (azure != AzureSettings::default()).then_some(azure),
+            models,
+        });
+    }
+
+    let settings = db::get_all_settings(conn)?
+        .into_iter()
+        .filter(|(key, _)| !LOCAL_SETTINGS.contains(&key.as_str()))
+        .collect();
+    let secrets = match passphrase {
+        Some(passphrase) => Some(encrypt_secrets(&api_keys, passphrase)?),
+        None => None,
+    };
+
+    Ok(ConfigExport {
+        version: CONFIG_EXPORT_VERSION,
+        exported_at: db::get_current_timestamp(),
+        settings,
+        secrets,
+        providers,
+    })
+}
+
+pub fn serialize_config(export: &ConfigExport, format: ConfigFormat) -> Result<String, ConfigExportError> {
+    match format {
+        ConfigFormat::Json => serde_json::to_string_pretty(export).map_err(|e| ConfigExportError::Parse(e.to_string())),
+        ConfigFormat::Toml => toml::to_string_pretty(export).map_err(|e| ConfigExportError::Parse(e.to_string())),
+    }
+}
+
+// Parse a config file, telling JSON from TOML by its first character
+pub fn parse_config(content: &str) -> Result<ConfigExport, ConfigExportError> {
+    let export: ConfigExport = if content.trim_start().starts_with('{') {
+        serde_json::from_str(content).map_err(|e| ConfigExportError::Parse(e.to_string()))?
+    } else {
+        toml::from_str(content).map_err(|e| ConfigExportError::Parse(e.to_string()))?
+    };
+    if export.version > CONFIG_EXPORT_VERSION {
+        return Err(ConfigExportError::UnsupportedVersion(export.version));
+    }
+    Ok(export)
+}
+
+// Import a config in one transaction. Encrypted API keys are only imported
+// when the passphrase is given; without it the rest is still imported.
+// Providers and models managed by the team catalog are read-only, so they are
+// skipped rather than overwritten.
+pub fn import_config(
+    conn: &mut Connection,
+    export: &ConfigExport,
+    passphrase: Option<&str>,
+    policy: ConflictPolicy,
+) -> Result<ImportSummary, ConfigExportError> {
+    let api_keys = match (&export.secrets, passphrase) {
+        (Some(secrets), Some(passphrase)) => decrypt_secrets(secrets, passphrase)?,
+        _ => BTreeMap::new(),
+    };
+
+    let tx = conn.transaction()?;
+    let mut summary = ImportSummary::default();
+    for provider in &export.providers {
+        let existing = db::get_provider_by_id(&tx, &provider.id)?;
+        let managed = existing.as_ref().is_some_and(|p| p.is_managed);
+        let (id, name) = match (existing.is_some(), policy) {
+            (true, ConflictPolicy::Skip) => {
+                summary.providers_skipped += 1;
+                continue;
+            }
+            (true, ConflictPolicy::Overwrite) if managed => {
+                summary.providers_skipped += 1;
+                continue;
+            }
+            (true, ConflictPolicy::Rename) => {
+                summary.providers_renamed += 1;
+                (unused_provider_id(&tx, &provider.id)?, format!("{} (imported)", provider.name))
+            }
+            (true, ConflictPolicy::Overwrite) => {
+                summary.providers_updated += 1;
+                (provider.id.clone(), provider.name.clone())
+            }
+            (false, _) => {
+                summary.providers_added += 1;
+                (provider.id.clone(), provider.name.clone())
+            }
+        };
+
+        let api_key = api_keys.get(&provider.id).map(String::as_str);
+        if api_key.is_some() {
+            summary.api_keys_imported += 1;
+        }
+        db::upsert_provider(&tx, &id, &name, &provider.api_url, &provider.api_key_name, api_key)?;
+        db::set_provider_endpoints(&tx, &id, provider.chat_path.as_deref(), provider.models_path.as_deref())?;
+        db::set_connection_settings(&tx, &id, &provider.connection.clone().unwrap_or_default())?;
+        if let Some(azure) = &provider.azure {
+            db::set_azure_settings(&tx, &id, azure)?;
+        }
+
+        // Models of a renamed provider are copies, so they get new ids too
+        let renamed = id != provider.id;
+        for model in &provider.models {
+            let existing_model = db::get_model_by_id(&tx, &model.id)?;
+            let model_managed = existing_model.as_ref().is_some_and(|m| m.is_managed);
+            let model_id = match (renamed, existing_model.is_some(), policy) {
+                (false, true, _) if model_managed => {
+                    summary.models_skipped += 1;
+                    continue;
+                }
+                (false, false, _) | (false, true, ConflictPolicy::Overwrite) => model.id.clone(),
+                (false, true, ConflictPolicy::Skip) => {
+                    summary.models_skipped += 1;
+                    continue;
+                }
+                _ => uuid::Uuid::new_v4().to_string(),
+            };
+            db::upsert_model(&tx, &model_id, &id, &model.name, model.is_favorite, model.api_type)?;
+            summary.models_imported += 1;
+        }
+    }
+
+    for (key, value) in &export.settings {
+        if LOCAL_SETTINGS.contains(&key.as_str()) {
+            continue;
+        }
+        let exists = db::get_setting(&tx, key)?.is_some();
+        if exists && policy != ConflictPolicy::Overwrite {
+            summary.settings_skipped += 1;
+            continue;
+        }
+        db::set_setting(&tx, key, value)?;
+        summary.settings_imported += 1;
+    }
+
+    tx.commit()?;
+    Ok(summary)
+}
+
+// First of `<id>-imported`, `<id>-imported-2`, ... not taken by a provider
+fn unused_provider_id(conn: &Connection, id: &str) -> rusqlite::Result<String> {
+    let mut candidate = format!("{}-imported", id);
+    let mut n = 2;
+    while db::get_provider_by_id(conn, &candidate)?.is_some() {
+        candidate = format!("{}-imported-{}", id, n);
+        n += 1;
+    }
+    Ok(candidate)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], ConfigExportError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| ConfigExportError::Passphrase(e.to_string()))?;
+    Ok(key)
+}
+
+fn encrypt_secrets(api_keys: &BTreeMap<String, String>, passphrase: &str) -> Result<EncryptedSecrets, ConfigExportError> {
+    if passphrase.is_empty() {
+        return Err(ConfigExportError::Passphrase("Passphrase cannot be empty".to_string()));
+    }
+    let salt: [u8; 16] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let key = derive_key(passphrase, &salt)?;
+    let plaintext = serde_json::to_vec(api_keys).map_err(|e| ConfigExportError::Parse(e.to_string()))?;
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| ConfigExportError::Passphrase("Could not encrypt API keys".to_string()))?;
+
+    Ok(EncryptedSecrets {
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn decrypt_secrets(secrets: &EncryptedSecrets, passphrase: &str) -> Result<BTreeMap<String, String>, ConfigExportError> {
+    let decode = |value: &str| BASE64.decode(value).map_err(|e| ConfigExportError::Parse(e.to_string()));
+    let (salt, nonce, ciphertext) = (decode(&secrets.salt)?, decode(&secrets.nonce)?, decode(&secrets.ciphertext)?);
+    if nonce.len() != 12 {
+        return Err(ConfigExportError::Parse("Invalid nonce".to_string()));
+    }
+
+    let key = derive_key(passphrase, &salt)?;
+    let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| ConfigExportError::Passphrase("Incorrect passphrase for the API keys".to_string()))?;
+    serde_json::from_slice(&plaintext).map_err(|e| ConfigExportError::Parse(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded_db() -> Connection {
+        let conn = db::create_test_db().unwrap();
+        db::upsert_provider(&conn, "openai", "OpenAI", "https://api.openai.com", "openai_api_key", Some("sk-secret")).unwrap();
+        db::upsert_model(&conn, "m1", "openai", "gpt-4o", true, ApiType::Responses).unwrap();
+        db::set_setting(&conn, "theme", "dark").unwrap();
+        for key in LOCAL_SETTINGS {
+            db::set_setting(&conn, key, "local").unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn test_export_round_trips_through_json_and_toml() {
+        let conn = seeded_db();
+        let export = export_config(&conn, None).unwrap();
+
+        assert!(export.secrets.is_none());
+        assert_eq!(export.providers[0].models[0].api_type, ApiType::Responses);
+        assert_eq!(export.settings.get("theme").map(String::as_str), Some("dark"));
+        for key in LOCAL_SETTINGS {
+            assert!(!export.settings.contains_key(*key), "{} was exported", key);
+        }
+        let json = serialize_config(&export, ConfigFormat::Json).unwrap();
+        assert!(!json.contains("sk-secret"));
+
+        assert_eq!(parse_config(&json).unwrap(), export);
+        let toml = serialize_config(&export, ConfigFormat::Toml).unwrap();
+        assert_eq!(parse_config(&toml).unwrap(), export);
+
+        let future = json.replacen("\"version\": 1", "\"version\": 99", 1);
+        assert!(matches!(parse_config(&future), Err(ConfigExportError::UnsupportedVersion(99))));
+
+        // Machine-local settings in a hand-edited file are not imported either
+        let mut with_local = export.clone();
+        for key in LOCAL_SETTINGS {
+            with_local.settings.insert(key.to_string(), "imported".to_string());
+        }
+        let mut target = db::create_test_db().unwrap();
+        import_config(&mut target, &with_local, None, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(db::get_setting(&target, "theme").unwrap().as_deref(), Some("dark"));
+        for key in LOCAL_SETTINGS {
+            assert_eq!(db::get_setting(&target, key).unwrap(), None, "{} was imported", key);
+        }
+    }
+
+    #[test]
+    fn test_import_resolves_conflicts() {
+        let export = export_config(&seeded_db(), Some("team pass")).unwrap();
+
+        // Wrong passphrase for the encrypted keys
+        let mut conn = db::create_test_db().unwrap();
+        assert!(matches!(
+            import_config(&mut conn, &export, Some("wrong"), ConflictPolicy::Skip),
+            Err(ConfigExportError::Passphrase(_))
+        ));
+
+        let summary = import_config(&mut conn, &export, Some("team pass"), ConflictPolicy::Skip).unwrap();
+        assert_eq!(summary.providers_added, 1);
+        assert_eq!(summary.api_keys_imported, 1);
+        let provider = db::get_provider_by_id(&conn, "openai").unwrap().unwrap();
+        assert_eq!(provider.api_key.as_deref(), Some("sk-secret"));
+        assert!(db::get_model_by_id(&conn, "m1").unwrap().unwrap().is_favorite);
+
+        // Skipping leaves existing rows alone
+        db::set_setting(&conn, "theme", "light").unwrap();
+        let summary = import_config(&mut conn, &export, None, ConflictPolicy::Skip).unwrap();
+        assert_eq!((summary.providers_skipped, summary.settings_skipped), (1, 1));
+        assert_eq!(db::get_setting(&conn, "theme").unwrap().as_deref(), Some("light"));
+
+        // Renaming copies the provider and its models under new ids
+        let summary = import_config(&mut conn, &export, None, ConflictPolicy::Rename).unwrap();
+        assert_eq!(summary.providers_renamed, 1);
+        let copy = db::get_provider_by_id(&conn, "openai-imported").unwrap().unwrap();
+        assert_eq!(copy.name, "OpenAI (imported)");
+        let copied_models = db::get_models_by_provider(&conn, "openai-imported").unwrap();
+        assert_eq!(copied_models[0].name, "gpt-4o");
+        assert_ne!(copied_models[0].id, "m1");
+
+        // Overwriting keeps the stored key when the file has none
+        db::toggle_model_favorite(&conn, "m1", false).unwrap();
+        let summary = import_config(&mut conn, &export, None, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!((summary.providers_updated, summary.settings_imported), (1, 1));
+        assert!(db::get_model_by_id(&conn, "m1").unwrap().unwrap().is_favorite);
+        assert_eq!(db::get_setting(&conn, "theme").unwrap().as_deref(), Some("dark"));
+        let provider = db::get_provider_by_id(&conn, "openai").unwrap().unwrap();
+        assert_eq!(provider.api_key.as_deref(), Some("sk-secret"));
+    }
+
+    #[test]
+    fn test_import_skips_catalog_managed_entries() {
+        let export = export_config(&seeded_db(), None).unwrap();
+        let mut conn = db::create_test_db().unwrap();
+        db::save_managed_provider(&conn, "openai", "Team OpenAI", "https://llm.example.com", "openai_api_key", None, None).unwrap();
+        db::upsert_model(&conn, "m1", "openai", "gpt-4o", false, ApiType::ChatCompletions).unwrap();
+        conn.execute("UPDATE ai_models SET is_managed = TRUE WHERE id = 'm1'", []).unwrap();
+
+        let summary = import_config(&mut conn, &export, None, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!((summary.providers_skipped, summary.providers_updated), (1, 0));
+        let provider = db::get_provider_by_id(&conn, "openai").unwrap().unwrap();
+        assert_eq!((provider.name.as_str(), provider.is_managed), ("Team OpenAI", true));
+        assert!(!db::get_model_by_id(&conn, "m1").unwrap().unwrap().is_favorite);
+
+        // A renamed copy is a regular provider with copies of the models
+        let summary = import_config(&mut conn, &export, None, ConflictPolicy::Rename).unwrap();
+        assert_eq!((summary.providers_renamed, summary.models_imported), (1, 1));
+        assert!(!db::get_provider_by_id(&conn, "openai-imported").unwrap().unwrap().is_managed);
+    }
+}
@@ -1,6 +1,9 @@
 use keyring::Entry;
 use std::error::Error;
 use std::fmt;
+use std::sync::RwLock;
+
+use crate::profiles;
 
 #[derive(Debug)]
 pub enum CredentialError {
@@ -25,9 +28,32 @@ impl From<keyring::Error> for CredentialError {
     }
 }
 
+// Keyring service of the default profile; other profiles get their own
+const SERVICE_NAME: &str = "com.aichatbox.app";
+
+// Profile whose credentials are used, None for the default one
+static PROFILE: RwLock<Option<String>> = RwLock::new(None);
+
+// Use the credentials of a profile from now on
+pub fn set_profile(profile: &str) {
+    let profile = (profile != profiles::DEFAULT_PROFILE).then(|| profile.to_string());
+    if let Ok(mut current) = PROFILE.write() {
+        *current = profile;
+    }
+}
+
+// Keyring service holding a profile's credentials
+fn service_name(profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!("{}.profile.{}", SERVICE_NAME, profile),
+        None => SERVICE_NAME.to_string(),
+    }
+}
+
 // Helper function to create a keyring entry for an API key
 fn create_entry(key_name: &str) -> Entry {
-    Entry::new("com.aichatbox.app", key_name).unwrap()
+    let profile = PROFILE.read().ok().and_then(|p| p.clone());
+    Entry::new(&service_name(profile.as_deref()), key_name).unwrap()
 }
 
 // Store an API key securely
@@ -52,4 +78,29 @@ pub fn delete_api_key(key_name: &str) -> Result<(), CredentialError> {
     // Try to delete the entry; ignore if it doesn't exist
     let _ = entry.delete_password();
     Ok(())
-} 
\ No newline at end of file
+}
+
+// Keyring entry holding the database encryption key
+const DATABASE_KEY_NAME: &str = "aichat-pro-database-key";
+
+// Store the key the database is encrypted with
+pub fn store_database_key(key: &str) -> Result<(), CredentialError> {
+    store_api_key(DATABASE_KEY_NAME, key)
+}
+
+// Retrieve the database key, if the database is encrypted with one from the keyring
+pub fn get_database_key() -> Result<String, CredentialError> {
+    get_api_key(DATABASE_KEY_NAME)
+}
+
+// Delete the database key
+pub fn delete_database_key() -> Result<(), CredentialError> {
+    delete_api_key(DATABASE_KEY_NAME)
+}
+
+// Delete the credentials of a profile that is being deleted
+pub fn delete_profile_credentials(profile: &str) -> Result<(), CredentialError> {
+    let entry = Entry::new(&service_name(Some(profile)), DATABASE_KEY_NAME)?;
+    let _ = entry.delete_password();
+    Ok(())
+}
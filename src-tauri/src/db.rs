@@ -1,10 +1,12 @@
 use rusqlite::{params, Connection, Result};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use uuid::Uuid;
-use std::path::Path;
+use crate::model_info::ModelMetadata;
+use std::path::{Path, PathBuf};
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-const SCHEMA_SQL: &str = r#"
+pub(crate) const SCHEMA_SQL: &str = r#"
 -- AI Providers Table
 CREATE TABLE IF NOT EXISTS ai_providers (
     id TEXT PRIMARY KEY,
@@ -12,30 +14,101 @@ CREATE TABLE IF NOT EXISTS ai_providers (
     api_url TEXT NOT NULL,
     api_key_name TEXT NOT NULL,
     api_key TEXT,
+    chat_path TEXT,
+    models_path TEXT,
+    is_managed BOOLEAN NOT NULL DEFAULT FALSE,
     created_at INTEGER NOT NULL,
     updated_at INTEGER NOT NULL
 );
 
+-- Provider Connection Settings Table
+CREATE TABLE IF NOT EXISTS provider_connection_settings (
+    provider_id TEXT PRIMARY KEY,
+    proxy_url TEXT,
+    connect_timeout_secs INTEGER,
+    read_timeout_secs INTEGER,
+    extra_headers TEXT NOT NULL DEFAULT '{}',
+    ca_cert_path TEXT,
+    accept_invalid_certs BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (provider_id) REFERENCES ai_providers(id)
+);
+
+-- Azure OpenAI Provider Settings Table
+CREATE TABLE IF NOT EXISTS azure_provider_settings (
+    provider_id TEXT PRIMARY KEY,
+    api_version TEXT NOT NULL,
+    auth_type TEXT NOT NULL DEFAULT 'api_key' CHECK(auth_type IN ('api_key', 'bearer_token')),
+    deployments TEXT NOT NULL DEFAULT '{}',
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (provider_id) REFERENCES ai_providers(id)
+);
+
 -- AI Models Table
 CREATE TABLE IF NOT EXISTS ai_models (
     id TEXT PRIMARY KEY,
     provider_id TEXT NOT NULL,
     name TEXT NOT NULL,
     is_favorite BOOLEAN DEFAULT FALSE,
+    api_type TEXT NOT NULL DEFAULT 'chat_completions',
+    is_managed BOOLEAN NOT NULL DEFAULT FALSE,
+    is_available BOOLEAN NOT NULL DEFAULT TRUE,
+    context_length INTEGER,
+    max_output_tokens INTEGER,
+    input_price REAL,
+    output_price REAL,
+    supports_vision BOOLEAN,
+    supports_tools BOOLEAN,
+    supports_streaming BOOLEAN,
+    supports_reasoning BOOLEAN,
+    input_modalities TEXT,
+    output_modalities TEXT,
     created_at INTEGER NOT NULL,
     updated_at INTEGER NOT NULL,
     FOREIGN KEY (provider_id) REFERENCES ai_providers(id)
 );
 
+-- Session Folders Table (nested through parent_id)
+CREATE TABLE IF NOT EXISTS session_folders (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    parent_id TEXT,
+    sort_order INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (parent_id) REFERENCES session_folders(id)
+);
+
 -- Chat Sessions Table
 CREATE TABLE IF NOT EXISTS chat_sessions (
     id TEXT PRIMARY KEY,
     name TEXT NOT NULL,
     model_id TEXT,
     system_prompt TEXT,
+    folder_id TEXT,
+    is_pinned BOOLEAN NOT NULL DEFAULT FALSE,
+    is_archived BOOLEAN NOT NULL DEFAULT FALSE,
+    name_is_custom BOOLEAN NOT NULL DEFAULT FALSE,
     created_at INTEGER NOT NULL,
     updated_at INTEGER NOT NULL,
-    FOREIGN KEY (model_id) REFERENCES ai_models(id)
+    FOREIGN KEY (model_id) REFERENCES ai_models(id),
+    FOREIGN KEY (folder_id) REFERENCES session_folders(id)
+);
+
+-- Session Tags Tables
+CREATE TABLE IF NOT EXISTS session_tags (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    color TEXT,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS chat_session_tags (
+    session_id TEXT NOT NULL,
+    tag_id TEXT NOT NULL,
+    PRIMARY KEY (session_id, tag_id),
+    FOREIGN KEY (session_id) REFERENCES chat_sessions(id),
+    FOREIGN KEY (tag_id) REFERENCES session_tags(id)
 );
 
 -- Chat Messages Table
@@ -45,10 +118,171 @@ CREATE TABLE IF NOT EXISTS chat_messages (
     role TEXT NOT NULL CHECK(role IN ('user', 'assistant', 'system')),
     content TEXT NOT NULL,
     reasoning TEXT,
+    response_id TEXT,
+    model_id TEXT,
     timestamp INTEGER NOT NULL,
     FOREIGN KEY (session_id) REFERENCES chat_sessions(id)
 );
 
+-- Session Summaries Table (rolling summary of a session's older messages)
+CREATE TABLE IF NOT EXISTS session_summaries (
+    session_id TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    first_message_id TEXT NOT NULL,
+    last_message_id TEXT NOT NULL,
+    message_count INTEGER NOT NULL,
+    is_edited BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (session_id) REFERENCES chat_sessions(id)
+);
+
+-- Model Comparisons Table (a session's history sent to several models at once)
+CREATE TABLE IF NOT EXISTS model_comparisons (
+    id TEXT PRIMARY KEY,
+    session_id TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (session_id) REFERENCES chat_sessions(id)
+);
+
+CREATE TABLE IF NOT EXISTS comparison_candidates (
+    comparison_id TEXT NOT NULL,
+    candidate_index INTEGER NOT NULL,
+    provider_id TEXT NOT NULL,
+    model_id TEXT NOT NULL,
+    model_name TEXT NOT NULL,
+    content TEXT,
+    reasoning TEXT,
+    error TEXT,
+    latency_ms INTEGER,
+    prompt_tokens INTEGER,
+    completion_tokens INTEGER,
+    total_tokens INTEGER,
+    promoted_message_id TEXT,
+    PRIMARY KEY (comparison_id, candidate_index),
+    FOREIGN KEY (comparison_id) REFERENCES model_comparisons(id)
+);
+
+-- Memories Table (facts remembered across sessions)
+CREATE TABLE IF NOT EXISTS memories (
+    id TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    scope TEXT NOT NULL DEFAULT 'global' CHECK(scope IN ('global', 'assistant', 'folder')),
+    scope_id TEXT,
+    source_message_id TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_memories_scope ON memories(scope, scope_id);
+
+-- Knowledge Base Tables (local files chunked and embedded for retrieval).
+-- Collections are kept when their embedding provider is deleted; retrieval
+-- then reports the missing provider.
+CREATE TABLE IF NOT EXISTS kb_collections (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    provider_id TEXT NOT NULL,
+    embedding_model TEXT NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    chunk_overlap INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS kb_documents (
+    id TEXT PRIMARY KEY,
+    collection_id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    chunk_count INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    UNIQUE (collection_id, path),
+    FOREIGN KEY (collection_id) REFERENCES kb_collections(id)
+);
+
+CREATE TABLE IF NOT EXISTS kb_chunks (
+    id TEXT PRIMARY KEY,
+    document_id TEXT NOT NULL,
+    collection_id TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    embedding BLOB NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES kb_documents(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_kb_chunks_collection ON kb_chunks(collection_id);
+
+-- Knowledge base chunks cited by assistant messages (copied, so they survive re-indexing)
+CREATE TABLE IF NOT EXISTS chat_message_citations (
+    message_id TEXT NOT NULL,
+    rank INTEGER NOT NULL,
+    chunk_id TEXT NOT NULL,
+    collection_id TEXT NOT NULL,
+    document_id TEXT NOT NULL,
+    document_path TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    score REAL NOT NULL,
+    PRIMARY KEY (message_id, rank),
+    FOREIGN KEY (message_id) REFERENCES chat_messages(id)
+);
+
+-- Prompt Templates Table
+CREATE TABLE IF NOT EXISTS prompt_templates (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    category TEXT,
+    tags TEXT NOT NULL DEFAULT '[]',
+    content TEXT NOT NULL,
+    variables TEXT NOT NULL DEFAULT '[]',
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+-- Local API Server Request Log Table
+CREATE TABLE IF NOT EXISTS api_server_logs (
+    id TEXT PRIMARY KEY,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    model TEXT,
+    -- Configured model the request was routed to, when it matched one
+    model_id TEXT,
+    provider_id TEXT,
+    status INTEGER NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    error TEXT,
+    created_at INTEGER NOT NULL
+);
+
+-- Provider Health Check History Table
+CREATE TABLE IF NOT EXISTS provider_health_checks (
+    id TEXT PRIMARY KEY,
+    provider_id TEXT NOT NULL,
+    probe TEXT NOT NULL,
+    model TEXT,
+    ok BOOLEAN NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    error_kind TEXT,
+    http_status INTEGER,
+    error TEXT,
+    created_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_provider_health_checks_provider ON provider_health_checks(provider_id, created_at);
+
+-- Fallback Chains Table (models tried in order when a session's or assistant's model fails)
+CREATE TABLE IF NOT EXISTS fallback_chains (
+    owner_type TEXT NOT NULL CHECK(owner_type IN ('session', 'assistant')),
+    owner_id TEXT NOT NULL,
+    model_ids TEXT NOT NULL,
+    updated_at INTEGER NOT NULL,
+    PRIMARY KEY (owner_type, owner_id)
+);
+
 -- App Settings Table
 CREATE TABLE IF NOT EXISTS app_settings (
     key TEXT PRIMARY KEY,
@@ -104,21 +338,27 @@ pub fn get_current_timestamp() -> i64 {
         .as_secs() as i64
 }
 
-// Initialize the database
-pub fn init_db(app_data_dir: &Path) -> Result<Connection> {
+// Name of the database file in the app data directory
+const DB_FILE_NAME: &str = "aichat-pro.db";
+
+// Path of the database file in the app data directory
+pub fn database_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(DB_FILE_NAME)
+}
+
+// Initialize the database, keying it first when it is encrypted
+pub fn init_db(app_data_dir: &Path, key: Option<&DatabaseKey>) -> Result<Connection> {
     // Ensure the directory exists
     fs::create_dir_all(app_data_dir)
         .map_err(|e| rusqlite::Error::InvalidParameterName(format!("Failed to create directory: {}", e)))?;
     
     // Create the database file path
-    let db_path = app_data_dir.join("aichat-pro.db");
-    
-    // Check if database already exists
-    let _db_exists = db_path.exists();
+    let db_path = database_path(app_data_dir);
     
     // Open connection to the database
     let conn = Connection::open(db_path)
         .map_err(|e| rusqlite::Error::InvalidParameterName(format!("Failed to open database: {}", e)))?;
+    configure_connection(&conn, key)?;
     
     // Execute schema SQL to create tables
     conn.execute_batch(SCHEMA_SQL)?;
@@ -132,6 +372,170 @@ pub fn init_db(app_data_dir: &Path) -> Result<Connection> {
     Ok(conn)
 }
 
+// Connections kept by the pool, and how long a caller waits for a free one
+const POOL_SIZE: u32 = 8;
+const POOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+// How long a connection waits for another one's write lock before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub type DbPool = r2d2::Pool<ConnectionManager>;
+pub type PooledConnection = r2d2::PooledConnection<ConnectionManager>;
+
+// Opens pooled connections to the database file
+#[derive(Debug)]
+pub struct ConnectionManager {
+    path: PathBuf,
+    key: Option<DatabaseKey>,
+}
+
+impl r2d2::ManageConnection for ConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        configure_connection(&conn, self.key.as_ref())?;
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut Connection) -> Result<()> {
+        conn.execute_batch("")
+    }
+
+    fn has_broken(&self, _conn: &mut Connection) -> bool {
+        false
+    }
+}
+
+// Settings every connection needs. The key has to come before anything reads
+// the file. WAL lets readers proceed while another connection writes, and the
+// busy timeout makes writers wait for each other.
+fn configure_connection(conn: &Connection, key: Option<&DatabaseKey>) -> Result<()> {
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key.value())?;
+    }
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.busy_timeout(BUSY_TIMEOUT)
+}
+
+// Initialize the database and open a pool of connections to it
+pub fn open_pool(app_data_dir: &Path, key: Option<DatabaseKey>) -> std::result::Result<DbPool, String> {
+    init_db(app_data_dir, key.as_ref()).map_err(|e| format!("Could not initialize database: {}", e))?;
+    
+    let manager = ConnectionManager { path: database_path(app_data_dir), key };
+    r2d2::Pool::builder()
+        .max_size(POOL_SIZE)
+        .min_idle(Some(1))
+        .connection_timeout(POOL_TIMEOUT)
+        .build(manager)
+        .map_err(|e| format!("Could not open database connections: {}", e))
+}
+
+// ====== Encryption functions =======
+
+// Key of an encrypted database. Passphrases are stretched by SQLCipher, while
+// keys kept in the OS keyring are random 256-bit keys used as they are.
+#[derive(Clone, PartialEq)]
+pub enum DatabaseKey {
+    Passphrase(String),
+    Raw(String),
+}
+
+impl DatabaseKey {
+    // New random key to keep in the keyring
+    pub fn generate() -> Self {
+        let bytes: [u8; 32] = rand::random();
+        DatabaseKey::Raw(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    // Value given to `PRAGMA key`. Raw keys use SQLCipher's `x'...'` form so
+    // they skip key derivation.
+    fn value(&self) -> String {
+        match self {
+            DatabaseKey::Passphrase(passphrase) => passphrase.clone(),
+            DatabaseKey::Raw(hex) => format!("x'{}'", hex),
+        }
+    }
+}
+
+// Keep keys out of logs
+impl std::fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatabaseKey::Passphrase(_) => write!(f, "Passphrase(..)"),
+            DatabaseKey::Raw(_) => write!(f, "Raw(..)"),
+        }
+    }
+}
+
+// Header every unencrypted SQLite file starts with
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+// Whether the database file exists and is encrypted. SQLCipher encrypts the
+// whole file, header included.
+pub fn is_encrypted(app_data_dir: &Path) -> bool {
+    let mut header = [0u8; 16];
+    match fs::File::open(database_path(app_data_dir)) {
+        Ok(mut file) => match std::io::Read::read_exact(&mut file, &mut header) {
+            Ok(()) => &header != SQLITE_HEADER,
+            // Files shorter than a header are empty databases
+            Err(_) => false,
+        },
+        Err(_) => false,
+    }
+}
+
+// Whether the key opens the database
+pub fn verify_key(app_data_dir: &Path, key: &DatabaseKey) -> bool {
+    let Ok(conn) = Connection::open(database_path(app_data_dir)) else {
+        return false;
+    };
+    conn.pragma_update(None, "key", key.value()).is_ok()
+        && conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)).is_ok()
+}
+
+// Path the database is exported to before it replaces the original
+fn export_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(format!("{}.export", DB_FILE_NAME))
+}
+
+// Copy the database into a new file encrypted with `key`, or unencrypted when
+// there is no key. SQLCipher can only change whether a file is encrypted by
+// exporting it, so the copy replaces the original through
+// `replace_with_export` once every connection is closed.
+pub fn export_database(conn: &Connection, app_data_dir: &Path, key: Option<&DatabaseKey>) -> Result<()> {
+    let path = export_path(app_data_dir);
+    let _ = fs::remove_file(&path);
+
+    let key = key.map(DatabaseKey::value).unwrap_or_default();
+    conn.execute("ATTACH DATABASE ?1 AS export KEY ?2", params![path.to_string_lossy(), key])?;
+    let exported = conn.query_row("SELECT sqlcipher_export('export')", [], |_| Ok(()));
+    conn.execute_batch("DETACH DATABASE export")?;
+    if exported.is_err() {
+        let _ = fs::remove_file(&path);
+    }
+    exported
+}
+
+// Move an exported database over the original, dropping the original's
+// write-ahead log. Every connection to the original must be closed first.
+pub fn replace_with_export(app_data_dir: &Path) -> std::io::Result<()> {
+    let path = database_path(app_data_dir);
+    fs::rename(export_path(app_data_dir), &path)?;
+    for suffix in ["-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
+    Ok(())
+}
+
+// Change the key of an encrypted database in place. Other connections keep
+// the old key, so the pool has to be reopened afterwards.
+pub fn rekey_database(conn: &Connection, key: &DatabaseKey) -> Result<()> {
+    conn.pragma_update(None, "rekey", key.value())
+}
+
 // Migrate database to latest schema
 fn migrate_database(conn: &Connection) -> Result<()> {
     // Check if api_key column exists in ai_providers table
@@ -146,6 +550,18 @@ fn migrate_database(conn: &Connection) -> Result<()> {
         conn.execute("ALTER TABLE ai_providers ADD COLUMN api_key TEXT", [])?;
     }
     
+    // Add configurable endpoint path columns if they don't exist
+    for column in ["chat_path", "models_path"] {
+        let has_column = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('ai_providers') WHERE name = ?",
+            params![column],
+            |row| row.get::<_, i64>(0)
+        )?;
+        if has_column == 0 {
+            conn.execute(&format!("ALTER TABLE ai_providers ADD COLUMN {} TEXT", column), [])?;
+        }
+    }
+    
     // Check if is_favorite column exists in ai_models table
     let has_is_favorite = conn.query_row(
         "SELECT COUNT(*) FROM pragma_table_info('ai_models') WHERE name = 'is_favorite'",
@@ -160,6 +576,73 @@ fn migrate_database(conn: &Connection) -> Result<()> {
         conn.execute("CREATE INDEX IF NOT EXISTS idx_ai_models_favorite ON ai_models(provider_id, is_favorite)", [])?;
     }
     
+    // Add api_type column to ai_models if it doesn't exist
+    let has_api_type = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('ai_models') WHERE name = 'api_type'",
+        [],
+        |row| row.get::<_, i64>(0)
+    )?;
+    if has_api_type == 0 {
+        conn.execute("ALTER TABLE ai_models ADD COLUMN api_type TEXT NOT NULL DEFAULT 'chat_completions'", [])?;
+    }
+    
+    // Add team catalog and model metadata columns if they don't exist
+    for (table, column, definition) in [
+        ("ai_providers", "is_managed", "BOOLEAN NOT NULL DEFAULT FALSE"),
+        ("ai_models", "is_managed", "BOOLEAN NOT NULL DEFAULT FALSE"),
+        ("ai_models", "context_length", "INTEGER"),
+        ("ai_models", "input_price", "REAL"),
+        ("ai_models", "output_price", "REAL"),
+        ("ai_models", "max_output_tokens", "INTEGER"),
+        ("ai_models", "supports_vision", "BOOLEAN"),
+        ("ai_models", "supports_tools", "BOOLEAN"),
+        ("ai_models", "supports_streaming", "BOOLEAN"),
+        ("ai_models", "supports_reasoning", "BOOLEAN"),
+        ("ai_models", "input_modalities", "TEXT"),
+        ("ai_models", "output_modalities", "TEXT"),
+        ("ai_models", "is_available", "BOOLEAN NOT NULL DEFAULT TRUE"),
+        ("chat_messages", "model_id", "TEXT"),
+        ("api_server_logs", "model_id", "TEXT"),
+    ] {
+        let has_column = conn.query_row(
+            &format!("SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = ?", table),
+            params![column],
+            |row| row.get::<_, i64>(0)
+        )?;
+        if has_column == 0 {
+            conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+        }
+    }
+    
+    // Add folder, pinned, archived and custom name columns to chat_sessions if they don't exist
+    for (column, definition) in [
+        ("folder_id", "TEXT"),
+        ("is_pinned", "BOOLEAN NOT NULL DEFAULT FALSE"),
+        ("is_archived", "BOOLEAN NOT NULL DEFAULT FALSE"),
+        ("name_is_custom", "BOOLEAN NOT NULL DEFAULT FALSE"),
+    ] {
+        let has_column = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('chat_sessions') WHERE name = ?",
+            params![column],
+            |row| row.get::<_, i64>(0)
+        )?;
+        if has_column == 0 {
+            conn.execute(&format!("ALTER TABLE chat_sessions ADD COLUMN {} {}", column, definition), [])?;
+        }
+    }
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_chat_sessions_folder ON chat_sessions(folder_id)", [])?;
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_chat_sessions_listing ON chat_sessions(is_archived, is_pinned, updated_at)", [])?;
+    
+    // Add response_id column to chat_messages if it doesn't exist
+    let has_response_id = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('chat_messages') WHERE name = 'response_id'",
+        [],
+        |row| row.get::<_, i64>(0)
+    )?;
+    if has_response_id == 0 {
+        conn.execute("ALTER TABLE chat_messages ADD COLUMN response_id TEXT", [])?;
+    }
+    
     Ok(())
 }
 
@@ -172,24 +655,36 @@ pub struct AIProvider {
     pub api_url: String,
     pub api_key_name: String,
     pub api_key: Option<String>,
+    // Endpoint paths for OpenAI-compatible providers (defaults apply when unset)
+    pub chat_path: Option<String>,
+    pub models_path: Option<String>,
+    // Maintained by the team catalog and read-only in the app
+    pub is_managed: bool,
     pub created_at: i64,
     pub updated_at: i64,
 }
 
+const PROVIDER_COLUMNS: &str = "id, name, api_url, api_key_name, api_key, chat_path, models_path, is_managed, created_at, updated_at";
+
+fn provider_from_row(row: &rusqlite::Row) -> Result<AIProvider> {
+    Ok(AIProvider {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        api_url: row.get(2)?,
+        api_key_name: row.get(3)?,
+        api_key: row.get(4)?,
+        chat_path: row.get(5)?,
+        models_path: row.get(6)?,
+        is_managed: row.get(7)?,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
 // Get all providers
 pub fn get_all_providers(conn: &Connection) -> Result<Vec<AIProvider>> {
-    let mut stmt = conn.prepare("SELECT id, name, api_url, api_key_name, api_key, created_at, updated_at FROM ai_providers")?;
-    let provider_iter = stmt.query_map([], |row| {
-        Ok(AIProvider {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            api_url: row.get(2)?,
-            api_key_name: row.get(3)?,
-            api_key: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-        })
-    })?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM ai_providers", PROVIDER_COLUMNS))?;
+    let provider_iter = stmt.query_map([], provider_from_row)?;
 
     let mut providers = Vec::new();
     for provider in provider_iter {
@@ -280,6 +775,11 @@ pub fn delete_provider(conn: &mut Connection, id: &str) -> Result<()> {
         }
     }
     
+    // Delete the provider's connection and Azure settings
+    tx.execute("DELETE FROM provider_connection_settings WHERE provider_id = ?", params![id])?;
+    tx.execute("DELETE FROM azure_provider_settings WHERE provider_id = ?", params![id])?;
+    tx.execute("DELETE FROM provider_health_checks WHERE provider_id = ?", params![id])?;
+    
     // Then delete the provider itself
     println!("DB: Deleting provider: {}", id);
     match tx.execute("DELETE FROM ai_providers WHERE id = ?", params![id]) {
@@ -312,21 +812,9 @@ pub fn delete_provider(conn: &mut Connection, id: &str) -> Result<()> {
 
 // Get a provider by ID
 pub fn get_provider_by_id(conn: &Connection, id: &str) -> Result<Option<AIProvider>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, name, api_url, api_key_name, api_key, created_at, updated_at FROM ai_providers WHERE id = ?"
-    )?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM ai_providers WHERE id = ?", PROVIDER_COLUMNS))?;
     
-    let provider = stmt.query_row(params![id], |row| {
-        Ok(AIProvider {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            api_url: row.get(2)?,
-            api_key_name: row.get(3)?,
-            api_key: row.get(4)?,
-            created_at: row.get(5)?,
-            updated_at: row.get(6)?,
-        })
-    });
+    let provider = stmt.query_row(params![id], provider_from_row);
     
     match provider {
         Ok(p) => Ok(Some(p)),
@@ -347,6 +835,126 @@ pub fn add_provider_with_id(conn: &Connection, id: &str, name: &str, api_url: &s
     Ok(id.to_string())
 }
 
+// Insert a provider with a given id, or update it if it exists. An omitted
+// API key keeps the stored one.
+pub fn upsert_provider(conn: &Connection, id: &str, name: &str, api_url: &str, api_key_name: &str, api_key: Option<&str>) -> Result<()> {
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "INSERT INTO ai_providers (id, name, api_url, api_key_name, api_key, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+         ON CONFLICT(id) DO UPDATE SET name = excluded.name, api_url = excluded.api_url, api_key_name = excluded.api_key_name,
+             api_key = COALESCE(excluded.api_key, api_key), updated_at = excluded.updated_at",
+        params![id, name, api_url, api_key_name, api_key, timestamp],
+    )?;
+    
+    Ok(())
+}
+
+// Set the endpoint paths of an OpenAI-compatible provider (None restores the default)
+pub fn set_provider_endpoints(conn: &Connection, id: &str, chat_path: Option<&str>, models_path: Option<&str>) -> Result<()> {
+    let timestamp = get_current_timestamp();
+
+    conn.execute(
+        "UPDATE ai_providers SET chat_path = ?, models_path = ?, updated_at = ? WHERE id = ?",
+        params![chat_path, models_path, timestamp, id],
+    )?;
+
+    Ok(())
+}
+
+// Get the connection settings of a provider (defaults if none are stored)
+pub fn get_connection_settings(conn: &Connection, provider_id: &str) -> Result<crate::ai::ConnectionSettings> {
+    let mut stmt = conn.prepare(
+        "SELECT proxy_url, connect_timeout_secs, read_timeout_secs, extra_headers, ca_cert_path, accept_invalid_certs
+         FROM provider_connection_settings WHERE provider_id = ?"
+    )?;
+
+    let settings = stmt.query_row(params![provider_id], |row| {
+        let extra_headers: String = row.get(3)?;
+        Ok(crate::ai::ConnectionSettings {
+            proxy_url: row.get(0)?,
+            connect_timeout_secs: row.get(1)?,
+            read_timeout_secs: row.get(2)?,
+            extra_headers: serde_json::from_str(&extra_headers)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e)))?,
+            ca_cert_path: row.get(4)?,
+            accept_invalid_certs: row.get(5)?,
+        })
+    });
+
+    match settings {
+        Ok(s) => Ok(s),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(crate::ai::ConnectionSettings::default()),
+        Err(e) => Err(e),
+    }
+}
+
+// Store the connection settings of a provider
+pub fn set_connection_settings(conn: &Connection, provider_id: &str, settings: &crate::ai::ConnectionSettings) -> Result<()> {
+    let timestamp = get_current_timestamp();
+
+    conn.execute(
+        "INSERT OR REPLACE INTO provider_connection_settings
+         (provider_id, proxy_url, connect_timeout_secs, read_timeout_secs, extra_headers, ca_cert_path, accept_invalid_certs, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            provider_id,
+            settings.proxy_url,
+            settings.connect_timeout_secs,
+            settings.read_timeout_secs,
+            to_json_text(&settings.extra_headers)?,
+            settings.ca_cert_path,
+            settings.accept_invalid_certs,
+            timestamp
+        ],
+    )?;
+
+    Ok(())
+}
+
+// Get the Azure OpenAI settings of a provider (defaults if none are stored)
+pub fn get_azure_settings(conn: &Connection, provider_id: &str) -> Result<crate::ai::AzureSettings> {
+    let mut stmt = conn.prepare(
+        "SELECT api_version, auth_type, deployments FROM azure_provider_settings WHERE provider_id = ?"
+    )?;
+
+    let settings = stmt.query_row(params![provider_id], |row| {
+        let auth_type: String = row.get(1)?;
+        let deployments: String = row.get(2)?;
+        Ok(crate::ai::AzureSettings {
+            api_version: row.get(0)?,
+            auth_type: crate::ai::AzureAuthType::parse(&auth_type).unwrap_or_default(),
+            deployments: serde_json::from_str(&deployments)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e)))?,
+        })
+    });
+
+    match settings {
+        Ok(s) => Ok(s),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(crate::ai::AzureSettings::default()),
+        Err(e) => Err(e),
+    }
+}
+
+// Store the Azure OpenAI settings of a provider
+pub fn set_azure_settings(conn: &Connection, provider_id: &str, settings: &crate::ai::AzureSettings) -> Result<()> {
+    let timestamp = get_current_timestamp();
+
+    conn.execute(
+        "INSERT OR REPLACE INTO azure_provider_settings (provider_id, api_version, auth_type, deployments, updated_at)
+         VALUES (?, ?, ?, ?, ?)",
+        params![
+            provider_id,
+            settings.api_version,
+            settings.auth_type.as_str(),
+            to_json_text(&settings.deployments)?,
+            timestamp
+        ],
+    )?;
+
+    Ok(())
+}
+
 // ====== AI Model functions =======
 
 #[derive(Debug, serde::Serialize)]
@@ -355,26 +963,90 @@ pub struct AIModel {
     pub provider_id: String,
     pub name: String,
     pub is_favorite: bool,
+    // "chat_completions" or "responses"
+    pub api_type: String,
+    // Maintained by the team catalog and read-only in the app
+    pub is_managed: bool,
+    // False once the provider no longer lists it; kept for the sessions using it
+    pub is_available: bool,
+    #[serde(flatten)]
+    pub metadata: ModelMetadata,
     pub created_at: i64,
     pub updated_at: i64,
 }
 
+const MODEL_COLUMNS: &str = "id, provider_id, name, is_favorite, api_type, is_managed, is_available, \
+    context_length, max_output_tokens, input_price, output_price, supports_vision, supports_tools, \
+    supports_streaming, supports_reasoning, input_modalities, output_modalities, created_at, updated_at";
+
+// Modalities are stored comma-separated, e.g. "text,image"
+fn join_modalities(modalities: &[String]) -> Option<String> {
+    (!modalities.is_empty()).then(|| modalities.join(","))
+}
+
+fn split_modalities(value: Option<String>) -> Vec<String> {
+    value
+        .map(|v| v.split(',').filter(|m| !m.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn model_from_row(row: &rusqlite::Row) -> Result<AIModel> {
+    Ok(AIModel {
+        id: row.get(0)?,
+        provider_id: row.get(1)?,
+        name: row.get(2)?,
+        is_favorite: row.get(3)?,
+        api_type: row.get(4)?,
+        is_managed: row.get(5)?,
+        is_available: row.get(6)?,
+        metadata: ModelMetadata {
+            context_length: row.get(7)?,
+            max_output_tokens: row.get(8)?,
+            input_price: row.get(9)?,
+            output_price: row.get(10)?,
+            supports_vision: row.get(11)?,
+            supports_tools: row.get(12)?,
+            supports_streaming: row.get(13)?,
+            supports_reasoning: row.get(14)?,
+            input_modalities: split_modalities(row.get(15)?),
+            output_modalities: split_modalities(row.get(16)?),
+        },
+        created_at: row.get(17)?,
+        updated_at: row.get(18)?,
+    })
+}
+
+// Store what is known about a model, replacing what was stored before
+pub fn set_model_metadata(conn: &Connection, model_id: &str, metadata: &ModelMetadata) -> Result<()> {
+    conn.execute(
+        "UPDATE ai_models SET context_length = ?, max_output_tokens = ?, input_price = ?, output_price = ?, \
+         supports_vision = ?, supports_tools = ?, supports_streaming = ?, supports_reasoning = ?, \
+         input_modalities = ?, output_modalities = ?, updated_at = ? WHERE id = ?",
+        params![
+            metadata.context_length,
+            metadata.max_output_tokens,
+            metadata.input_price,
+            metadata.output_price,
+            metadata.supports_vision,
+            metadata.supports_tools,
+            metadata.supports_streaming,
+            metadata.supports_reasoning,
+            join_modalities(&metadata.input_modalities),
+            join_modalities(&metadata.output_modalities),
+            get_current_timestamp(),
+            model_id
+        ],
+    )?;
+    Ok(())
+}
+
 // Get all models for a provider
 pub fn get_models_by_provider(conn: &Connection, provider_id: &str) -> Result<Vec<AIModel>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, provider_id, name, is_favorite, created_at, updated_at FROM ai_models WHERE provider_id = ? ORDER BY is_favorite DESC, name ASC"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM ai_models WHERE provider_id = ? ORDER BY is_favorite DESC, name ASC", MODEL_COLUMNS
+    ))?;
     
-    let model_iter = stmt.query_map(params![provider_id], |row| {
-        Ok(AIModel {
-            id: row.get(0)?,
-            provider_id: row.get(1)?,
-            name: row.get(2)?,
-            is_favorite: row.get(3)?,
-            created_at: row.get(4)?,
-            updated_at: row.get(5)?,
-        })
-    })?;
+    let model_iter = stmt.query_map(params![provider_id], model_from_row)?;
 
     let mut models = Vec::new();
     for model in model_iter {
@@ -396,10 +1068,24 @@ pub fn add_model(conn: &Connection, provider_id: &str, name: &str) -> Result<Str
     Ok(id)
 }
 
-// Delete a model
-pub fn delete_model(conn: &mut Connection, id: &str) -> Result<()> {
-    // Begin transaction
-    let tx = conn.transaction()?;
+// Insert a model with a given id, or update it if it exists
+pub fn upsert_model(conn: &Connection, id: &str, provider_id: &str, name: &str, is_favorite: bool, api_type: crate::ai::ApiType) -> Result<()> {
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "INSERT INTO ai_models (id, provider_id, name, is_favorite, api_type, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+         ON CONFLICT(id) DO UPDATE SET provider_id = excluded.provider_id, name = excluded.name,
+             is_favorite = excluded.is_favorite, api_type = excluded.api_type, updated_at = excluded.updated_at",
+        params![id, provider_id, name, is_favorite, api_type.as_str(), timestamp],
+    )?;
+    
+    Ok(())
+}
+
+// Delete a model
+pub fn delete_model(conn: &mut Connection, id: &str) -> Result<()> {
+    // Begin transaction
+    let tx = conn.transaction()?;
     
     // First update any chat sessions that use this model to set model_id to NULL
     tx.execute(
@@ -428,23 +1114,57 @@ pub fn toggle_model_favorite(conn: &Connection, model_id: &str, is_favorite: boo
     Ok(())
 }
 
+// Set which OpenAI API a model is called through
+pub fn set_model_api_type(conn: &Connection, model_id: &str, api_type: crate::ai::ApiType) -> Result<()> {
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "UPDATE ai_models SET api_type = ?, updated_at = ? WHERE id = ?",
+        params![api_type.as_str(), timestamp, model_id],
+    )?;
+    
+    Ok(())
+}
+
+// Get a model by ID
+pub fn get_model_by_id(conn: &Connection, id: &str) -> Result<Option<AIModel>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM ai_models WHERE id = ?", MODEL_COLUMNS
+    ))?;
+    
+    let model = stmt.query_row(params![id], model_from_row);
+    
+    match model {
+        Ok(m) => Ok(Some(m)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Find models with the given name across all providers, available ones and
+// favorites first
+pub fn find_models_by_name(conn: &Connection, name: &str) -> Result<Vec<AIModel>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM ai_models WHERE name = ? ORDER BY is_available DESC, is_favorite DESC, created_at ASC", MODEL_COLUMNS
+    ))?;
+    
+    let model_iter = stmt.query_map(params![name], model_from_row)?;
+
+    let mut models = Vec::new();
+    for model in model_iter {
+        models.push(model?);
+    }
+    Ok(models)
+}
+
 // Get only favorite models for a provider
 #[allow(dead_code)]
 pub fn get_favorite_models_by_provider(conn: &Connection, provider_id: &str) -> Result<Vec<AIModel>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, provider_id, name, is_favorite, created_at, updated_at FROM ai_models WHERE provider_id = ? AND is_favorite = TRUE ORDER BY name ASC"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM ai_models WHERE provider_id = ? AND is_favorite = TRUE ORDER BY name ASC", MODEL_COLUMNS
+    ))?;
     
-    let model_iter = stmt.query_map(params![provider_id], |row| {
-        Ok(AIModel {
-            id: row.get(0)?,
-            provider_id: row.get(1)?,
-            name: row.get(2)?,
-            is_favorite: row.get(3)?,
-            created_at: row.get(4)?,
-            updated_at: row.get(5)?,
-        })
-    })?;
+    let model_iter = stmt.query_map(params![provider_id], model_from_row)?;
 
     let mut models = Vec::new();
     for model in model_iter {
@@ -453,6 +1173,131 @@ pub fn get_favorite_models_by_provider(conn: &Connection, provider_id: &str) ->
     Ok(models)
 }
 
+// ====== Team catalog functions =======
+
+// Release every catalog-managed provider and model. A sync then marks the
+// ones still in the catalog again, so removed entries become the user's own.
+pub fn clear_managed_entries(conn: &Connection) -> Result<()> {
+    conn.execute("UPDATE ai_providers SET is_managed = FALSE WHERE is_managed", [])?;
+    conn.execute("UPDATE ai_models SET is_managed = FALSE WHERE is_managed", [])?;
+    Ok(())
+}
+
+// Insert or update a catalog provider and mark it managed, keeping the user's
+// API key. Returns whether it was added.
+pub fn save_managed_provider(
+    conn: &Connection,
+    id: &str,
+    name: &str,
+    api_url: &str,
+    api_key_name: &str,
+    chat_path: Option<&str>,
+    models_path: Option<&str>,
+) -> Result<bool> {
+    let added = get_provider_by_id(conn, id)?.is_none();
+    upsert_provider(conn, id, name, api_url, api_key_name, None)?;
+    set_provider_endpoints(conn, id, chat_path, models_path)?;
+    conn.execute("UPDATE ai_providers SET is_managed = TRUE WHERE id = ?", params![id])?;
+    Ok(added)
+}
+
+// Insert or update a catalog model, matched by provider and name so its id
+// and favorite flag survive syncs. Returns whether it was added.
+pub fn save_managed_model(
+    conn: &Connection,
+    provider_id: &str,
+    name: &str,
+    api_type: Option<crate::ai::ApiType>,
+    metadata: &ModelMetadata,
+) -> Result<bool> {
+    let existing = conn.query_row(
+        "SELECT id FROM ai_models WHERE provider_id = ? AND name = ? ORDER BY created_at LIMIT 1",
+        params![provider_id, name],
+        |row| row.get::<_, String>(0),
+    );
+    let (id, added) = match existing {
+        Ok(id) => (id, false),
+        Err(rusqlite::Error::QueryReturnedNoRows) => (add_model(conn, provider_id, name)?, true),
+        Err(e) => return Err(e),
+    };
+    conn.execute(
+        "UPDATE ai_models SET is_managed = TRUE, api_type = COALESCE(?, api_type) WHERE id = ?",
+        params![api_type.map(|t| t.as_str()), id],
+    )?;
+    set_model_metadata(conn, &id, metadata)?;
+    Ok(added)
+}
+
+// ====== Model sync functions =======
+
+// Changes made by syncing a provider's models with its listing, by model name
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub struct ModelSyncDiff {
+    pub added: Vec<String>,
+    // No longer listed; kept, but marked unavailable
+    pub removed: Vec<String>,
+    // Listed again after being marked unavailable
+    pub restored: Vec<String>,
+    pub unchanged: usize,
+}
+
+// Id of a model added by a sync, the same on every install
+pub fn synced_model_id(provider_id: &str, name: &str) -> String {
+    format!("{}:{}", provider_id, name)
+}
+
+// Reconcile a provider's stored models with its listing in one transaction.
+// Models are never deleted, so sessions keep their model, and favorites are
+// left alone. New models are only added when `add_new` is set.
+pub fn sync_provider_models(
+    conn: &mut Connection,
+    provider_id: &str,
+    listing: &[crate::model_info::ModelInfo],
+    add_new: bool,
+) -> Result<ModelSyncDiff> {
+    let tx = conn.transaction()?;
+    let stored = get_models_by_provider(&tx, provider_id)?;
+    let mut diff = ModelSyncDiff::default();
+
+    for model in &stored {
+        let listed = listing.iter().find(|m| m.id == model.name);
+        match (listed.is_some(), model.is_available) {
+            (true, true) => diff.unchanged += 1,
+            (true, false) => diff.restored.push(model.name.clone()),
+            (false, true) => diff.removed.push(model.name.clone()),
+            (false, false) => {}
+        }
+        if listed.is_some() != model.is_available {
+            tx.execute(
+                "UPDATE ai_models SET is_available = ?, updated_at = ? WHERE id = ?",
+                params![listed.is_some(), get_current_timestamp(), model.id],
+            )?;
+        }
+        if let Some(listed) = listed.filter(|m| !model.is_managed && !m.metadata.is_empty()) {
+            set_model_metadata(&tx, &model.id, &listed.metadata)?;
+        }
+    }
+
+    if add_new {
+        for listed in listing {
+            let known = stored.iter().any(|m| m.name == listed.id) || diff.added.contains(&listed.id);
+            if known {
+                continue;
+            }
+            let id = synced_model_id(provider_id, &listed.id);
+            upsert_model(&tx, &id, provider_id, &listed.id, false, crate::ai::ApiType::default())?;
+            set_model_metadata(&tx, &id, &listed.metadata)?;
+            diff.added.push(listed.id.clone());
+        }
+    }
+
+    tx.commit()?;
+    diff.added.sort();
+    diff.removed.sort();
+    diff.restored.sort();
+    Ok(diff)
+}
+
 // ====== Chat Session functions =======
 
 #[derive(Debug, serde::Serialize)]
@@ -461,26 +1306,46 @@ pub struct ChatSession {
     pub name: String,
     pub model_id: Option<String>,
     pub system_prompt: Option<String>,
+    pub folder_id: Option<String>,
+    pub is_pinned: bool,
+    pub is_archived: bool,
+    // Whether the user renamed the session (generated titles no longer apply)
+    pub name_is_custom: bool,
+    pub tag_ids: Vec<String>,
     pub created_at: i64,
     pub updated_at: i64,
 }
 
+const SESSION_COLUMNS: &str = "s.id, s.name, s.model_id, s.system_prompt, s.folder_id, s.is_pinned, s.is_archived, s.name_is_custom,
+    (SELECT json_group_array(st.tag_id) FROM chat_session_tags st WHERE st.session_id = s.id),
+    s.created_at, s.updated_at";
+
+fn session_from_row(row: &rusqlite::Row) -> Result<ChatSession> {
+    let tag_ids: String = row.get(8)?;
+    Ok(ChatSession {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        model_id: row.get(2)?,
+        system_prompt: row.get(3)?,
+        folder_id: row.get(4)?,
+        is_pinned: row.get(5)?,
+        is_archived: row.get(6)?,
+        name_is_custom: row.get(7)?,
+        tag_ids: serde_json::from_str(&tag_ids)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Text, Box::new(e)))?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+    })
+}
+
 // Get all chat sessions
 pub fn get_all_chat_sessions(conn: &Connection) -> Result<Vec<ChatSession>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, name, model_id, system_prompt, created_at, updated_at FROM chat_sessions ORDER BY updated_at DESC"
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM chat_sessions s ORDER BY s.updated_at DESC",
+        SESSION_COLUMNS
+    ))?;
     
-    let session_iter = stmt.query_map([], |row| {
-        Ok(ChatSession {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            model_id: row.get(2)?,
-            system_prompt: row.get(3)?,
-            created_at: row.get(4)?,
-            updated_at: row.get(5)?,
-        })
-    })?;
+    let session_iter = stmt.query_map([], session_from_row)?;
 
     let mut sessions = Vec::new();
     for session in session_iter {
@@ -489,6 +1354,85 @@ pub fn get_all_chat_sessions(conn: &Connection) -> Result<Vec<ChatSession>> {
     Ok(sessions)
 }
 
+// Filters for listing chat sessions; unset fields do not filter
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct SessionFilter {
+    pub folder_id: Option<String>,
+    // Also match sessions in subfolders of `folder_id`
+    pub include_subfolders: bool,
+    // Only sessions that are not in any folder
+    pub unfiled: bool,
+    pub tag_id: Option<String>,
+    pub archived: Option<bool>,
+    pub model_id: Option<String>,
+    // Unix timestamps bounding `updated_at`
+    pub updated_after: Option<i64>,
+    pub updated_before: Option<i64>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SessionPage {
+    pub sessions: Vec<ChatSession>,
+    // Number of sessions matching the filter, ignoring limit and offset
+    pub total: i64,
+}
+
+const SESSION_FILTER_SQL: &str = "
+    WITH RECURSIVE folder_tree(id) AS (
+        SELECT ?1 WHERE ?1 IS NOT NULL
+        UNION
+        SELECT f.id FROM session_folders f JOIN folder_tree t ON f.parent_id = t.id WHERE ?2
+    )
+    SELECT {} FROM chat_sessions s
+    WHERE (?1 IS NULL OR s.folder_id IN (SELECT id FROM folder_tree))
+      AND (?3 = 0 OR s.folder_id IS NULL)
+      AND (?4 IS NULL OR EXISTS (SELECT 1 FROM chat_session_tags st WHERE st.session_id = s.id AND st.tag_id = ?4))
+      AND (?5 IS NULL OR s.is_archived = ?5)
+      AND (?6 IS NULL OR s.model_id = ?6)
+      AND (?7 IS NULL OR s.updated_at >= ?7)
+      AND (?8 IS NULL OR s.updated_at < ?8)";
+
+// List chat sessions matching a filter, pinned sessions first, then most recently updated
+pub fn list_chat_sessions(conn: &Connection, filter: &SessionFilter) -> Result<SessionPage> {
+    let filter_params = params![
+        filter.folder_id,
+        filter.include_subfolders,
+        filter.unfiled,
+        filter.tag_id,
+        filter.archived,
+        filter.model_id,
+        filter.updated_after,
+        filter.updated_before,
+    ];
+    
+    let total = conn.query_row(
+        &SESSION_FILTER_SQL.replace("{}", "COUNT(*)"),
+        filter_params,
+        |row| row.get::<_, i64>(0)
+    )?;
+    
+    let mut stmt = conn.prepare(&format!(
+        "{} ORDER BY s.is_pinned DESC, s.updated_at DESC LIMIT ?9 OFFSET ?10",
+        SESSION_FILTER_SQL.replace("{}", SESSION_COLUMNS)
+    ))?;
+    
+    // A negative limit means no limit in SQLite
+    let limit = filter.limit.map(i64::from).unwrap_or(-1);
+    let offset = filter.offset.unwrap_or(0);
+    let mut page_params = filter_params.to_vec();
+    page_params.extend(params![limit, offset]);
+    let session_iter = stmt.query_map(page_params.as_slice(), session_from_row)?;
+
+    let mut sessions = Vec::new();
+    for session in session_iter {
+        sessions.push(session?);
+    }
+    Ok(SessionPage { sessions, total })
+}
+
 // Create a new chat session
 pub fn create_chat_session(conn: &Connection, name: &str, model_id: Option<&str>, system_prompt: Option<&str>) -> Result<String> {
     let id = Uuid::new_v4().to_string();
@@ -503,18 +1447,32 @@ pub fn create_chat_session(conn: &Connection, name: &str, model_id: Option<&str>
     Ok(id)
 }
 
-// Update a chat session
-pub fn update_chat_session(conn: &Connection, id: &str, name: &str, model_id: Option<&str>, system_prompt: Option<&str>) -> Result<()> {
+// Update a chat session, keeping its name when `name` is None. A changed name
+// is marked as chosen by the user.
+pub fn update_chat_session(conn: &Connection, id: &str, name: Option<&str>, model_id: Option<&str>, system_prompt: Option<&str>) -> Result<()> {
     let timestamp = get_current_timestamp();
     
     conn.execute(
-        "UPDATE chat_sessions SET name = ?, model_id = ?, system_prompt = ?, updated_at = ? WHERE id = ?",
+        "UPDATE chat_sessions SET name_is_custom = (name_is_custom OR (?1 IS NOT NULL AND name <> ?1)), name = COALESCE(?1, name),
+             model_id = ?2, system_prompt = ?3, updated_at = ?4 WHERE id = ?5",
         params![name, model_id, system_prompt, timestamp, id],
     )?;
     
     Ok(())
 }
 
+// Set a generated title as the session name. Sessions the user renamed are
+// left alone unless `force` is set. Returns whether the name was updated.
+pub fn set_generated_session_name(conn: &Connection, id: &str, name: &str, force: bool) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE chat_sessions SET name = ?1, name_is_custom = FALSE
+         WHERE id = ?2 AND (?3 OR name_is_custom = FALSE)",
+        params![name, id, force],
+    )?;
+    
+    Ok(updated > 0)
+}
+
 // Delete a chat session
 pub fn delete_chat_session(conn: &mut Connection, id: &str) -> Result<()> {
     println!("DB: Starting delete_chat_session for ID: {}", id);
@@ -531,8 +1489,12 @@ pub fn delete_chat_session(conn: &mut Connection, id: &str) -> Result<()> {
         }
     };
     
-    // First delete all messages in the session
+    // First delete all messages in the session, with their citations
     println!("DB: Deleting messages for session: {}", id);
+    tx.execute(
+        "DELETE FROM chat_message_citations WHERE message_id IN (SELECT id FROM chat_messages WHERE session_id = ?)",
+        params![id],
+    )?;
     match tx.execute("DELETE FROM chat_messages WHERE session_id = ?", params![id]) {
         Ok(count) => println!("DB: Deleted {} messages", count),
         Err(e) => {
@@ -541,6 +1503,16 @@ pub fn delete_chat_session(conn: &mut Connection, id: &str) -> Result<()> {
         }
     }
     
+    // Remove the session's tags, summary, fallback chain and model comparisons
+    tx.execute("DELETE FROM chat_session_tags WHERE session_id = ?", params![id])?;
+    tx.execute("DELETE FROM session_summaries WHERE session_id = ?", params![id])?;
+    tx.execute("DELETE FROM fallback_chains WHERE owner_type = 'session' AND owner_id = ?", params![id])?;
+    tx.execute(
+        "DELETE FROM comparison_candidates WHERE comparison_id IN (SELECT id FROM model_comparisons WHERE session_id = ?)",
+        params![id],
+    )?;
+    tx.execute("DELETE FROM model_comparisons WHERE session_id = ?", params![id])?;
+    
     // Then delete the session itself
     println!("DB: Deleting session: {}", id);
     match tx.execute("DELETE FROM chat_sessions WHERE id = ?", params![id]) {
@@ -573,26 +1545,243 @@ pub fn delete_chat_session(conn: &mut Connection, id: &str) -> Result<()> {
 
 // Get a chat session by ID
 pub fn get_chat_session_by_id(conn: &Connection, id: &str) -> Result<Option<ChatSession>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM chat_sessions s WHERE s.id = ?", SESSION_COLUMNS))?;
+    
+    let session = stmt.query_row(params![id], session_from_row);
+    
+    match session {
+        Ok(s) => Ok(Some(s)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Pin or unpin a chat session (does not change its position by recency)
+pub fn set_session_pinned(conn: &Connection, id: &str, is_pinned: bool) -> Result<()> {
+    conn.execute(
+        "UPDATE chat_sessions SET is_pinned = ? WHERE id = ?",
+        params![is_pinned, id],
+    )?;
+    
+    Ok(())
+}
+
+// Archive or restore a chat session
+pub fn set_session_archived(conn: &Connection, id: &str, is_archived: bool) -> Result<()> {
+    conn.execute(
+        "UPDATE chat_sessions SET is_archived = ? WHERE id = ?",
+        params![is_archived, id],
+    )?;
+    
+    Ok(())
+}
+
+// Move a chat session into a folder, or out of all folders with `None`
+pub fn move_session_to_folder(conn: &Connection, id: &str, folder_id: Option<&str>) -> Result<()> {
+    conn.execute(
+        "UPDATE chat_sessions SET folder_id = ? WHERE id = ?",
+        params![folder_id, id],
+    )?;
+    
+    Ok(())
+}
+
+// ====== Session Folder functions =======
+
+#[derive(Debug, serde::Serialize)]
+pub struct SessionFolder {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub sort_order: i64,
+    // Unarchived sessions directly in this folder
+    pub session_count: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// Get all session folders with their session counts
+pub fn get_session_folders(conn: &Connection) -> Result<Vec<SessionFolder>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, model_id, system_prompt, created_at, updated_at FROM chat_sessions WHERE id = ?"
+        "SELECT f.id, f.name, f.parent_id, f.sort_order,
+                (SELECT COUNT(*) FROM chat_sessions s WHERE s.folder_id = f.id AND s.is_archived = FALSE),
+                f.created_at, f.updated_at
+         FROM session_folders f ORDER BY f.sort_order ASC, f.name ASC"
     )?;
     
-    let session = stmt.query_row(params![id], |row| {
-        Ok(ChatSession {
+    let folder_iter = stmt.query_map([], |row| {
+        Ok(SessionFolder {
             id: row.get(0)?,
             name: row.get(1)?,
-            model_id: row.get(2)?,
-            system_prompt: row.get(3)?,
+            parent_id: row.get(2)?,
+            sort_order: row.get(3)?,
+            session_count: row.get(4)?,
+            created_at: row.get(5)?,
+            updated_at: row.get(6)?,
+        })
+    })?;
+
+    let mut folders = Vec::new();
+    for folder in folder_iter {
+        folders.push(folder?);
+    }
+    Ok(folders)
+}
+
+// Create a session folder
+pub fn create_session_folder(conn: &Connection, name: &str, parent_id: Option<&str>) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "INSERT INTO session_folders (id, name, parent_id, sort_order, created_at, updated_at)
+         VALUES (?, ?, ?, (SELECT COALESCE(MAX(sort_order), -1) + 1 FROM session_folders WHERE parent_id IS ?), ?, ?)",
+        params![id, name, parent_id, parent_id, timestamp, timestamp],
+    )?;
+    
+    Ok(id)
+}
+
+// Rename, move or reorder a session folder
+pub fn update_session_folder(conn: &Connection, id: &str, name: &str, parent_id: Option<&str>, sort_order: Option<i64>) -> Result<()> {
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "UPDATE session_folders SET name = ?, parent_id = ?, sort_order = COALESCE(?, sort_order), updated_at = ? WHERE id = ?",
+        params![name, parent_id, sort_order, timestamp, id],
+    )?;
+    
+    Ok(())
+}
+
+// Check whether `folder_id` is `ancestor_id` or one of its subfolders
+pub fn is_folder_within(conn: &Connection, folder_id: &str, ancestor_id: &str) -> Result<bool> {
+    conn.query_row(
+        "WITH RECURSIVE folder_tree(id) AS (
+             SELECT ?1
+             UNION
+             SELECT f.id FROM session_folders f JOIN folder_tree t ON f.parent_id = t.id
+         )
+         SELECT EXISTS (SELECT 1 FROM folder_tree WHERE id = ?2)",
+        params![ancestor_id, folder_id],
+        |row| row.get(0),
+    )
+}
+
+// Delete a session folder; its sessions and subfolders move to its parent
+pub fn delete_session_folder(conn: &mut Connection, id: &str) -> Result<()> {
+    let tx = conn.transaction()?;
+    
+    let parent_id: Option<Option<String>> = match tx.query_row(
+        "SELECT parent_id FROM session_folders WHERE id = ?",
+        params![id],
+        |row| row.get(0),
+    ) {
+        Ok(parent_id) => Some(parent_id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(e),
+    };
+    
+    if let Some(parent_id) = parent_id {
+        tx.execute("UPDATE chat_sessions SET folder_id = ? WHERE folder_id = ?", params![parent_id, id])?;
+        tx.execute("UPDATE session_folders SET parent_id = ? WHERE parent_id = ?", params![parent_id, id])?;
+        // Memories of the folder move to its parent, or become global at the top level
+        tx.execute(
+            "UPDATE memories SET scope = CASE WHEN ?1 IS NULL THEN 'global' ELSE 'folder' END, scope_id = ?1
+             WHERE scope = 'folder' AND scope_id = ?2",
+            params![parent_id, id],
+        )?;
+        tx.execute("DELETE FROM session_folders WHERE id = ?", params![id])?;
+    }
+    
+    tx.commit()?;
+    
+    Ok(())
+}
+
+// ====== Session Tag functions =======
+
+#[derive(Debug, serde::Serialize)]
+pub struct SessionTag {
+    pub id: String,
+    pub name: String,
+    pub color: Option<String>,
+    pub session_count: i64,
+    pub created_at: i64,
+}
+
+// Get all session tags with the number of sessions using them
+pub fn get_session_tags(conn: &Connection) -> Result<Vec<SessionTag>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.name, t.color,
+                (SELECT COUNT(*) FROM chat_session_tags st WHERE st.tag_id = t.id),
+                t.created_at
+         FROM session_tags t ORDER BY t.name ASC"
+    )?;
+    
+    let tag_iter = stmt.query_map([], |row| {
+        Ok(SessionTag {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            color: row.get(2)?,
+            session_count: row.get(3)?,
             created_at: row.get(4)?,
-            updated_at: row.get(5)?,
         })
-    });
+    })?;
+
+    let mut tags = Vec::new();
+    for tag in tag_iter {
+        tags.push(tag?);
+    }
+    Ok(tags)
+}
+
+// Create a session tag, returning the existing tag's ID if the name is taken
+pub fn create_session_tag(conn: &Connection, name: &str, color: Option<&str>) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = get_current_timestamp();
     
-    match session {
-        Ok(s) => Ok(Some(s)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(e),
+    conn.execute(
+        "INSERT OR IGNORE INTO session_tags (id, name, color, created_at) VALUES (?, ?, ?, ?)",
+        params![id, name, color, timestamp],
+    )?;
+    
+    conn.query_row("SELECT id FROM session_tags WHERE name = ?", params![name], |row| row.get(0))
+}
+
+// Rename or recolor a session tag
+pub fn update_session_tag(conn: &Connection, id: &str, name: &str, color: Option<&str>) -> Result<()> {
+    conn.execute(
+        "UPDATE session_tags SET name = ?, color = ? WHERE id = ?",
+        params![name, color, id],
+    )?;
+    
+    Ok(())
+}
+
+// Delete a session tag and remove it from all sessions
+pub fn delete_session_tag(conn: &mut Connection, id: &str) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM chat_session_tags WHERE tag_id = ?", params![id])?;
+    tx.execute("DELETE FROM session_tags WHERE id = ?", params![id])?;
+    tx.commit()?;
+    
+    Ok(())
+}
+
+// Replace the tags of a chat session
+pub fn set_session_tags(conn: &mut Connection, session_id: &str, tag_ids: &[String]) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM chat_session_tags WHERE session_id = ?", params![session_id])?;
+    for tag_id in tag_ids {
+        tx.execute(
+            "INSERT OR IGNORE INTO chat_session_tags (session_id, tag_id) VALUES (?, ?)",
+            params![session_id, tag_id],
+        )?;
     }
+    tx.commit()?;
+    
+    Ok(())
 }
 
 // ====== Chat Message functions =======
@@ -604,14 +1793,18 @@ pub struct ChatMessage {
     pub role: String,
     pub content: String,
     pub reasoning: Option<String>,
+    pub response_id: Option<String>,
+    // Model that wrote an assistant message, which differs from the session's
+    // model when a fallback answered
+    pub model_id: Option<String>,
     pub timestamp: i64,
 }
 
 // Get all messages for a chat session
 pub fn get_messages_by_session(conn: &Connection, session_id: &str) -> Result<Vec<ChatMessage>> {
     let mut stmt = conn.prepare(
-        "SELECT id, session_id, role, content, reasoning, timestamp FROM chat_messages 
-         WHERE session_id = ? ORDER BY timestamp ASC"
+        "SELECT id, session_id, role, content, reasoning, response_id, model_id, timestamp FROM chat_messages 
+         WHERE session_id = ? ORDER BY timestamp ASC, rowid ASC"
     )?;
     
     let message_iter = stmt.query_map(params![session_id], |row| {
@@ -621,7 +1814,9 @@ pub fn get_messages_by_session(conn: &Connection, session_id: &str) -> Result<Ve
             role: row.get(2)?,
             content: row.get(3)?,
             reasoning: row.get(4)?,
-            timestamp: row.get(5)?,
+            response_id: row.get(5)?,
+            model_id: row.get(6)?,
+            timestamp: row.get(7)?,
         })
     })?;
 
@@ -658,27 +1853,1123 @@ pub fn add_message(
     Ok(id)
 }
 
-
-
-// ====== Settings functions =======
-
-// Get a setting
-pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
-    let mut stmt = conn.prepare("SELECT value FROM app_settings WHERE key = ?")?;
-    
-    let value = stmt.query_row(params![key], |row| {
-        row.get::<_, String>(0)
-    });
+// Record the provider response id of an assistant message
+pub fn set_message_response_id(conn: &Connection, message_id: &str, response_id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE chat_messages SET response_id = ? WHERE id = ?",
+        params![response_id, message_id],
+    )?;
     
-    match value {
-        Ok(v) => Ok(Some(v)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(e),
-    }
+    Ok(())
 }
 
-// Set a setting
-pub fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<()> {
+// Record the model that wrote an assistant message
+pub fn set_message_model(conn: &Connection, message_id: &str, model_id: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE chat_messages SET model_id = ? WHERE id = ?",
+        params![model_id, message_id],
+    )?;
+    
+    Ok(())
+}
+
+// Get the response id of the latest assistant message of a session, if it has
+// one and was written by `model_id`. Responses are stored by the provider that
+// made them, so a reply from another model, such as a fallback, cannot be
+// continued. Messages saved without their model are assumed to match.
+pub fn get_latest_response_id(conn: &Connection, session_id: &str, model_id: Option<&str>) -> Result<Option<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT response_id, model_id IS NULL OR model_id = ?2 FROM chat_messages
+         WHERE session_id = ?1 AND role = 'assistant'
+         ORDER BY timestamp DESC, rowid DESC LIMIT 1"
+    )?;
+    
+    let latest = stmt.query_row(params![session_id, model_id], |row| {
+        Ok((row.get::<_, Option<String>>(0)?, row.get::<_, bool>(1)?))
+    });
+    match latest {
+        Ok((id, same_model)) => Ok(id.filter(|_| same_model)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// ====== Session Summary functions =======
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub content: String,
+    // First and last message covered by the summary
+    pub first_message_id: String,
+    pub last_message_id: String,
+    pub message_count: i64,
+    // Whether the user edited the generated summary
+    pub is_edited: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// Get the summary of a chat session
+pub fn get_session_summary(conn: &Connection, session_id: &str) -> Result<Option<SessionSummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT session_id, content, first_message_id, last_message_id, message_count, is_edited, created_at, updated_at
+         FROM session_summaries WHERE session_id = ?"
+    )?;
+    
+    let summary = stmt.query_row(params![session_id], |row| {
+        Ok(SessionSummary {
+            session_id: row.get(0)?,
+            content: row.get(1)?,
+            first_message_id: row.get(2)?,
+            last_message_id: row.get(3)?,
+            message_count: row.get(4)?,
+            is_edited: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    });
+    
+    match summary {
+        Ok(s) => Ok(Some(s)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Save a generated summary, replacing the session's previous one
+pub fn save_session_summary(
+    conn: &Connection,
+    session_id: &str,
+    content: &str,
+    first_message_id: &str,
+    last_message_id: &str,
+    message_count: i64
+) -> Result<()> {
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "INSERT INTO session_summaries (session_id, content, first_message_id, last_message_id, message_count, is_edited, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, FALSE, ?6, ?6)
+         ON CONFLICT(session_id) DO UPDATE SET content = excluded.content,
+             first_message_id = excluded.first_message_id, last_message_id = excluded.last_message_id,
+             message_count = excluded.message_count, is_edited = FALSE, updated_at = excluded.updated_at",
+        params![session_id, content, first_message_id, last_message_id, message_count, timestamp],
+    )?;
+    
+    Ok(())
+}
+
+// Replace the text of a session's summary with the user's edit.
+// Returns false if the session has no summary.
+pub fn update_session_summary_content(conn: &Connection, session_id: &str, content: &str) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE session_summaries SET content = ?, is_edited = TRUE, updated_at = ? WHERE session_id = ?",
+        params![content, get_current_timestamp(), session_id],
+    )?;
+    
+    Ok(updated > 0)
+}
+
+// Delete a session's summary so requests include the full history again
+pub fn delete_session_summary(conn: &Connection, session_id: &str) -> Result<()> {
+    conn.execute("DELETE FROM session_summaries WHERE session_id = ?", params![session_id])?;
+    
+    Ok(())
+}
+
+// ====== Model Comparison functions =======
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ModelComparison {
+    pub id: String,
+    pub session_id: String,
+    pub candidates: Vec<ComparisonCandidate>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ComparisonCandidate {
+    pub candidate_index: i64,
+    pub provider_id: String,
+    pub model_id: String,
+    pub model_name: String,
+    #[serde(flatten)]
+    pub result: CandidateResult,
+    // Chat message created when the answer was promoted into the session
+    pub promoted_message_id: Option<String>,
+}
+
+// Outcome of one candidate; all fields are empty while it is running
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CandidateResult {
+    pub content: Option<String>,
+    pub reasoning: Option<String>,
+    pub error: Option<String>,
+    pub latency_ms: Option<i64>,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub total_tokens: Option<i64>,
+}
+
+// Start a comparison of the given (provider_id, model_id, model_name)
+// candidates, numbered in order from 0
+pub fn create_model_comparison(
+    conn: &mut Connection,
+    session_id: &str,
+    candidates: &[(String, String, String)]
+) -> Result<ModelComparison> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = get_current_timestamp();
+    
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO model_comparisons (id, session_id, created_at) VALUES (?, ?, ?)",
+        params![id, session_id, timestamp],
+    )?;
+    for (i, (provider_id, model_id, model_name)) in candidates.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO comparison_candidates (comparison_id, candidate_index, provider_id, model_id, model_name)
+             VALUES (?, ?, ?, ?, ?)",
+            params![id, i as i64, provider_id, model_id, model_name],
+        )?;
+    }
+    tx.commit()?;
+    
+    let candidates = candidates.iter()
+        .enumerate()
+        .map(|(i, (provider_id, model_id, model_name))| ComparisonCandidate {
+            candidate_index: i as i64,
+            provider_id: provider_id.clone(),
+            model_id: model_id.clone(),
+            model_name: model_name.clone(),
+            result: CandidateResult::default(),
+            promoted_message_id: None,
+        })
+        .collect();
+    Ok(ModelComparison { id, session_id: session_id.to_string(), candidates, created_at: timestamp })
+}
+
+// Record the outcome of a candidate
+pub fn save_candidate_result(conn: &Connection, comparison_id: &str, candidate_index: i64, result: &CandidateResult) -> Result<()> {
+    conn.execute(
+        "UPDATE comparison_candidates SET content = ?, reasoning = ?, error = ?, latency_ms = ?,
+             prompt_tokens = ?, completion_tokens = ?, total_tokens = ?
+         WHERE comparison_id = ? AND candidate_index = ?",
+        params![
+            result.content, result.reasoning, result.error, result.latency_ms,
+            result.prompt_tokens, result.completion_tokens, result.total_tokens,
+            comparison_id, candidate_index
+        ],
+    )?;
+    
+    Ok(())
+}
+
+fn get_comparison_candidates(conn: &Connection, comparison_id: &str) -> Result<Vec<ComparisonCandidate>> {
+    let mut stmt = conn.prepare(
+        "SELECT candidate_index, provider_id, model_id, model_name, content, reasoning, error, latency_ms,
+                prompt_tokens, completion_tokens, total_tokens, promoted_message_id
+         FROM comparison_candidates WHERE comparison_id = ? ORDER BY candidate_index"
+    )?;
+    
+    let candidates = stmt.query_map(params![comparison_id], |row| {
+        Ok(ComparisonCandidate {
+            candidate_index: row.get(0)?,
+            provider_id: row.get(1)?,
+            model_id: row.get(2)?,
+            model_name: row.get(3)?,
+            result: CandidateResult {
+                content: row.get(4)?,
+                reasoning: row.get(5)?,
+                error: row.get(6)?,
+                latency_ms: row.get(7)?,
+                prompt_tokens: row.get(8)?,
+                completion_tokens: row.get(9)?,
+                total_tokens: row.get(10)?,
+            },
+            promoted_message_id: row.get(11)?,
+        })
+    })?;
+    
+    candidates.collect()
+}
+
+// Get a model comparison with its candidates
+pub fn get_model_comparison(conn: &Connection, id: &str) -> Result<Option<ModelComparison>> {
+    let comparison = conn.query_row(
+        "SELECT id, session_id, created_at FROM model_comparisons WHERE id = ?",
+        params![id],
+        |row| Ok(ModelComparison {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            candidates: Vec::new(),
+            created_at: row.get(2)?,
+        }),
+    );
+    
+    match comparison {
+        Ok(mut c) => {
+            c.candidates = get_comparison_candidates(conn, &c.id)?;
+            Ok(Some(c))
+        },
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Get the model comparisons of a session, oldest first
+pub fn get_session_comparisons(conn: &Connection, session_id: &str) -> Result<Vec<ModelComparison>> {
+    let ids: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT id FROM model_comparisons WHERE session_id = ? ORDER BY created_at, rowid"
+        )?;
+        let rows = stmt.query_map(params![session_id], |row| row.get(0))?;
+        rows.collect::<Result<_>>()?
+    };
+    
+    let mut comparisons = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(comparison) = get_model_comparison(conn, &id)? {
+            comparisons.push(comparison);
+        }
+    }
+    Ok(comparisons)
+}
+
+// Add a candidate's answer to the session as an assistant message and mark
+// the candidate as promoted. Returns the id of the new message.
+pub fn promote_comparison_candidate(
+    conn: &mut Connection,
+    comparison: &ModelComparison,
+    candidate: &ComparisonCandidate
+) -> Result<String> {
+    let tx = conn.transaction()?;
+    let message_id = add_message(
+        &tx,
+        &comparison.session_id,
+        "assistant",
+        candidate.result.content.as_deref().unwrap_or_default(),
+        candidate.result.reasoning.as_deref(),
+    )?;
+    tx.execute(
+        "UPDATE comparison_candidates SET promoted_message_id = ? WHERE comparison_id = ? AND candidate_index = ?",
+        params![message_id, comparison.id, candidate.candidate_index],
+    )?;
+    tx.commit()?;
+    
+    Ok(message_id)
+}
+
+// ====== Memory functions =======
+
+// What a memory applies to: every session, sessions of one assistant, or
+// sessions in a folder and its subfolders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryScope {
+    Global,
+    Assistant,
+    Folder,
+}
+
+impl MemoryScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MemoryScope::Global => "global",
+            MemoryScope::Assistant => "assistant",
+            MemoryScope::Folder => "folder",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "global" => Some(MemoryScope::Global),
+            "assistant" => Some(MemoryScope::Assistant),
+            "folder" => Some(MemoryScope::Folder),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for MemoryScope {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for MemoryScope {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let value = value.as_str()?;
+        MemoryScope::parse(value).ok_or_else(|| FromSqlError::Other(format!("Unknown memory scope: {}", value).into()))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Memory {
+    pub id: String,
+    pub content: String,
+    pub scope: MemoryScope,
+    // Assistant or folder id for scoped memories
+    pub scope_id: Option<String>,
+    // Message the memory was saved from, if any
+    pub source_message_id: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// Filters for listing memories; unset fields do not filter
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct MemoryFilter {
+    pub scope: Option<MemoryScope>,
+    pub scope_id: Option<String>,
+    // Text contained in the memory
+    pub search: Option<String>,
+}
+
+const MEMORY_COLUMNS: &str = "m.id, m.content, m.scope, m.scope_id, m.source_message_id, m.created_at, m.updated_at";
+
+fn memory_from_row(row: &rusqlite::Row) -> Result<Memory> {
+    Ok(Memory {
+        id: row.get(0)?,
+        content: row.get(1)?,
+        scope: row.get(2)?,
+        scope_id: row.get(3)?,
+        source_message_id: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+// Get memories matching a filter, most recently updated first
+pub fn get_memories(conn: &Connection, filter: &MemoryFilter) -> Result<Vec<Memory>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM memories m
+         WHERE (?1 IS NULL OR m.scope = ?1)
+           AND (?2 IS NULL OR m.scope_id = ?2)
+           AND (?3 IS NULL OR m.content LIKE '%' || ?3 || '%')
+         ORDER BY m.updated_at DESC, m.rowid DESC",
+        MEMORY_COLUMNS
+    ))?;
+    
+    let memories = stmt.query_map(params![filter.scope, filter.scope_id, filter.search], memory_from_row)?;
+    memories.collect()
+}
+
+// Get the memories that apply to a conversation: global ones, those of its
+// assistant and those of its folder or any folder above it
+pub fn get_memories_for_context(conn: &Connection, assistant_id: Option<&str>, folder_id: Option<&str>) -> Result<Vec<Memory>> {
+    let mut stmt = conn.prepare(&format!(
+        "WITH RECURSIVE ancestors(id) AS (
+             SELECT ?2 WHERE ?2 IS NOT NULL
+             UNION
+             SELECT f.parent_id FROM session_folders f JOIN ancestors a ON f.id = a.id
+             WHERE f.parent_id IS NOT NULL
+         )
+         SELECT {} FROM memories m
+         WHERE m.scope = 'global'
+            OR (m.scope = 'assistant' AND m.scope_id = ?1)
+            OR (m.scope = 'folder' AND m.scope_id IN (SELECT id FROM ancestors))
+         ORDER BY m.updated_at DESC, m.rowid DESC",
+        MEMORY_COLUMNS
+    ))?;
+    
+    let memories = stmt.query_map(params![assistant_id, folder_id], memory_from_row)?;
+    memories.collect()
+}
+
+// Add a memory
+pub fn add_memory(
+    conn: &Connection,
+    content: &str,
+    scope: MemoryScope,
+    scope_id: Option<&str>,
+    source_message_id: Option<&str>
+) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "INSERT INTO memories (id, content, scope, scope_id, source_message_id, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![id, content, scope, scope_id, source_message_id, timestamp, timestamp],
+    )?;
+    
+    Ok(id)
+}
+
+// Update the text and scope of a memory. Returns false if it does not exist.
+pub fn update_memory(conn: &Connection, id: &str, content: &str, scope: MemoryScope, scope_id: Option<&str>) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE memories SET content = ?, scope = ?, scope_id = ?, updated_at = ? WHERE id = ?",
+        params![content, scope, scope_id, get_current_timestamp(), id],
+    )?;
+    
+    Ok(updated > 0)
+}
+
+// Delete a memory
+pub fn delete_memory(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute("DELETE FROM memories WHERE id = ?", params![id])?;
+    
+    Ok(())
+}
+
+// ====== Knowledge Base functions =======
+
+#[derive(Debug, serde::Serialize)]
+pub struct KbCollection {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    // Provider and model used to embed the collection's chunks and queries
+    pub provider_id: String,
+    pub embedding_model: String,
+    // Chunk length and overlap between chunks, in characters
+    pub chunk_size: i64,
+    pub chunk_overlap: i64,
+    pub document_count: i64,
+    pub chunk_count: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct KbDocument {
+    pub id: String,
+    pub collection_id: String,
+    pub path: String,
+    // SHA-256 of the indexed text, to skip unchanged files
+    pub content_hash: String,
+    pub size_bytes: i64,
+    pub chunk_count: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+// A knowledge base chunk used to answer a message
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Citation {
+    pub chunk_id: String,
+    pub collection_id: String,
+    pub document_id: String,
+    pub document_path: String,
+    pub chunk_index: i64,
+    pub content: String,
+    // Cosine similarity between the chunk and the question
+    pub score: f64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct MessageCitation {
+    pub message_id: String,
+    // 1-based position, matching the [n] markers in the prompt
+    pub rank: i64,
+    #[serde(flatten)]
+    pub citation: Citation,
+}
+
+const KB_COLLECTION_COLUMNS: &str = "c.id, c.name, c.description, c.provider_id, c.embedding_model, c.chunk_size, c.chunk_overlap,
+    (SELECT COUNT(*) FROM kb_documents d WHERE d.collection_id = c.id),
+    (SELECT COUNT(*) FROM kb_chunks k WHERE k.collection_id = c.id),
+    c.created_at, c.updated_at";
+
+fn kb_collection_from_row(row: &rusqlite::Row) -> Result<KbCollection> {
+    Ok(KbCollection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        provider_id: row.get(3)?,
+        embedding_model: row.get(4)?,
+        chunk_size: row.get(5)?,
+        chunk_overlap: row.get(6)?,
+        document_count: row.get(7)?,
+        chunk_count: row.get(8)?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+    })
+}
+
+// Embeddings are stored as little-endian f32 values
+fn embedding_to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn embedding_from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+// Get all knowledge base collections
+pub fn get_kb_collections(conn: &Connection) -> Result<Vec<KbCollection>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM kb_collections c ORDER BY c.name COLLATE NOCASE", KB_COLLECTION_COLUMNS))?;
+    let collections = stmt.query_map([], kb_collection_from_row)?;
+    collections.collect()
+}
+
+// Get a knowledge base collection by ID
+pub fn get_kb_collection_by_id(conn: &Connection, id: &str) -> Result<Option<KbCollection>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM kb_collections c WHERE c.id = ?", KB_COLLECTION_COLUMNS))?;
+    
+    match stmt.query_row(params![id], kb_collection_from_row) {
+        Ok(c) => Ok(Some(c)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Create a knowledge base collection
+pub fn create_kb_collection(
+    conn: &Connection,
+    name: &str,
+    description: Option<&str>,
+    provider_id: &str,
+    embedding_model: &str,
+    chunk_size: i64,
+    chunk_overlap: i64
+) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "INSERT INTO kb_collections (id, name, description, provider_id, embedding_model, chunk_size, chunk_overlap, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![id, name, description, provider_id, embedding_model, chunk_size, chunk_overlap, timestamp, timestamp],
+    )?;
+    
+    Ok(id)
+}
+
+// Rename a knowledge base collection. The embedding model cannot change
+// without re-embedding, so it is fixed at creation.
+pub fn update_kb_collection(conn: &Connection, id: &str, name: &str, description: Option<&str>) -> Result<bool> {
+    let updated = conn.execute(
+        "UPDATE kb_collections SET name = ?, description = ?, updated_at = ? WHERE id = ?",
+        params![name, description, get_current_timestamp(), id],
+    )?;
+    
+    Ok(updated > 0)
+}
+
+// Delete a knowledge base collection with its documents and chunks
+pub fn delete_kb_collection(conn: &mut Connection, id: &str) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM kb_chunks WHERE collection_id = ?", params![id])?;
+    tx.execute("DELETE FROM kb_documents WHERE collection_id = ?", params![id])?;
+    tx.execute("DELETE FROM kb_collections WHERE id = ?", params![id])?;
+    tx.commit()?;
+    
+    Ok(())
+}
+
+// Get the documents of a knowledge base collection
+pub fn get_kb_documents(conn: &Connection, collection_id: &str) -> Result<Vec<KbDocument>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, collection_id, path, content_hash, size_bytes, chunk_count, created_at, updated_at
+         FROM kb_documents WHERE collection_id = ? ORDER BY path"
+    )?;
+    
+    let documents = stmt.query_map(params![collection_id], |row| {
+        Ok(KbDocument {
+            id: row.get(0)?,
+            collection_id: row.get(1)?,
+            path: row.get(2)?,
+            content_hash: row.get(3)?,
+            size_bytes: row.get(4)?,
+            chunk_count: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        })
+    })?;
+    documents.collect()
+}
+
+// Get the content hash of an indexed file, if it is in the collection
+pub fn get_kb_document_hash(conn: &Connection, collection_id: &str, path: &str) -> Result<Option<String>> {
+    match conn.query_row(
+        "SELECT content_hash FROM kb_documents WHERE collection_id = ? AND path = ?",
+        params![collection_id, path],
+        |row| row.get(0),
+    ) {
+        Ok(hash) => Ok(Some(hash)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Store a file's chunks and their embeddings, replacing an earlier version of it
+pub fn replace_kb_document(
+    conn: &mut Connection,
+    collection_id: &str,
+    path: &str,
+    content_hash: &str,
+    size_bytes: i64,
+    chunks: &[(String, Vec<f32>)]
+) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = get_current_timestamp();
+    let tx = conn.transaction()?;
+    
+    tx.execute(
+        "DELETE FROM kb_chunks WHERE document_id IN (SELECT id FROM kb_documents WHERE collection_id = ? AND path = ?)",
+        params![collection_id, path],
+    )?;
+    tx.execute("DELETE FROM kb_documents WHERE collection_id = ? AND path = ?", params![collection_id, path])?;
+    tx.execute(
+        "INSERT INTO kb_documents (id, collection_id, path, content_hash, size_bytes, chunk_count, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![id, collection_id, path, content_hash, size_bytes, chunks.len() as i64, timestamp, timestamp],
+    )?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO kb_chunks (id, document_id, collection_id, chunk_index, content, embedding) VALUES (?, ?, ?, ?, ?, ?)"
+        )?;
+        for (index, (content, embedding)) in chunks.iter().enumerate() {
+            insert.execute(params![
+                Uuid::new_v4().to_string(), id, collection_id, index as i64, content, embedding_to_blob(embedding)
+            ])?;
+        }
+    }
+    tx.execute("UPDATE kb_collections SET updated_at = ? WHERE id = ?", params![timestamp, collection_id])?;
+    tx.commit()?;
+    
+    Ok(id)
+}
+
+// Remove a document and its chunks from its collection
+pub fn delete_kb_document(conn: &mut Connection, id: &str) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM kb_chunks WHERE document_id = ?", params![id])?;
+    tx.execute("DELETE FROM kb_documents WHERE id = ?", params![id])?;
+    tx.commit()?;
+    
+    Ok(())
+}
+
+// Get the id and embedding of every chunk in a collection
+pub fn get_kb_chunk_embeddings(conn: &Connection, collection_id: &str) -> Result<Vec<(String, Vec<f32>)>> {
+    let mut stmt = conn.prepare("SELECT id, embedding FROM kb_chunks WHERE collection_id = ?")?;
+    let chunks = stmt.query_map(params![collection_id], |row| {
+        let blob: Vec<u8> = row.get(1)?;
+        Ok((row.get(0)?, embedding_from_blob(&blob)))
+    })?;
+    chunks.collect()
+}
+
+// Get a chunk with its document, as a citation with the given score
+pub fn get_kb_citation(conn: &Connection, chunk_id: &str, score: f64) -> Result<Option<Citation>> {
+    match conn.query_row(
+        "SELECT k.id, k.collection_id, k.document_id, d.path, k.chunk_index, k.content
+         FROM kb_chunks k JOIN kb_documents d ON d.id = k.document_id WHERE k.id = ?",
+        params![chunk_id],
+        |row| Ok(Citation {
+            chunk_id: row.get(0)?,
+            collection_id: row.get(1)?,
+            document_id: row.get(2)?,
+            document_path: row.get(3)?,
+            chunk_index: row.get(4)?,
+            content: row.get(5)?,
+            score,
+        }),
+    ) {
+        Ok(citation) => Ok(Some(citation)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Store the citations of an assistant message, in prompt order
+pub fn add_message_citations(conn: &Connection, message_id: &str, citations: &[Citation]) -> Result<()> {
+    let mut insert = conn.prepare(
+        "INSERT OR REPLACE INTO chat_message_citations
+         (message_id, rank, chunk_id, collection_id, document_id, document_path, chunk_index, content, score)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )?;
+    for (i, c) in citations.iter().enumerate() {
+        insert.execute(params![
+            message_id, i as i64 + 1, c.chunk_id, c.collection_id, c.document_id, c.document_path, c.chunk_index, c.content, c.score
+        ])?;
+    }
+    
+    Ok(())
+}
+
+// Get the citations of all messages in a session
+pub fn get_session_citations(conn: &Connection, session_id: &str) -> Result<Vec<MessageCitation>> {
+    let mut stmt = conn.prepare(
+        "SELECT c.message_id, c.rank, c.chunk_id, c.collection_id, c.document_id, c.document_path, c.chunk_index, c.content, c.score
+         FROM chat_message_citations c JOIN chat_messages m ON m.id = c.message_id
+         WHERE m.session_id = ? ORDER BY m.timestamp, m.rowid, c.rank"
+    )?;
+    
+    let citations = stmt.query_map(params![session_id], |row| {
+        Ok(MessageCitation {
+            message_id: row.get(0)?,
+            rank: row.get(1)?,
+            citation: Citation {
+                chunk_id: row.get(2)?,
+                collection_id: row.get(3)?,
+                document_id: row.get(4)?,
+                document_path: row.get(5)?,
+                chunk_index: row.get(6)?,
+                content: row.get(7)?,
+                score: row.get(8)?,
+            },
+        })
+    })?;
+    citations.collect()
+}
+
+// ====== Prompt Template functions =======
+
+#[derive(Debug, serde::Serialize)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub content: String,
+    pub variables: Vec<crate::templates::TemplateVariable>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+const PROMPT_TEMPLATE_COLUMNS: &str = "id, name, description, category, tags, content, variables, created_at, updated_at";
+
+fn prompt_template_from_row(row: &rusqlite::Row) -> Result<PromptTemplate> {
+    let tags: String = row.get(4)?;
+    let variables: String = row.get(6)?;
+    Ok(PromptTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        category: row.get(3)?,
+        tags: serde_json::from_str(&tags)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e)))?,
+        content: row.get(5)?,
+        variables: serde_json::from_str(&variables)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(6, rusqlite::types::Type::Text, Box::new(e)))?,
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+    })
+}
+
+fn to_json_text<T: serde::Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+// Get prompt templates, optionally filtered by category and/or tag
+pub fn get_prompt_templates(conn: &Connection, category: Option<&str>, tag: Option<&str>) -> Result<Vec<PromptTemplate>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM prompt_templates
+         WHERE (?1 IS NULL OR category = ?1)
+           AND (?2 IS NULL OR EXISTS (SELECT 1 FROM json_each(prompt_templates.tags) WHERE json_each.value = ?2))
+         ORDER BY category ASC, name ASC",
+        PROMPT_TEMPLATE_COLUMNS
+    ))?;
+
+    let template_iter = stmt.query_map(params![category, tag], prompt_template_from_row)?;
+
+    let mut templates = Vec::new();
+    for template in template_iter {
+        templates.push(template?);
+    }
+    Ok(templates)
+}
+
+// Get a prompt template by ID
+pub fn get_prompt_template_by_id(conn: &Connection, id: &str) -> Result<Option<PromptTemplate>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM prompt_templates WHERE id = ?", PROMPT_TEMPLATE_COLUMNS))?;
+
+    match stmt.query_row(params![id], prompt_template_from_row) {
+        Ok(t) => Ok(Some(t)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Add a new prompt template
+pub fn add_prompt_template(conn: &Connection, template: &crate::templates::PackTemplate) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = get_current_timestamp();
+
+    conn.execute(
+        "INSERT INTO prompt_templates (id, name, description, category, tags, content, variables, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            id,
+            template.name,
+            template.description,
+            template.category,
+            to_json_text(&template.tags)?,
+            template.content,
+            to_json_text(&template.variables)?,
+            timestamp,
+            timestamp
+        ],
+    )?;
+
+    Ok(id)
+}
+
+// Update a prompt template
+pub fn update_prompt_template(conn: &Connection, id: &str, template: &crate::templates::PackTemplate) -> Result<()> {
+    let timestamp = get_current_timestamp();
+
+    conn.execute(
+        "UPDATE prompt_templates SET name = ?, description = ?, category = ?, tags = ?, content = ?, variables = ?, updated_at = ?
+         WHERE id = ?",
+        params![
+            template.name,
+            template.description,
+            template.category,
+            to_json_text(&template.tags)?,
+            template.content,
+            to_json_text(&template.variables)?,
+            timestamp,
+            id
+        ],
+    )?;
+
+    Ok(())
+}
+
+// Delete a prompt template
+pub fn delete_prompt_template(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute("DELETE FROM prompt_templates WHERE id = ?", params![id])?;
+    Ok(())
+}
+
+// ====== API Server functions =======
+
+// Number of request log entries kept; older entries are pruned on insert
+const API_SERVER_LOG_LIMIT: i64 = 1000;
+
+#[derive(Debug, serde::Serialize)]
+pub struct ApiServerLog {
+    pub id: String,
+    pub method: String,
+    pub path: String,
+    pub model: Option<String>,
+    pub model_id: Option<String>,
+    pub provider_id: Option<String>,
+    pub status: u16,
+    pub latency_ms: i64,
+    pub error: Option<String>,
+    pub created_at: i64,
+}
+
+// Record a request handled by the local API server
+#[allow(clippy::too_many_arguments)]
+pub fn add_api_server_log(
+    conn: &Connection,
+    method: &str,
+    path: &str,
+    model: Option<&str>,
+    model_id: Option<&str>,
+    provider_id: Option<&str>,
+    status: u16,
+    latency_ms: i64,
+    error: Option<&str>,
+) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let timestamp = get_current_timestamp();
+    
+    conn.execute(
+        "INSERT INTO api_server_logs (id, method, path, model, model_id, provider_id, status, latency_ms, error, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![id, method, path, model, model_id, provider_id, status, latency_ms, error, timestamp],
+    )?;
+    
+    conn.execute(
+        "DELETE FROM api_server_logs WHERE rowid NOT IN (SELECT rowid FROM api_server_logs ORDER BY rowid DESC LIMIT ?)",
+        params![API_SERVER_LOG_LIMIT],
+    )?;
+    
+    Ok(id)
+}
+
+// Get the most recent local API server requests, newest first
+pub fn get_api_server_logs(conn: &Connection, limit: u32) -> Result<Vec<ApiServerLog>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, method, path, model, model_id, provider_id, status, latency_ms, error, created_at FROM api_server_logs ORDER BY rowid DESC LIMIT ?"
+    )?;
+    
+    let log_iter = stmt.query_map(params![limit], |row| {
+        Ok(ApiServerLog {
+            id: row.get(0)?,
+            method: row.get(1)?,
+            path: row.get(2)?,
+            model: row.get(3)?,
+            model_id: row.get(4)?,
+            provider_id: row.get(5)?,
+            status: row.get(6)?,
+            latency_ms: row.get(7)?,
+            error: row.get(8)?,
+            created_at: row.get(9)?,
+        })
+    })?;
+
+    let mut logs = Vec::new();
+    for log in log_iter {
+        logs.push(log?);
+    }
+    Ok(logs)
+}
+
+// Delete all local API server request logs
+pub fn clear_api_server_logs(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM api_server_logs", [])?;
+    Ok(())
+}
+
+// ====== Health check functions =======
+
+// Number of health checks kept per provider; older ones are pruned on insert
+const HEALTH_CHECK_LIMIT: i64 = 500;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HealthCheck {
+    pub id: String,
+    pub provider_id: String,
+    // "models" or "chat"
+    pub probe: String,
+    // Model the chat probe was sent to
+    pub model: Option<String>,
+    pub ok: bool,
+    pub latency_ms: i64,
+    // Category of the failure, as in provider errors, e.g. "rate_limited"
+    pub error_kind: Option<String>,
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+    pub created_at: i64,
+}
+
+// Record the outcome of a health check probe
+#[allow(clippy::too_many_arguments)]
+pub fn add_health_check(
+    conn: &Connection,
+    provider_id: &str,
+    probe: &str,
+    model: Option<&str>,
+    latency_ms: i64,
+    error_kind: Option<&str>,
+    http_status: Option<u16>,
+    error: Option<&str>,
+) -> Result<HealthCheck> {
+    let check = HealthCheck {
+        id: Uuid::new_v4().to_string(),
+        provider_id: provider_id.to_string(),
+        probe: probe.to_string(),
+        model: model.map(str::to_string),
+        ok: error_kind.is_none(),
+        latency_ms,
+        error_kind: error_kind.map(str::to_string),
+        http_status,
+        error: error.map(str::to_string),
+        created_at: get_current_timestamp(),
+    };
+    
+    conn.execute(
+        "INSERT INTO provider_health_checks (id, provider_id, probe, model, ok, latency_ms, error_kind, http_status, error, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![check.id, check.provider_id, check.probe, check.model, check.ok, check.latency_ms, check.error_kind, check.http_status, check.error, check.created_at],
+    )?;
+    
+    conn.execute(
+        "DELETE FROM provider_health_checks WHERE provider_id = ?1 AND rowid NOT IN
+             (SELECT rowid FROM provider_health_checks WHERE provider_id = ?1 ORDER BY rowid DESC LIMIT ?2)",
+        params![provider_id, HEALTH_CHECK_LIMIT],
+    )?;
+    
+    Ok(check)
+}
+
+// Get the most recent health checks, of one provider or all, newest first
+pub fn get_health_checks(conn: &Connection, provider_id: Option<&str>, limit: u32) -> Result<Vec<HealthCheck>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, provider_id, probe, model, ok, latency_ms, error_kind, http_status, error, created_at FROM provider_health_checks
+         WHERE ?1 IS NULL OR provider_id = ?1 ORDER BY rowid DESC LIMIT ?2"
+    )?;
+    
+    let check_iter = stmt.query_map(params![provider_id, limit], |row| {
+        Ok(HealthCheck {
+            id: row.get(0)?,
+            provider_id: row.get(1)?,
+            probe: row.get(2)?,
+            model: row.get(3)?,
+            ok: row.get(4)?,
+            latency_ms: row.get(5)?,
+            error_kind: row.get(6)?,
+            http_status: row.get(7)?,
+            error: row.get(8)?,
+            created_at: row.get(9)?,
+        })
+    })?;
+
+    let mut checks = Vec::new();
+    for check in check_iter {
+        checks.push(check?);
+    }
+    Ok(checks)
+}
+
+// ====== Fallback chain functions =======
+
+// Get the fallback models of a session or assistant, in the order they are tried
+pub fn get_fallback_chain(conn: &Connection, owner_type: &str, owner_id: &str) -> Result<Vec<String>> {
+    let model_ids = conn.query_row(
+        "SELECT model_ids FROM fallback_chains WHERE owner_type = ? AND owner_id = ?",
+        params![owner_type, owner_id],
+        |row| row.get::<_, String>(0),
+    );
+    
+    match model_ids {
+        Ok(json) => Ok(serde_json::from_str(&json).unwrap_or_default()),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// Set the fallback models of a session or assistant; an empty chain removes it
+pub fn set_fallback_chain(conn: &Connection, owner_type: &str, owner_id: &str, model_ids: &[String]) -> Result<()> {
+    if model_ids.is_empty() {
+        conn.execute(
+            "DELETE FROM fallback_chains WHERE owner_type = ? AND owner_id = ?",
+            params![owner_type, owner_id],
+        )?;
+        return Ok(());
+    }
+    
+    let json = serde_json::to_string(model_ids).unwrap_or_else(|_| "[]".to_string());
+    conn.execute(
+        "INSERT OR REPLACE INTO fallback_chains (owner_type, owner_id, model_ids, updated_at) VALUES (?, ?, ?, ?)",
+        params![owner_type, owner_id, json, get_current_timestamp()],
+    )?;
+    
+    Ok(())
+}
+
+// ====== Settings functions =======
+
+// Get a setting
+pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT value FROM app_settings WHERE key = ?")?;
+    
+    let value = stmt.query_row(params![key], |row| {
+        row.get::<_, String>(0)
+    });
+    
+    match value {
+        Ok(v) => Ok(Some(v)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Set a setting
+pub fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<()> {
     conn.execute(
         "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?, ?)",
         params![key, value],
@@ -687,20 +2978,31 @@ pub fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<()> {
     Ok(())
 }
 
+// Get every setting, ordered by key
+pub fn get_all_settings(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT key, value FROM app_settings ORDER BY key")?;
+    let settings = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    settings.collect()
+}
+
+// Delete a setting
+pub fn delete_setting(conn: &Connection, key: &str) -> Result<()> {
+    conn.execute("DELETE FROM app_settings WHERE key = ?", params![key])?;
+    Ok(())
+}
+
+// In-memory database with the schema, for the tests of every module
+#[cfg(test)]
+pub(crate) fn create_test_db() -> Result<Connection> {
+    let conn = Connection::open(":memory:")?;
+    conn.execute_batch(SCHEMA_SQL)?;
+    Ok(conn)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn create_test_db() -> Result<Connection> {
-        // Create in-memory database for testing
-        let conn = Connection::open(":memory:")?;
-        
-        // Execute schema SQL to create tables
-        conn.execute_batch(SCHEMA_SQL)?;
-        
-        Ok(conn)
-    }
-
     #[test]
     fn test_toggle_model_favorite() {
         let conn = create_test_db().unwrap();
@@ -803,4 +3105,437 @@ mod tests {
         // Should not return an error (SQLite UPDATE with no matching rows succeeds)
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_prompt_templates_filtering() {
+        let conn = create_test_db().unwrap();
+
+        let template = |name: &str, category: &str, tags: &[&str]| crate::templates::PackTemplate {
+            name: name.to_string(),
+            description: None,
+            category: Some(category.to_string()),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            content: "{{text}}".to_string(),
+            variables: Vec::new(),
+        };
+
+        let id = add_prompt_template(&conn, &template("Translate", "language", &["daily", "translation"])).unwrap();
+        add_prompt_template(&conn, &template("Review", "code", &["daily"])).unwrap();
+        add_prompt_template(&conn, &template("Explain", "code", &[])).unwrap();
+
+        assert_eq!(get_prompt_templates(&conn, None, None).unwrap().len(), 3);
+        assert_eq!(get_prompt_templates(&conn, Some("code"), None).unwrap().len(), 2);
+        assert_eq!(get_prompt_templates(&conn, None, Some("daily")).unwrap().len(), 2);
+
+        let filtered = get_prompt_templates(&conn, Some("code"), Some("daily")).unwrap();
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].name, "Review");
+
+        // Tags round-trip through JSON storage
+        let stored = get_prompt_template_by_id(&conn, &id).unwrap().unwrap();
+        assert_eq!(stored.tags, vec!["daily", "translation"]);
+
+        delete_prompt_template(&conn, &id).unwrap();
+        assert!(get_prompt_template_by_id(&conn, &id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_connection_settings_round_trip() {
+        let conn = create_test_db().unwrap();
+        let provider_id = add_provider_with_id(&conn, "test-provider", "Test Provider", "https://api.test.com", "test_key", "test-api-key").unwrap();
+
+        // Providers without stored settings get the defaults
+        let settings = get_connection_settings(&conn, &provider_id).unwrap();
+        assert_eq!(settings, crate::ai::ConnectionSettings::default());
+
+        let mut settings = crate::ai::ConnectionSettings {
+            proxy_url: Some("http://proxy.corp:3128".to_string()),
+            connect_timeout_secs: Some(10),
+            read_timeout_secs: Some(120),
+            ca_cert_path: Some("/etc/ssl/corp-ca.pem".to_string()),
+            ..Default::default()
+        };
+        settings.extra_headers.insert("api-key".to_string(), "gateway".to_string());
+        set_connection_settings(&conn, &provider_id, &settings).unwrap();
+        assert_eq!(get_connection_settings(&conn, &provider_id).unwrap(), settings);
+    }
+
+    #[test]
+    fn test_find_models_by_name_prefers_favorites() {
+        let conn = create_test_db().unwrap();
+        add_provider_with_id(&conn, "first", "First", "https://api.first.com", "first_key", "key").unwrap();
+        add_provider_with_id(&conn, "second", "Second", "https://api.second.com", "second_key", "key").unwrap();
+        add_model(&conn, "first", "shared-model").unwrap();
+        let favorite_id = add_model(&conn, "second", "shared-model").unwrap();
+        add_model(&conn, "second", "other-model").unwrap();
+        toggle_model_favorite(&conn, &favorite_id, true).unwrap();
+
+        let models = find_models_by_name(&conn, "shared-model").unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].provider_id, "second");
+        assert!(find_models_by_name(&conn, "missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sync_provider_models_diff() {
+        use crate::model_info::ModelInfo;
+
+        let mut conn = create_test_db().unwrap();
+        add_provider_with_id(&conn, "openai", "OpenAI", "https://api.openai.com", "openai_key", "key").unwrap();
+        let kept_id = add_model(&conn, "openai", "gpt-4o").unwrap();
+        let old_id = add_model(&conn, "openai", "gpt-3.5-turbo").unwrap();
+        toggle_model_favorite(&conn, &old_id, true).unwrap();
+        let session_id = create_chat_session(&conn, "Old chat", Some(&old_id), None).unwrap();
+
+        let mut listed = ModelInfo::named("gpt-4.1");
+        listed.metadata.context_length = Some(1_047_576);
+        let listing = vec![ModelInfo::named("gpt-4o"), listed];
+        let diff = sync_provider_models(&mut conn, "openai", &listing, true).unwrap();
+        assert_eq!(diff.added, vec!["gpt-4.1"]);
+        assert_eq!(diff.removed, vec!["gpt-3.5-turbo"]);
+        assert_eq!(diff.unchanged, 1);
+
+        let added = get_model_by_id(&conn, &synced_model_id("openai", "gpt-4.1")).unwrap().unwrap();
+        assert_eq!(added.metadata.context_length, Some(1_047_576));
+        assert!(get_model_by_id(&conn, &kept_id).unwrap().unwrap().is_available);
+        // The removed model stays, with its favorite flag and its session
+        let removed = get_model_by_id(&conn, &old_id).unwrap().unwrap();
+        assert!(!removed.is_available && removed.is_favorite);
+        assert_eq!(get_chat_session_by_id(&conn, &session_id).unwrap().unwrap().model_id.as_deref(), Some(old_id.as_str()));
+
+        // Syncing again changes nothing; relisting restores the model
+        let diff = sync_provider_models(&mut conn, "openai", &listing, true).unwrap();
+        assert_eq!(diff, ModelSyncDiff { unchanged: 2, ..ModelSyncDiff::default() });
+        let diff = sync_provider_models(&mut conn, "openai", &[ModelInfo::named("gpt-3.5-turbo")], false).unwrap();
+        assert_eq!(diff.restored, vec!["gpt-3.5-turbo"]);
+        assert_eq!(diff.removed, vec!["gpt-4.1", "gpt-4o"]);
+    }
+
+    #[test]
+    fn test_health_checks_newest_first_per_provider() {
+        let conn = create_test_db().unwrap();
+        add_health_check(&conn, "openai", "models", None, 120, None, None, None).unwrap();
+        let failed = add_health_check(&conn, "openai", "chat", Some("gpt-4o"), 900, Some("rate_limited"), Some(429), Some("Slow down")).unwrap();
+        add_health_check(&conn, "gemini", "models", None, 80, None, None, None).unwrap();
+        assert!(!failed.ok);
+
+        let checks = get_health_checks(&conn, Some("openai"), 10).unwrap();
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].id, failed.id);
+        assert_eq!(checks[0].http_status, Some(429));
+        assert_eq!(get_health_checks(&conn, None, 10).unwrap().len(), 3);
+        assert_eq!(get_health_checks(&conn, None, 1).unwrap()[0].provider_id, "gemini");
+    }
+
+    #[test]
+    fn test_fallback_chain_and_answering_model() {
+        let mut conn = create_test_db().unwrap();
+        let session = create_chat_session(&conn, "Fallbacks", None, None).unwrap();
+        let chain = vec!["openrouter:deepseek/deepseek-chat".to_string(), "ollama:llama3.2".to_string()];
+        set_fallback_chain(&conn, "session", &session, &chain).unwrap();
+        set_fallback_chain(&conn, "assistant", "writer", &chain[1..]).unwrap();
+        assert_eq!(get_fallback_chain(&conn, "session", &session).unwrap(), chain);
+        assert!(get_fallback_chain(&conn, "assistant", "other").unwrap().is_empty());
+
+        let message = add_message(&conn, &session, "assistant", "Hi", None).unwrap();
+        set_message_model(&conn, &message, "ollama:llama3.2").unwrap();
+        let messages = get_messages_by_session(&conn, &session).unwrap();
+        assert_eq!(messages[0].model_id.as_deref(), Some("ollama:llama3.2"));
+
+        // Only the model that wrote the latest reply continues its response
+        set_message_response_id(&conn, &message, "resp_1").unwrap();
+        assert_eq!(get_latest_response_id(&conn, &session, Some("ollama:llama3.2")).unwrap().as_deref(), Some("resp_1"));
+        assert_eq!(get_latest_response_id(&conn, &session, Some("openai:gpt-4o")).unwrap(), None);
+
+        // Clearing a chain removes it, and deleting a session drops its chain
+        set_fallback_chain(&conn, "assistant", "writer", &[]).unwrap();
+        assert!(get_fallback_chain(&conn, "assistant", "writer").unwrap().is_empty());
+        delete_chat_session(&mut conn, &session).unwrap();
+        assert!(get_fallback_chain(&conn, "session", &session).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_list_chat_sessions_filters() {
+        let mut conn = create_test_db().unwrap();
+        let work = create_session_folder(&conn, "Work", None).unwrap();
+        let project = create_session_folder(&conn, "Project", Some(&work)).unwrap();
+        let tag = create_session_tag(&conn, "urgent", Some("#ff0000")).unwrap();
+        assert_eq!(create_session_tag(&conn, "Urgent", None).unwrap(), tag);
+
+        let a = create_chat_session(&conn, "A", None, None).unwrap();
+        let b = create_chat_session(&conn, "B", None, None).unwrap();
+        let c = create_chat_session(&conn, "C", None, None).unwrap();
+        move_session_to_folder(&conn, &a, Some(&work)).unwrap();
+        move_session_to_folder(&conn, &b, Some(&project)).unwrap();
+        set_session_tags(&mut conn, &b, std::slice::from_ref(&tag)).unwrap();
+        set_session_pinned(&conn, &c, true).unwrap();
+        set_session_archived(&conn, &a, true).unwrap();
+
+        let all = list_chat_sessions(&conn, &SessionFilter::default()).unwrap();
+        assert_eq!(all.total, 3);
+        assert_eq!(all.sessions[0].id, c);
+
+        let filter = SessionFilter { folder_id: Some(work.clone()), ..Default::default() };
+        assert_eq!(list_chat_sessions(&conn, &filter).unwrap().total, 1);
+        let filter = SessionFilter { folder_id: Some(work.clone()), include_subfolders: true, ..Default::default() };
+        assert_eq!(list_chat_sessions(&conn, &filter).unwrap().total, 2);
+        let filter = SessionFilter { unfiled: true, ..Default::default() };
+        assert_eq!(list_chat_sessions(&conn, &filter).unwrap().sessions[0].id, c);
+        let filter = SessionFilter { archived: Some(false), tag_id: Some(tag.clone()), ..Default::default() };
+        let tagged = list_chat_sessions(&conn, &filter).unwrap();
+        assert_eq!(tagged.total, 1);
+        assert_eq!(tagged.sessions[0].tag_ids, vec![tag.clone()]);
+
+        let filter = SessionFilter { limit: Some(1), offset: Some(1), ..Default::default() };
+        let page = list_chat_sessions(&conn, &filter).unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.sessions.len(), 1);
+
+        // Archived sessions are not counted in folder totals
+        let folders = get_session_folders(&conn).unwrap();
+        let count = |id: &str| folders.iter().find(|f| f.id == id).unwrap().session_count;
+        assert_eq!(count(&work), 0);
+        assert_eq!(count(&project), 1);
+    }
+
+    #[test]
+    fn test_delete_session_folder_reparents_contents() {
+        let mut conn = create_test_db().unwrap();
+        let work = create_session_folder(&conn, "Work", None).unwrap();
+        let project = create_session_folder(&conn, "Project", Some(&work)).unwrap();
+        let nested = create_session_folder(&conn, "Nested", Some(&project)).unwrap();
+        let session = create_chat_session(&conn, "A", None, None).unwrap();
+        move_session_to_folder(&conn, &session, Some(&project)).unwrap();
+
+        assert!(is_folder_within(&conn, &nested, &work).unwrap());
+        assert!(!is_folder_within(&conn, &work, &nested).unwrap());
+
+        delete_session_folder(&mut conn, &project).unwrap();
+        let session = get_chat_session_by_id(&conn, &session).unwrap().unwrap();
+        assert_eq!(session.folder_id.as_deref(), Some(work.as_str()));
+        let folders = get_session_folders(&conn).unwrap();
+        assert_eq!(folders.len(), 2);
+        assert_eq!(folders.iter().find(|f| f.id == nested).unwrap().parent_id.as_deref(), Some(work.as_str()));
+    }
+
+    #[test]
+    fn test_api_server_logs_newest_first() {
+        let conn = create_test_db().unwrap();
+        add_api_server_log(&conn, "GET", "/v1/models", None, None, None, 200, 3, None).unwrap();
+        add_api_server_log(&conn, "POST", "/v1/chat/completions", Some("gpt-4o"), Some("m1"), Some("openai"), 502, 1, Some("Upstream failed")).unwrap();
+
+        let logs = get_api_server_logs(&conn, 10).unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].status, 502);
+        assert_eq!(logs[0].model.as_deref(), Some("gpt-4o"));
+        assert_eq!(logs[0].model_id.as_deref(), Some("m1"));
+        assert_eq!(get_api_server_logs(&conn, 1).unwrap().len(), 1);
+
+        clear_api_server_logs(&conn).unwrap();
+        assert!(get_api_server_logs(&conn, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_generated_session_name_respects_manual_rename() {
+        let conn = create_test_db().unwrap();
+        let id = create_chat_session(&conn, "New Chat", None, None).unwrap();
+
+        // Saving other fields with the same name keeps the session auto-named
+        update_chat_session(&conn, &id, Some("New Chat"), None, Some("Be brief")).unwrap();
+        assert!(set_generated_session_name(&conn, &id, "Rust lifetimes", false).unwrap());
+        // Saving them without a name keeps the generated title
+        update_chat_session(&conn, &id, None, None, Some("Be concise")).unwrap();
+        let session = get_chat_session_by_id(&conn, &id).unwrap().unwrap();
+        assert_eq!(session.name, "Rust lifetimes");
+        assert!(!session.name_is_custom);
+
+        update_chat_session(&conn, &id, Some("My notes"), None, None).unwrap();
+        assert!(!set_generated_session_name(&conn, &id, "Borrow checker", false).unwrap());
+        let session = get_chat_session_by_id(&conn, &id).unwrap().unwrap();
+        assert_eq!(session.name, "My notes");
+        assert!(session.name_is_custom);
+
+        assert!(set_generated_session_name(&conn, &id, "Borrow checker", true).unwrap());
+        let session = get_chat_session_by_id(&conn, &id).unwrap().unwrap();
+        assert_eq!(session.name, "Borrow checker");
+        assert!(!session.name_is_custom);
+    }
+
+    #[test]
+    fn test_session_summary_round_trip() {
+        let mut conn = create_test_db().unwrap();
+        let session = create_chat_session(&conn, "Long chat", None, None).unwrap();
+        let first = add_message(&conn, &session, "user", "Hi", None).unwrap();
+        let last = add_message(&conn, &session, "assistant", "Hello", None).unwrap();
+
+        assert!(!update_session_summary_content(&conn, &session, "Edited").unwrap());
+        save_session_summary(&conn, &session, "Greetings", &first, &last, 2).unwrap();
+        assert!(update_session_summary_content(&conn, &session, "The user said hi").unwrap());
+        let summary = get_session_summary(&conn, &session).unwrap().unwrap();
+        assert_eq!(summary.content, "The user said hi");
+        assert!(summary.is_edited);
+
+        // Regenerating replaces the edit
+        save_session_summary(&conn, &session, "Greetings exchanged", &first, &last, 2).unwrap();
+        let summary = get_session_summary(&conn, &session).unwrap().unwrap();
+        assert_eq!(summary.content, "Greetings exchanged");
+        assert!(!summary.is_edited);
+
+        delete_chat_session(&mut conn, &session).unwrap();
+        assert!(get_session_summary(&conn, &session).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pool_connections_share_the_database() {
+        let dir = std::env::temp_dir().join(format!("aichat-pro-pool-{}", Uuid::new_v4()));
+        let pool = open_pool(&dir, None).unwrap();
+
+        let writer = pool.get().unwrap();
+        let reader = pool.get().unwrap();
+        let mode: String = reader.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+
+        set_setting(&writer, "pool_test", "1").unwrap();
+        assert_eq!(get_setting(&reader, "pool_test").unwrap().as_deref(), Some("1"));
+
+        drop((writer, reader, pool));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypt_rekey_and_decrypt_database() {
+        let dir = std::env::temp_dir().join(format!("aichat-pro-cipher-{}", Uuid::new_v4()));
+        let pool = open_pool(&dir, None).unwrap();
+        set_setting(&pool.get().unwrap(), "cipher_test", "1").unwrap();
+        assert!(!is_encrypted(&dir));
+
+        // Encrypt with a passphrase
+        let passphrase = DatabaseKey::Passphrase("it's secret".to_string());
+        export_database(&pool.get().unwrap(), &dir, Some(&passphrase)).unwrap();
+        drop(pool);
+        replace_with_export(&dir).unwrap();
+        assert!(is_encrypted(&dir));
+        assert!(!verify_key(&dir, &DatabaseKey::Passphrase("wrong".to_string())));
+        assert!(verify_key(&dir, &passphrase));
+        assert!(open_pool(&dir, None).is_err());
+
+        // Re-key in place with a generated key
+        let pool = open_pool(&dir, Some(passphrase.clone())).unwrap();
+        assert_eq!(get_setting(&pool.get().unwrap(), "cipher_test").unwrap().as_deref(), Some("1"));
+        let raw = DatabaseKey::generate();
+        rekey_database(&pool.get().unwrap(), &raw).unwrap();
+        drop(pool);
+        assert!(!verify_key(&dir, &passphrase));
+        assert!(verify_key(&dir, &raw));
+
+        // Decrypt again
+        let pool = open_pool(&dir, Some(raw)).unwrap();
+        export_database(&pool.get().unwrap(), &dir, None).unwrap();
+        drop(pool);
+        replace_with_export(&dir).unwrap();
+        assert!(!is_encrypted(&dir));
+        let pool = open_pool(&dir, None).unwrap();
+        assert_eq!(get_setting(&pool.get().unwrap(), "cipher_test").unwrap().as_deref(), Some("1"));
+
+        drop(pool);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_model_comparison_results_and_promotion() {
+        let mut conn = create_test_db().unwrap();
+        let session = create_chat_session(&conn, "Eval", None, None).unwrap();
+        add_message(&conn, &session, "user", "What is 2 + 2?", None).unwrap();
+
+        let candidates = vec![
+            ("openai".to_string(), "m1".to_string(), "gpt-4o".to_string()),
+            ("deepseek".to_string(), "m2".to_string(), "deepseek-chat".to_string()),
+        ];
+        let comparison = create_model_comparison(&mut conn, &session, &candidates).unwrap();
+        let result = CandidateResult {
+            content: Some("4".to_string()),
+            latency_ms: Some(850),
+            total_tokens: Some(20),
+            ..Default::default()
+        };
+        save_candidate_result(&conn, &comparison.id, 1, &result).unwrap();
+
+        let stored = get_model_comparison(&conn, &comparison.id).unwrap().unwrap();
+        assert_eq!(stored.candidates.len(), 2);
+        assert!(stored.candidates[0].result.content.is_none());
+        assert_eq!(stored.candidates[1].model_name, "deepseek-chat");
+        assert_eq!(stored.candidates[1].result.latency_ms, Some(850));
+
+        let message_id = promote_comparison_candidate(&mut conn, &stored, &stored.candidates[1]).unwrap();
+        let messages = get_messages_by_session(&conn, &session).unwrap();
+        assert_eq!(messages.last().map(|m| (m.id.as_str(), m.content.as_str())), Some((message_id.as_str(), "4")));
+        let comparisons = get_session_comparisons(&conn, &session).unwrap();
+        assert_eq!(comparisons[0].candidates[1].promoted_message_id.as_deref(), Some(message_id.as_str()));
+
+        delete_chat_session(&mut conn, &session).unwrap();
+        assert!(get_model_comparison(&conn, &comparison.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_memories_for_context_follow_scope() {
+        let mut conn = create_test_db().unwrap();
+        let work = create_session_folder(&conn, "Work", None).unwrap();
+        let project = create_session_folder(&conn, "Project", Some(&work)).unwrap();
+        let other = create_session_folder(&conn, "Other", None).unwrap();
+
+        add_memory(&conn, "Prefers metric units", MemoryScope::Global, None, None).unwrap();
+        add_memory(&conn, "Work uses Postgres", MemoryScope::Folder, Some(&work), None).unwrap();
+        add_memory(&conn, "Other uses MySQL", MemoryScope::Folder, Some(&other), None).unwrap();
+        add_memory(&conn, "Answer in haiku", MemoryScope::Assistant, Some("poet"), None).unwrap();
+
+        let contents = |memories: Vec<Memory>| {
+            let mut contents: Vec<String> = memories.into_iter().map(|m| m.content).collect();
+            contents.sort();
+            contents
+        };
+        assert_eq!(
+            contents(get_memories_for_context(&conn, Some("poet"), Some(&project)).unwrap()),
+            vec!["Answer in haiku", "Prefers metric units", "Work uses Postgres"]
+        );
+        assert_eq!(contents(get_memories_for_context(&conn, None, None).unwrap()), vec!["Prefers metric units"]);
+
+        let filter = MemoryFilter { search: Some("uses".to_string()), ..MemoryFilter::default() };
+        assert_eq!(get_memories(&conn, &filter).unwrap().len(), 2);
+
+        // Deleting a top-level folder makes its memories global
+        delete_session_folder(&mut conn, &other).unwrap();
+        let filter = MemoryFilter { scope: Some(MemoryScope::Global), ..MemoryFilter::default() };
+        assert_eq!(get_memories(&conn, &filter).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_kb_document_replace_and_citations() {
+        let mut conn = create_test_db().unwrap();
+        let collection = create_kb_collection(&conn, "Docs", None, "openai", "text-embedding-3-small", 1000, 200).unwrap();
+        let chunks = vec![("alpha".to_string(), vec![1.0, 0.0]), ("beta".to_string(), vec![0.0, -2.5])];
+        replace_kb_document(&mut conn, &collection, "/docs/a.md", "h1", 10, &chunks).unwrap();
+        // Re-indexing the same path replaces its chunks
+        let document = replace_kb_document(&mut conn, &collection, "/docs/a.md", "h2", 12, &chunks).unwrap();
+
+        let stored = get_kb_collection_by_id(&conn, &collection).unwrap().unwrap();
+        assert_eq!((stored.document_count, stored.chunk_count), (1, 2));
+        assert_eq!(get_kb_document_hash(&conn, &collection, "/docs/a.md").unwrap().as_deref(), Some("h2"));
+        let mut embeddings = get_kb_chunk_embeddings(&conn, &collection).unwrap();
+        embeddings.sort_by(|a, b| a.1[0].total_cmp(&b.1[0]));
+        assert_eq!(embeddings[0].1, vec![0.0, -2.5]);
+
+        let citation = get_kb_citation(&conn, &embeddings[0].0, 0.5).unwrap().unwrap();
+        assert_eq!(citation.content, "beta");
+        assert_eq!(citation.document_path, "/docs/a.md");
+
+        let session = create_chat_session(&conn, "Docs chat", None, None).unwrap();
+        let message = add_message(&conn, &session, "assistant", "See [1]", None).unwrap();
+        add_message_citations(&conn, &message, &[citation]).unwrap();
+        delete_kb_document(&mut conn, &document).unwrap();
+        // Citations keep their copy of the chunk
+        let citations = get_session_citations(&conn, &session).unwrap();
+        assert_eq!(citations.len(), 1);
+        assert_eq!((citations[0].rank, citations[0].citation.content.as_str()), (1, "beta"));
+        assert!(get_kb_chunk_embeddings(&conn, &collection).unwrap().is_empty());
+    }
+}
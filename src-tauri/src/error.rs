@@ -0,0 +1,118 @@
+use serde::Serialize;
+use std::fmt;
+use std::sync::PoisonError;
+
+use crate::ai;
+use crate::catalog;
+use crate::config_export;
+use crate::templates;
+
+// Error returned by every Tauri command. Serialized as an object tagged with
+// `type` so the frontend can tell provider failures apart from local ones, e.g.
+// `{"type": "provider", "kind": "invalid_api_key", "status": 401, ...}`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommandError {
+    Provider(ai::ProviderError),
+    Database { message: String },
+    NotFound { message: String },
+    InvalidInput { message: String },
+    Internal { message: String },
+    // The database is encrypted and waits for its passphrase
+    Locked { message: String },
+    // The app is locked and waits for the app lock passphrase
+    AppLocked { message: String },
+}
+
+impl CommandError {
+    pub fn not_found(message: impl Into<String>) -> Self {
+        CommandError::NotFound { message: message.into() }
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        CommandError::InvalidInput { message: message.into() }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Provider(e) => write!(f, "{}", e),
+            CommandError::Database { message } => write!(f, "Database error: {}", message),
+            CommandError::NotFound { message }
+            | CommandError::InvalidInput { message }
+            | CommandError::Internal { message }
+            | CommandError::Locked { message }
+            | CommandError::AppLocked { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<ai::AIError> for CommandError {
+    fn from(error: ai::AIError) -> Self {
+        CommandError::Provider(error.to_provider_error())
+    }
+}
+
+impl From<rusqlite::Error> for CommandError {
+    fn from(error: rusqlite::Error) -> Self {
+        CommandError::Database { message: error.to_string() }
+    }
+}
+
+// No pooled database connection became free in time
+impl From<r2d2::Error> for CommandError {
+    fn from(error: r2d2::Error) -> Self {
+        CommandError::Database { message: error.to_string() }
+    }
+}
+
+impl From<templates::TemplateError> for CommandError {
+    fn from(error: templates::TemplateError) -> Self {
+        CommandError::InvalidInput { message: error.to_string() }
+    }
+}
+
+impl From<config_export::ConfigExportError> for CommandError {
+    fn from(error: config_export::ConfigExportError) -> Self {
+        match error {
+            config_export::ConfigExportError::Database(e) => e.into(),
+            other => CommandError::InvalidInput { message: other.to_string() },
+        }
+    }
+}
+
+impl From<catalog::CatalogError> for CommandError {
+    fn from(error: catalog::CatalogError) -> Self {
+        match error {
+            catalog::CatalogError::Database(e) => e.into(),
+            other => CommandError::InvalidInput { message: other.to_string() },
+        }
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(error: serde_json::Error) -> Self {
+        CommandError::InvalidInput { message: error.to_string() }
+    }
+}
+
+impl<T> From<PoisonError<T>> for CommandError {
+    fn from(error: PoisonError<T>) -> Self {
+        CommandError::Internal { message: error.to_string() }
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Internal { message }
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        CommandError::Internal { message: message.to_string() }
+    }
+}
@@ -0,0 +1,249 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::ai::{AIClient, AzureSettings, ErrorKind, ProviderError, RetryPolicy};
+use crate::chat;
+use crate::db::{self, AIProvider, HealthCheck};
+
+// Provider health checks: cheap probes whose latency and outcome are kept in
+// a history table for the status view. The models probe lists the provider's
+// models and spends no tokens; the chat probe asks one model for a single
+// token. Scheduled checks only run the models probe.
+
+pub const SCHEDULE_MINUTES_SETTING: &str = "health_check_minutes";
+pub const LAST_RUN_SETTING: &str = "health_check_last_run_at";
+
+// Checks a provider's summary is computed from
+const SUMMARY_WINDOW: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Probe {
+    Models,
+    Chat,
+}
+
+impl Probe {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Probe::Models => "models",
+            Probe::Chat => "chat",
+        }
+    }
+}
+
+// Outcome of one probe before it is recorded
+#[derive(Debug)]
+pub struct ProbeResult {
+    pub latency_ms: i64,
+    pub error: Option<ProviderError>,
+}
+
+// Run a probe against a provider. Retries are turned off so the latency and
+// error are those of a single request.
+pub async fn run_probe(
+    mut client: AIClient,
+    provider: &AIProvider,
+    azure_settings: &AzureSettings,
+    probe: Probe,
+    model: Option<&str>,
+) -> ProbeResult {
+    client.set_retry_policy(RetryPolicy { max_retries: 0, ..RetryPolicy::default() });
+
+    let started = Instant::now();
+    let result = match (probe, model) {
+        (Probe::Models, _) => chat::list_models(&client, provider, azure_settings).await.map(|_| ()),
+        (Probe::Chat, Some(model)) => chat::ping_model(&client, provider, azure_settings, model).await,
+        (Probe::Chat, None) => Err(ProviderError::new(
+            ErrorKind::InvalidRequest,
+            None,
+            "The chat probe needs a model",
+        ).into()),
+    };
+    ProbeResult {
+        latency_ms: started.elapsed().as_millis() as i64,
+        error: result.err().map(|e| e.to_provider_error()),
+    }
+}
+
+// Store a probe's outcome in the history
+pub fn record(
+    conn: &Connection,
+    provider_id: &str,
+    probe: Probe,
+    model: Option<&str>,
+    result: &ProbeResult,
+) -> rusqlite::Result<HealthCheck> {
+    let error_kind = result.error.as_ref().and_then(|e| {
+        serde_json::to_value(e.kind).ok().and_then(|v| v.as_str().map(str::to_string))
+    });
+    db::add_health_check(
+        conn,
+        provider_id,
+        probe.as_str(),
+        model,
+        result.latency_ms,
+        error_kind.as_deref(),
+        result.error.as_ref().and_then(|e| e.status),
+        result.error.as_ref().map(|e| e.message.as_str()),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthState {
+    // Never checked
+    Unknown,
+    Up,
+    // The last check passed but a recent one failed
+    Degraded,
+    Down,
+}
+
+// Health of a provider for the status view, from its recent checks
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub provider_id: String,
+    pub provider_name: String,
+    pub state: HealthState,
+    pub last_check: Option<HealthCheck>,
+    // Share of recent checks that passed, 0.0 - 1.0
+    pub success_rate: Option<f64>,
+    // Average latency of the recent checks that passed
+    pub avg_latency_ms: Option<i64>,
+    pub recent_checks: usize,
+}
+
+// Summarize a provider's checks, given newest first
+pub fn summarize(provider: &AIProvider, checks: Vec<HealthCheck>) -> ProviderHealth {
+    let passed: Vec<&HealthCheck> = checks.iter().filter(|c| c.ok).collect();
+    let state = match checks.first() {
+        None => HealthState::Unknown,
+        Some(last) if !last.ok => HealthState::Down,
+        Some(_) if passed.len() < checks.len() => HealthState::Degraded,
+        Some(_) => HealthState::Up,
+    };
+    let success_rate = (!checks.is_empty()).then(|| passed.len() as f64 / checks.len() as f64);
+    let avg_latency_ms = (!passed.is_empty())
+        .then(|| passed.iter().map(|c| c.latency_ms).sum::<i64>() / passed.len() as i64);
+
+    ProviderHealth {
+        provider_id: provider.id.clone(),
+        provider_name: provider.name.clone(),
+        state,
+        success_rate,
+        avg_latency_ms,
+        recent_checks: checks.len(),
+        last_check: checks.into_iter().next(),
+    }
+}
+
+// Health of every provider
+pub fn load_status(conn: &Connection) -> rusqlite::Result<Vec<ProviderHealth>> {
+    let mut status = Vec::new();
+    for provider in db::get_all_providers(conn)? {
+        let checks = db::get_health_checks(conn, Some(&provider.id), SUMMARY_WINDOW)?;
+        status.push(summarize(&provider, checks));
+    }
+    Ok(status)
+}
+
+// Minutes between scheduled checks; 0, the default, turns them off
+pub fn schedule_minutes(conn: &Connection) -> rusqlite::Result<u32> {
+    Ok(db::get_setting(conn, SCHEDULE_MINUTES_SETTING)?
+        .and_then(|m| m.parse().ok())
+        .unwrap_or(0))
+}
+
+// Whether scheduled checks are due, recording the run when they are
+pub fn start_scheduled_run(conn: &Connection, now: i64) -> rusqlite::Result<bool> {
+    let minutes = schedule_minutes(conn)?;
+    if minutes == 0 {
+        return Ok(false);
+    }
+    let last_run: Option<i64> = db::get_setting(conn, LAST_RUN_SETTING)?.and_then(|t| t.parse().ok());
+    if last_run.is_some_and(|last| now - last < i64::from(minutes) * 60) {
+        return Ok(false);
+    }
+    db::set_setting(conn, LAST_RUN_SETTING, &now.to_string())?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn provider(api_url: &str) -> AIProvider {
+        AIProvider {
+            id: "custom-test".to_string(),
+            name: "Test".to_string(),
+            api_url: api_url.to_string(),
+            api_key_name: "custom-test_api_key".to_string(),
+            api_key: Some("key".to_string()),
+            chat_path: None,
+            models_path: None,
+            is_managed: false,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    // Answer the models endpoint and rate-limit chat requests, for `requests` requests
+    fn serve(requests: usize) -> String {
+        test_support::serve(requests, |request| {
+            if request.starts_with("GET") && request.contains("/models") {
+                ("200 OK", r#"{"data": [{"id": "gpt-4o"}]}"#.to_string())
+            } else {
+                ("429 Too Many Requests", r#"{"error": {"message": "Rate limit reached", "code": "rate_limit_exceeded"}}"#.to_string())
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_probes_are_recorded_with_error_category() {
+        let provider = provider(&serve(2));
+        let azure = AzureSettings::default();
+        let conn = db::create_test_db().unwrap();
+
+        let models = run_probe(AIClient::new(), &provider, &azure, Probe::Models, None).await;
+        assert!(models.error.is_none());
+        record(&conn, &provider.id, Probe::Models, None, &models).unwrap();
+
+        let chat = run_probe(AIClient::new(), &provider, &azure, Probe::Chat, Some("gpt-4o")).await;
+        let check = record(&conn, &provider.id, Probe::Chat, Some("gpt-4o"), &chat).unwrap();
+        assert!(!check.ok);
+        assert_eq!(check.error_kind.as_deref(), Some("rate_limited"));
+        assert_eq!(check.http_status, Some(429));
+
+        let health = summarize(&provider, db::get_health_checks(&conn, Some(&provider.id), 10).unwrap());
+        assert_eq!(health.state, HealthState::Down);
+        assert_eq!(health.success_rate, Some(0.5));
+        assert_eq!(health.recent_checks, 2);
+    }
+
+    #[test]
+    fn test_summary_states_and_schedule() {
+        let conn = db::create_test_db().unwrap();
+        let provider = provider("http://localhost");
+        assert_eq!(summarize(&provider, Vec::new()).state, HealthState::Unknown);
+
+        let ok = |latency_ms| ProbeResult { latency_ms, error: None };
+        record(&conn, &provider.id, Probe::Models, None, &ok(100)).unwrap();
+        let failed = ProbeResult { latency_ms: 5000, error: Some(ProviderError::new(ErrorKind::Timeout, None, "timed out")) };
+        record(&conn, &provider.id, Probe::Models, None, &failed).unwrap();
+        record(&conn, &provider.id, Probe::Models, None, &ok(300)).unwrap();
+
+        let health = summarize(&provider, db::get_health_checks(&conn, Some(&provider.id), 10).unwrap());
+        assert_eq!(health.state, HealthState::Degraded);
+        assert_eq!(health.avg_latency_ms, Some(200));
+
+        // Scheduled checks are off until an interval is set
+        assert!(!start_scheduled_run(&conn, 1000).unwrap());
+        db::set_setting(&conn, SCHEDULE_MINUTES_SETTING, "10").unwrap();
+        assert!(start_scheduled_run(&conn, 1000).unwrap());
+        assert!(!start_scheduled_run(&conn, 1000 + 9 * 60).unwrap());
+        assert!(start_scheduled_run(&conn, 1000 + 10 * 60).unwrap());
+    }
+}
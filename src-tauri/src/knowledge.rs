@@ -0,0 +1,282 @@
+use crate::ai::{self, AIClient, AIError, ChatMessage};
+use crate::chat;
+use crate::db::{self, AIProvider};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Local knowledge bases: files are split into overlapping chunks, embedded
+// with the collection's provider and ranked against the question at chat time
+
+// Default chunk length and overlap between chunks, in characters
+pub const DEFAULT_CHUNK_SIZE: usize = 1000;
+pub const DEFAULT_CHUNK_OVERLAP: usize = 200;
+
+// Settings key holding the number of chunks added to a request
+pub const TOP_K_SETTING: &str = "kb_top_k";
+pub const DEFAULT_TOP_K: usize = 5;
+
+// Largest file that is indexed, in bytes
+pub const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+// Chunks embedded per provider request
+const EMBEDDING_BATCH_SIZE: usize = 32;
+
+// Extensions of files picked up when indexing a directory. Files chosen
+// directly are indexed whatever their extension, as long as they are text.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "mdx", "rst", "adoc", "org", "tex", "csv", "tsv", "json", "yaml", "yml",
+    "toml", "ini", "cfg", "xml", "html", "htm", "css", "scss", "sql", "sh", "log",
+    "rs", "py", "js", "jsx", "ts", "tsx", "go", "java", "kt", "c", "h", "cpp", "hpp", "cs", "rb",
+    "php", "swift",
+];
+
+// Split text into chunks of at most `chunk_size` characters, each starting
+// `overlap` characters before the end of the previous one. Chunks end at a
+// paragraph, line, sentence or word break when one falls in their second half.
+pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let size = chunk_size.max(1);
+    let overlap = overlap.min(size / 2);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + size).min(chars.len());
+        if end < chars.len() {
+            let window = start + size / 2;
+            if let Some(offset) = break_point(&chars[window..end]) {
+                end = window + offset;
+            }
+        }
+
+        let chunk: String = chars[start..end].iter().collect();
+        if !chunk.trim().is_empty() {
+            chunks.push(chunk.trim().to_string());
+        }
+        if end >= chars.len() {
+            break;
+        }
+        start = end.saturating_sub(overlap).max(start + 1);
+    }
+    chunks
+}
+
+// Position just after the best break in `window`, preferring paragraph
+// breaks over line breaks over sentence ends over spaces
+fn break_point(window: &[char]) -> Option<usize> {
+    let after = |i: usize| i + 1;
+    let paragraph = window.windows(2).rposition(|w| w[0] == '\n' && w[1] == '\n').map(|i| i + 2);
+    paragraph
+        .or_else(|| window.iter().rposition(|c| *c == '\n').map(after))
+        .or_else(|| window.iter().rposition(|c| matches!(c, '.' | '!' | '?' | '。' | '！' | '？')).map(after))
+        .or_else(|| window.iter().rposition(|c| c.is_whitespace()).map(after))
+}
+
+// Cosine similarity of two vectors, 0 for mismatched or zero vectors
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+// The `k` chunks most similar to the query, best first
+pub fn rank_chunks(query: &[f32], chunks: Vec<(String, Vec<f32>)>, k: usize) -> Vec<(String, f32)> {
+    let mut scored: Vec<(String, f32)> = chunks.into_iter()
+        .map(|(id, embedding)| {
+            let score = cosine_similarity(query, &embedding);
+            (id, score)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(k);
+    scored
+}
+
+// Files to index for the given paths: files as given, and text files found
+// in directories (skipping hidden entries and symlinked directories, which
+// may loop), sorted and without duplicates
+pub fn collect_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                walk(&path, files);
+            } else if path.is_file() && path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| TEXT_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            walk(path, &mut files);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+// Read a file to index, rejecting large and non-text files
+pub fn read_text_file(path: &Path) -> Result<String, String> {
+    let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+    if metadata.len() > MAX_FILE_BYTES {
+        return Err(format!("File is larger than {} MB", MAX_FILE_BYTES / (1024 * 1024)));
+    }
+
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|_| "Not a UTF-8 text file".to_string())
+}
+
+// SHA-256 of a file's text, hex encoded
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+// Embed texts with a provider's embeddings API, in batches
+pub async fn embed_texts(
+    client: &AIClient,
+    provider: &AIProvider,
+    model: &str,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, AIError> {
+    let provider_type = chat::determine_provider_type(&provider.id, &provider.api_url, &provider.name);
+    let api_url = provider.api_url.as_str();
+    let api_key = || provider.api_key.as_deref()
+        .ok_or_else(|| AIError::Provider(ai::ProviderError::missing_api_key()));
+
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(EMBEDDING_BATCH_SIZE) {
+        let batch_embeddings = match provider_type.as_str() {
+            "azure" => {
+                return Err(AIError::InvalidConfig("Embeddings are not supported for Azure OpenAI providers".to_string()));
+            },
+            "gemini" => client.gemini_embeddings(api_url, api_key()?, model, batch).await?,
+            // Local Ollama servers do not need a key
+            "ollama" => client.ollama_embeddings(api_url, model, batch).await?,
+            _ => client.openai_embeddings(api_url, api_key()?, model, batch).await?,
+        };
+        embeddings.extend(batch_embeddings);
+    }
+    Ok(embeddings)
+}
+
+// Add retrieved chunks to the system prompt of a request, numbered so the
+// model can cite them
+pub fn inject_context(messages: Vec<ChatMessage>, citations: &[db::Citation]) -> Vec<ChatMessage> {
+    if citations.is_empty() {
+        return messages;
+    }
+
+    let excerpts = citations.iter()
+        .enumerate()
+        .map(|(i, c)| format!("[{}] {} (part {})\n{}", i + 1, c.document_path, c.chunk_index + 1, c.content))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let section = format!(
+        "Excerpts from the user's documents that may help answer. Use them when relevant \
+         and cite them with their number, like [1].\n\n{}",
+        excerpts
+    );
+    chat::append_to_system_prompt(messages, &section)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_text_breaks_at_boundaries_with_overlap() {
+        let text = "First paragraph is here.\n\nSecond paragraph follows it. And more words to fill.";
+        let chunks = chunk_text(text, 40, 10);
+
+        assert_eq!(chunks[0], "First paragraph is here.");
+        assert!(chunks.iter().all(|c| c.chars().count() <= 40));
+        // Every word survives chunking
+        for word in text.split_whitespace() {
+            assert!(chunks.iter().any(|c| c.contains(word)), "missing {}", word);
+        }
+
+        assert_eq!(chunk_text("short", 100, 20), vec!["short"]);
+        assert!(chunk_text("  \n ", 100, 20).is_empty());
+        // Text without breaks is still split by length
+        assert_eq!(chunk_text(&"x".repeat(25), 10, 0).len(), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_files_skips_symlinked_directories() {
+        let dir = std::env::temp_dir().join(format!("aichat-pro-kb-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("docs/notes.md"), "notes").unwrap();
+        fs::write(dir.join("image.png"), "not text").unwrap();
+        // A link back to the root would recurse forever if followed
+        std::os::unix::fs::symlink(&dir, dir.join("docs/loop")).unwrap();
+
+        assert_eq!(collect_files(std::slice::from_ref(&dir)), vec![dir.join("docs/notes.md")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rank_chunks_by_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+
+        let chunks = vec![
+            ("opposite".to_string(), vec![-1.0, 0.0]),
+            ("close".to_string(), vec![0.9, 0.1]),
+            ("orthogonal".to_string(), vec![0.0, 1.0]),
+        ];
+        let ranked = rank_chunks(&[1.0, 0.0], chunks, 2);
+        let ids: Vec<&str> = ranked.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["close", "orthogonal"]);
+    }
+
+    #[test]
+    fn test_inject_context_numbers_excerpts() {
+        let citation = db::Citation {
+            chunk_id: "c1".to_string(),
+            collection_id: "kb".to_string(),
+            document_id: "d1".to_string(),
+            document_path: "/docs/setup.md".to_string(),
+            chunk_index: 2,
+            content: "Run make setup".to_string(),
+            score: 0.8,
+        };
+        let messages = vec![ChatMessage { role: "user".to_string(), content: "How do I set up?".to_string() }];
+
+        let injected = inject_context(messages, &[citation]);
+        assert_eq!(injected[0].role, "system");
+        assert!(injected[0].content.ends_with("[1] /docs/setup.md (part 3)\nRun make setup"));
+        assert_eq!(content_hash("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
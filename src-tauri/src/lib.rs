@@ -6,6 +6,18 @@
 pub mod ai;
 pub mod db;
 pub mod credentials;
+pub mod templates;
+pub mod chat;
+pub mod knowledge;
+pub mod app_lock;
+pub mod profiles;
+pub mod config_export;
+pub mod catalog;
+pub mod model_info;
+pub mod health;
+pub mod api_server_settings;
+#[cfg(test)]
+mod test_support;
 
 // Bindings for mobile
 #[cfg(any(target_os = "android", target_os = "ios"))]
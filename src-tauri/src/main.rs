@@ -1,23 +1,108 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::Mutex;
-use std::path::PathBuf;
-use std::fs;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use rusqlite::Connection;
-use tauri::State;
+use std::sync::Arc;
+use tauri::{Emitter, Manager, State};
 use serde::Deserialize;
 use uuid::Uuid;
 
 mod db;
 mod ai;
+mod templates;
+mod error;
+mod chat;
+mod knowledge;
+mod api_server;
+mod api_server_settings;
+mod credentials;
+mod app_lock;
+mod profiles;
+mod config_export;
+mod catalog;
+mod model_info;
+mod health;
+#[cfg(test)]
+mod test_support;
+
+use error::CommandError;
 
 // Structures for Tauri command parameters and responses
 
 // App state
 struct AppState {
-    db_conn: Mutex<Connection>,
+    // None while an encrypted database waits to be unlocked
+    db: RwLock<Option<db::DbPool>>,
+    // Held shared by every database task and exclusively while the database
+    // file is re-keyed or replaced, so no connection outlives the old file
+    db_access: Arc<tokio::sync::RwLock<()>>,
+    // Data directory of the open profile
+    location: RwLock<profiles::DataLocation>,
+    // Whether the data root was given by flag or environment, overriding the setting
+    data_root_overridden: bool,
     ai_client: Mutex<ai::AIClient>,
+    api_server: Mutex<Option<api_server::ServerHandle>>,
+    app_lock: Mutex<app_lock::AppLock>,
+    // Models from each provider's last listing, by provider id
+    model_listings: Mutex<HashMap<String, Vec<model_info::ModelInfo>>>,
+}
+
+impl AppState {
+    // The open database's pool, failing while it is locked
+    fn pool(&self) -> Result<db::DbPool, CommandError> {
+        self.db.read()?.clone().ok_or_else(|| CommandError::Locked {
+            message: "The database is encrypted and has not been unlocked".to_string(),
+        })
+    }
+
+    fn data_dir(&self) -> Result<PathBuf, CommandError> {
+        Ok(self.location.read()?.dir.clone())
+    }
+
+    // Check out a pooled connection for short synchronous work
+    fn conn(&self) -> Result<DbConnection, CommandError> {
+        let access = self.db_access.clone().try_read_owned().map_err(|_| CommandError::Locked {
+            message: "The database is being re-keyed".to_string(),
+        })?;
+        let conn = self.pool()?.get()?;
+        Ok(DbConnection { conn, _access: access })
+    }
+
+    // Run database work with a pooled connection on a blocking thread, so
+    // slow queries and long transactions do not hold up other commands
+    async fn with_db<T, F>(&self, f: F) -> Result<T, CommandError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, CommandError> + Send + 'static,
+    {
+        let access = self.db_access.clone().read_owned().await;
+        let pool = self.pool()?;
+        tauri::async_runtime::spawn_blocking(move || {
+            let _access = access;
+            let mut conn = pool.get()?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| CommandError::Internal { message: format!("Database task failed: {}", e) })?
+    }
+}
+
+// A pooled connection that keeps the database from being re-keyed while in use
+struct DbConnection {
+    conn: db::PooledConnection,
+    _access: tokio::sync::OwnedRwLockReadGuard<()>,
+}
+
+impl std::ops::Deref for DbConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
 }
 
 #[derive(Deserialize)]
@@ -26,6 +111,8 @@ struct ProviderRequest {
     api_url: String,
     api_key: String,
     id_prefix: Option<String>,
+    chat_path: Option<String>,
+    models_path: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -34,6 +121,21 @@ struct ProviderUpdateRequest {
     name: String,
     api_url: String,
     api_key: Option<String>,
+    // Empty strings restore the default paths; omitted paths are left unchanged
+    chat_path: Option<String>,
+    models_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ConnectionSettingsRequest {
+    provider_id: String,
+    settings: ai::ConnectionSettings,
+}
+
+#[derive(Deserialize)]
+struct AzureSettingsRequest {
+    provider_id: String,
+    settings: ai::AzureSettings,
 }
 
 #[derive(Deserialize)]
@@ -45,7 +147,8 @@ struct ModelRequest {
 #[derive(Deserialize)]
 struct ChatSessionRequest {
     id: Option<String>,
-    name: String,
+    // Required to create a session; updates keep the current name without it
+    name: Option<String>,
     model_id: Option<String>,
     system_prompt: Option<String>,
 }
@@ -59,11 +162,51 @@ struct ChatSessionUpdateRequest {
     system_prompt: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct SessionFolderRequest {
+    id: Option<String>,
+    name: String,
+    parent_id: Option<String>,
+    sort_order: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct SessionTagRequest {
+    id: Option<String>,
+    name: String,
+    color: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SessionTagsRequest {
+    session_id: String,
+    tag_ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SessionFlagRequest {
+    session_id: String,
+    value: bool,
+}
+
+#[derive(Deserialize)]
+struct MoveSessionRequest {
+    session_id: String,
+    folder_id: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct ChatMessageRequest {
     session_id: String,
     role: String,
     content: String,
+    reasoning: Option<String>,
+    // Provider response id of an assistant message (Responses API)
+    response_id: Option<String>,
+    // Model that wrote an assistant message, as reported by send_chat_request
+    model_id: Option<String>,
+    // Knowledge base chunks the assistant message was answered from
+    citations: Option<Vec<db::Citation>>,
 }
 
 
@@ -79,6 +222,162 @@ struct ChatRequest {
     provider_id: String,
     model_id: String,
     messages: Vec<ai::ChatMessage>,
+    // Session the request belongs to; applies its summary and lets Responses API
+    // models continue from the stored response
+    session_id: Option<String>,
+    // Assistant the session uses, selecting its memories
+    assistant_id: Option<String>,
+    // Knowledge base collections to retrieve context from
+    collection_ids: Option<Vec<String>>,
+}
+
+// Reply to a chat request, with the knowledge base chunks it was given and
+// the model that answered, which is a fallback when the requested one failed
+#[derive(serde::Serialize)]
+struct ChatResponse {
+    #[serde(flatten)]
+    response: ai::AIResponse,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    citations: Vec<db::Citation>,
+    provider_id: String,
+    model_id: String,
+    model_name: String,
+    // Switches made before a model answered, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fallbacks: Vec<ModelFallback>,
+}
+
+#[derive(Deserialize)]
+struct FallbackChainRequest {
+    // "session" or "assistant"
+    owner_type: String,
+    owner_id: String,
+    // Models tried in order after the requested one fails; empty removes the chain
+    model_ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CompareModelsRequest {
+    session_id: String,
+    candidates: Vec<CompareCandidateRequest>,
+}
+
+#[derive(Deserialize)]
+struct CompareCandidateRequest {
+    provider_id: String,
+    model_id: String,
+}
+
+#[derive(Deserialize)]
+struct PromoteCandidateRequest {
+    comparison_id: String,
+    candidate_index: i64,
+}
+
+#[derive(Deserialize)]
+struct KbCollectionRequest {
+    id: Option<String>,
+    name: String,
+    description: Option<String>,
+    // Only used when creating a collection
+    provider_id: Option<String>,
+    embedding_model: Option<String>,
+    chunk_size: Option<usize>,
+    chunk_overlap: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct KbSearchRequest {
+    collection_ids: Vec<String>,
+    query: String,
+    top_k: Option<usize>,
+}
+
+// Payload of `kb-index-progress` events
+#[derive(Clone, serde::Serialize)]
+struct KbIndexProgress {
+    collection_id: String,
+    path: String,
+    done: usize,
+    total: usize,
+}
+
+#[derive(Default, serde::Serialize)]
+struct KbIndexReport {
+    indexed: usize,
+    unchanged: usize,
+    failed: Vec<KbIndexFailure>,
+}
+
+#[derive(serde::Serialize)]
+struct KbIndexFailure {
+    path: String,
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct MemoryRequest {
+    id: Option<String>,
+    content: String,
+    scope: db::MemoryScope,
+    scope_id: Option<String>,
+    source_message_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SessionSummaryRequest {
+    session_id: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ModelApiTypeRequest {
+    model_id: String,
+    api_type: ai::ApiType,
+}
+
+// Payload of `chat-stream-delta` events
+#[derive(Clone, serde::Serialize)]
+struct ChatStreamDelta {
+    session_id: Option<String>,
+    content: Option<String>,
+    reasoning: Option<String>,
+}
+
+// Payload of `chat-model-fallback` events, sent when a model failed with a
+// retryable error and the next model of the fallback chain is tried. Deltas
+// already streamed by the failed model should be discarded.
+#[derive(Clone, serde::Serialize)]
+struct ModelFallback {
+    session_id: Option<String>,
+    from_model_id: String,
+    from_model_name: String,
+    to_model_id: String,
+    to_model_name: String,
+    error: ai::ProviderError,
+}
+
+// Payload of `compare-stream-delta` events
+#[derive(Clone, serde::Serialize)]
+struct CompareStreamDelta {
+    comparison_id: String,
+    candidate_index: i64,
+    content: Option<String>,
+    reasoning: Option<String>,
+}
+
+// Payload of `compare-candidate-finished` events
+#[derive(Clone, serde::Serialize)]
+struct CompareCandidateFinished {
+    comparison_id: String,
+    candidate: db::ComparisonCandidate,
+}
+
+// Payload of `session-title-updated` events
+#[derive(Clone, serde::Serialize)]
+struct SessionTitleUpdated {
+    session_id: String,
+    name: String,
 }
 
 #[derive(Deserialize)]
@@ -93,36 +392,91 @@ struct ToggleFavoriteRequest {
     is_favorite: bool,
 }
 
-// Get the application data directory
-fn get_app_data_dir() -> Result<PathBuf, String> {
-    let home = dirs::home_dir().ok_or_else(|| "Could not find home directory".to_string())?;
-    let data_dir = home.join(".aichat-pro");
-    
-    // Ensure directory exists
-    fs::create_dir_all(&data_dir)
-        .map_err(|e| format!("Could not create app data directory: {}", e))?;
-    
-    Ok(data_dir)
+#[derive(Deserialize)]
+struct PromptTemplateUpdateRequest {
+    id: String,
+    template: templates::PackTemplate,
+}
+
+#[derive(Deserialize)]
+struct RenderTemplateRequest {
+    template_id: String,
+    #[serde(default)]
+    values: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct InsertTemplateRequest {
+    session_id: String,
+    template_id: String,
+    #[serde(default)]
+    values: HashMap<String, String>,
+}
+
+// Initialize the database and its connection pool. An encrypted database is
+// opened with the key from the OS keyring, or left locked until the user
+// enters its passphrase.
+fn init_database(data_dir: &Path) -> Result<Option<db::DbPool>, String> {
+    if !db::is_encrypted(data_dir) {
+        return db::open_pool(data_dir, None).map(Some);
+    }
+    match keyring_database_key() {
+        Some(key) if db::verify_key(data_dir, &key) => db::open_pool(data_dir, Some(key)).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// Database key kept in the OS keyring, if there is one
+fn keyring_database_key() -> Option<db::DatabaseKey> {
+    credentials::get_database_key().ok().map(db::DatabaseKey::Raw)
+}
+
+// Error reported when a provider has no API key configured
+fn missing_api_key_error() -> CommandError {
+    CommandError::Provider(ai::ProviderError::missing_api_key())
+}
+
+// Get a copy of the AI client using the provider's connection settings and endpoint paths
+async fn provider_client(app_state: &AppState, provider_id: &str) -> Result<ai::AIClient, CommandError> {
+    let id = provider_id.to_string();
+    let (settings, endpoints) = app_state.with_db(move |conn| {
+        let endpoints = match db::get_provider_by_id(conn, &id)? {
+            Some(p) => ai::OpenAIEndpoints::new(p.chat_path.as_deref(), p.models_path.as_deref()),
+            None => ai::OpenAIEndpoints::default(),
+        };
+        Ok((db::get_connection_settings(conn, &id)?, endpoints))
+    }).await?;
+
+    let client = app_state.ai_client.lock()?.clone();
+    Ok(client.for_provider(provider_id, &settings)?.with_endpoints(endpoints))
 }
 
-// Initialize the database
-fn init_database() -> Result<Connection, String> {
-    let data_dir = get_app_data_dir()?;
-    db::init_db(&data_dir).map_err(|e| format!("Could not initialize database: {}", e))
+// Load the Azure OpenAI settings of a provider
+async fn load_azure_settings(app_state: &AppState, provider_id: &str) -> Result<ai::AzureSettings, CommandError> {
+    let provider_id = provider_id.to_string();
+    app_state.with_db(move |conn| db::get_azure_settings(conn, &provider_id).map_err(CommandError::from)).await
+}
+
+// Get a provider, failing if it does not exist
+async fn find_provider(app_state: &AppState, provider_id: &str) -> Result<db::AIProvider, CommandError> {
+    let provider_id = provider_id.to_string();
+    app_state.with_db(move |conn| {
+        db::get_provider_by_id(conn, &provider_id)?
+            .ok_or_else(|| CommandError::not_found("Provider not found"))
+    }).await
 }
 
 // Tauri commands for AI providers
 #[tauri::command]
-async fn get_providers(app_state: State<'_, AppState>) -> Result<Vec<db::AIProvider>, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::get_all_providers(&conn).map_err(|e| e.to_string())
+async fn get_providers(app_state: State<'_, AppState>) -> Result<Vec<db::AIProvider>, CommandError> {
+    app_state.with_db(move |conn| db::get_all_providers(conn).map_err(CommandError::from)).await
 }
 
 #[tauri::command]
 async fn add_provider(
     app_state: State<'_, AppState>,
     provider: ProviderRequest
-) -> Result<String, String> {
+) -> Result<String, CommandError> {
     // 生成 ID，如果提供了前缀则使用前缀
     let id = if let Some(prefix) = &provider.id_prefix {
         format!("{}-{}", prefix, Uuid::new_v4().to_string().split('-').next().unwrap())
@@ -130,278 +484,356 @@ async fn add_provider(
         Uuid::new_v4().to_string()
     };
     
+    let api_url = ai::normalize_base_url(&provider.api_url);
+    
     // Add provider to database with API key
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::add_provider_with_id(&conn, &id, &provider.name, &provider.api_url, &provider.name, &provider.api_key)
-        .map_err(|e| e.to_string())
+    app_state.with_db(move |conn| {
+        db::add_provider_with_id(conn, &id, &provider.name, &api_url, &provider.name, &provider.api_key)?;
+        db::set_provider_endpoints(
+            conn,
+            &id,
+            non_empty(provider.chat_path.as_deref()),
+            non_empty(provider.models_path.as_deref())
+        )?;
+        
+        Ok(id)
+    }).await
 }
 
 #[tauri::command]
 async fn update_provider(
     app_state: State<'_, AppState>,
     provider: ProviderUpdateRequest
-) -> Result<(), String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    
-    // Get current provider to get api_key_name
-    let current = db::get_provider_by_id(&conn, &provider.id)
-        .map_err(|e| e.to_string())?
-        .ok_or_else(|| "Provider not found".to_string())?;
-    
-    // Update provider in database with API key if provided
-    db::update_provider(
-        &conn, 
-        &provider.id, 
-        &provider.name, 
-        &provider.api_url, 
-        &current.api_key_name,
-        provider.api_key.as_deref()
-    ).map_err(|e| e.to_string())
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| {
+        // Get current provider to get api_key_name
+        let current = db::get_provider_by_id(conn, &provider.id)?
+            .ok_or_else(|| CommandError::not_found("Provider not found"))?;
+        
+        // Only the API key of a catalog provider can be changed
+        if current.is_managed {
+            let endpoint_changed = |new: &Option<String>, old: &Option<String>| {
+                new.is_some() && non_empty(new.as_deref()) != old.as_deref()
+            };
+            if provider.name != current.name
+                || ai::normalize_base_url(&provider.api_url) != current.api_url
+                || endpoint_changed(&provider.chat_path, &current.chat_path)
+                || endpoint_changed(&provider.models_path, &current.models_path)
+            {
+                return Err(managed_entry_error("provider"));
+            }
+        }
+        
+        // Update provider in database with API key if provided
+        db::update_provider(
+            conn, 
+            &provider.id, 
+            &provider.name, 
+            &ai::normalize_base_url(&provider.api_url), 
+            &current.api_key_name,
+            provider.api_key.as_deref()
+        )?;
+        
+        if provider.chat_path.is_some() || provider.models_path.is_some() {
+            db::set_provider_endpoints(
+                conn,
+                &provider.id,
+                non_empty(provider.chat_path.as_deref()),
+                non_empty(provider.models_path.as_deref())
+            )?;
+        }
+        
+        Ok(())
+    }).await
+}
+
+// Error for edits to providers and models kept by the team catalog
+fn managed_entry_error(entry: &str) -> CommandError {
+    CommandError::invalid_input(format!("This {} is managed by the team catalog and cannot be changed", entry))
+}
+
+// Treat blank optional strings as unset
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|v| !v.is_empty())
+}
+
+// Report which chat and models endpoints of an OpenAI-compatible provider respond
+#[tauri::command]
+async fn diagnose_provider(
+    app_state: State<'_, AppState>,
+    provider_id: String
+) -> Result<Vec<ai::EndpointProbe>, CommandError> {
+    let provider = find_provider(&app_state, &provider_id).await?;
+    // Gemini and Azure authenticate and route differently, so the probes would fail misleadingly
+    let provider_type = chat::determine_provider_type(&provider.id, &provider.api_url, &provider.name);
+    if matches!(provider_type.as_str(), "gemini" | "azure") {
+        return Err(CommandError::invalid_input(
+            "Endpoint diagnosis is only available for OpenAI-compatible providers",
+        ));
+    }
+    let api_key = provider.api_key.unwrap_or_default();
+    let ai_client = provider_client(&app_state, &provider_id).await?;
+
+    Ok(ai_client.diagnose_openai_endpoints(&provider.api_url, &api_key).await)
 }
 
 #[tauri::command]
 async fn delete_provider(
     app_state: State<'_, AppState>,
     id: String
-) -> Result<String, String> {
+) -> Result<String, CommandError> {
     println!("Received delete_provider request for ID: {}", id);
     
-    let mut conn = match app_state.db_conn.lock() {
-        Ok(conn) => conn,
-        Err(e) => {
-            let err_msg = format!("Failed to lock database connection: {}", e);
-            println!("{}", err_msg);
-            return Err(err_msg);
-        }
-    };
-    
     // Delete provider from database (this will cascade delete models too)
-    match db::delete_provider(&mut conn, &id) {
+    let provider_id = id.clone();
+    let result = app_state.with_db(move |conn| {
+        if db::get_provider_by_id(conn, &provider_id)?.is_some_and(|p| p.is_managed) {
+            return Err(managed_entry_error("provider"));
+        }
+        db::delete_provider(conn, &provider_id).map_err(CommandError::from)
+    }).await;
+    match result {
         Ok(_) => {
+            if let Ok(client) = app_state.ai_client.lock() {
+                client.forget_provider(&id);
+            }
             let msg = format!("Successfully deleted provider with ID: {}", id);
             println!("{}", msg);
             Ok(msg)
         },
         Err(e) => {
-            let err_msg = format!("Failed to delete provider: {}", e);
-            println!("{}", err_msg);
-            Err(err_msg)
+            println!("Failed to delete provider: {}", e);
+            Err(e)
         }
     }
 }
 
+#[tauri::command]
+async fn get_provider_connection_settings(
+    app_state: State<'_, AppState>,
+    provider_id: String
+) -> Result<ai::ConnectionSettings, CommandError> {
+    app_state.with_db(move |conn| db::get_connection_settings(conn, &provider_id).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn set_provider_connection_settings(
+    app_state: State<'_, AppState>,
+    request: ConnectionSettingsRequest
+) -> Result<(), CommandError> {
+    // Reject settings that cannot produce a working client before saving them
+    request.settings.build_client()?;
+
+    let provider_id = request.provider_id.clone();
+    app_state.with_db(move |conn| {
+        db::get_provider_by_id(conn, &request.provider_id)?
+            .ok_or_else(|| CommandError::not_found("Provider not found"))?;
+        db::set_connection_settings(conn, &request.provider_id, &request.settings).map_err(CommandError::from)
+    }).await?;
+
+    app_state.ai_client.lock()?.forget_provider(&provider_id);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_azure_settings(
+    app_state: State<'_, AppState>,
+    provider_id: String
+) -> Result<ai::AzureSettings, CommandError> {
+    load_azure_settings(&app_state, &provider_id).await
+}
+
+#[tauri::command]
+async fn set_azure_settings(
+    app_state: State<'_, AppState>,
+    request: AzureSettingsRequest
+) -> Result<(), CommandError> {
+    if request.settings.api_version.trim().is_empty() {
+        return Err(CommandError::invalid_input("Azure API version is required"));
+    }
+
+    app_state.with_db(move |conn| {
+        db::get_provider_by_id(conn, &request.provider_id)?
+            .ok_or_else(|| CommandError::not_found("Provider not found"))?;
+        db::set_azure_settings(conn, &request.provider_id, &request.settings).map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn fetch_azure_deployments(
+    app_state: State<'_, AppState>,
+    provider_id: String
+) -> Result<Vec<ai::AzureDeployment>, CommandError> {
+    let provider = find_provider(&app_state, &provider_id).await?;
+    let api_key = provider.api_key.ok_or_else(missing_api_key_error)?;
+    let settings = load_azure_settings(&app_state, &provider_id).await?;
+    let ai_client = provider_client(&app_state, &provider_id).await?;
+
+    ai_client.fetch_azure_deployments(&provider.api_url, &api_key, &settings)
+        .await
+        .map_err(CommandError::from)
+}
+
 // Tauri commands for AI models
 #[tauri::command]
 async fn get_models(
     app_state: State<'_, AppState>,
     provider_id: String
-) -> Result<Vec<db::AIModel>, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::get_models_by_provider(&conn, &provider_id).map_err(|e| e.to_string())
+) -> Result<Vec<db::AIModel>, CommandError> {
+    let mut models = app_state.with_db(move |conn| db::get_models_by_provider(conn, &provider_id).map_err(CommandError::from)).await?;
+    // Fill in what neither the catalog nor the listing said about well-known models
+    for model in &mut models {
+        model.metadata = std::mem::take(&mut model.metadata).or(model_info::builtin_metadata(&model.name));
+    }
+    Ok(models)
 }
 
 #[tauri::command]
 async fn add_model(
     app_state: State<'_, AppState>,
     model: ModelRequest
-) -> Result<String, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::add_model(&conn, &model.provider_id, &model.name).map_err(|e| e.to_string())
+) -> Result<String, CommandError> {
+    // Metadata from the provider's last listing, if it included this model
+    let listed = app_state.model_listings.lock()?
+        .get(&model.provider_id)
+        .and_then(|listing| listing.iter().find(|m| m.id == model.name))
+        .map(|m| m.metadata.clone());
+    
+    app_state.with_db(move |conn| {
+        if db::get_provider_by_id(conn, &model.provider_id)?.is_some_and(|p| p.is_managed) {
+            return Err(managed_entry_error("provider"));
+        }
+        let id = db::add_model(conn, &model.provider_id, &model.name)?;
+        if let Some(metadata) = listed.filter(|m| !m.is_empty()) {
+            db::set_model_metadata(conn, &id, &metadata)?;
+        }
+        Ok(id)
+    }).await
 }
 
 #[tauri::command]
 async fn delete_model(
     app_state: State<'_, AppState>,
     id: String
-) -> Result<(), String> {
-    let mut conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::delete_model(&mut conn, &id).map_err(|e| e.to_string())
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| {
+        ensure_model_unmanaged(conn, &id)?;
+        db::delete_model(conn, &id).map_err(CommandError::from)
+    }).await
+}
+
+fn ensure_model_unmanaged(conn: &Connection, model_id: &str) -> Result<(), CommandError> {
+    if db::get_model_by_id(conn, model_id)?.is_some_and(|m| m.is_managed) {
+        return Err(managed_entry_error("model"));
+    }
+    Ok(())
 }
 
 #[tauri::command]
 async fn fetch_models_from_provider(
     app_state: State<'_, AppState>,
     provider_id: String
-) -> Result<Vec<String>, String> {
-    // Get provider details from database
-    let provider = {
-        let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-        db::get_provider_by_id(&conn, &provider_id)
-            .map_err(|e| e.to_string())?
-            .ok_or_else(|| "Provider not found".to_string())?
-    };
+) -> Result<Vec<String>, CommandError> {
+    let listing = list_provider_models(&app_state, &provider_id).await?;
+    let names = listing.iter().map(|m| m.id.clone()).collect();
 
-    // Check if API key is set
-    if provider.api_key.is_none() {
-        return Err("API key not set for this provider. Please set an API key in the Providers page.".to_string());
-    }
-
-    let api_url = provider.api_url.clone();
-    let api_key = provider.api_key.unwrap();
-
-    let ai_client = {
-        let guard = app_state.ai_client.lock().map_err(|e| e.to_string())?;
-        guard.clone()
-    };
-
-    // Determine provider type based on ID prefix, API URL, or name
-    let provider_type = determine_provider_type(&provider_id, &api_url, &provider.name);
-
-    match provider_type.as_str() {
-        "openai" => {
-            ai_client.fetch_openai_models(&api_url, &api_key)
-                .await
-                .map_err(|e| e.to_string())
-        },
-        "deepseek" => {
-            // DeepSeek uses OpenAI-compatible API
-            ai_client.fetch_openai_models(&api_url, &api_key)
-                .await
-                .map_err(|e| e.to_string())
-        },
-        "grok" => {
-            // Grok uses OpenAI-compatible API
-            ai_client.fetch_openai_models(&api_url, &api_key)
-                .await
-                .map_err(|e| e.to_string())
-        },
-        "gemini" => {
-            // For now, return some common Gemini models
-            // TODO: Implement actual Gemini models API call
-            Ok(vec![
-                "gemini-1.5-pro".to_string(),
-                "gemini-1.5-flash".to_string(),
-                "gemini-1.0-pro".to_string(),
-            ])
-        },
-        "custom" => {
-            // For custom providers, default to OpenAI-compatible API
-            ai_client.fetch_openai_models(&api_url, &api_key)
-                .await
-                .map_err(|e| format!("Custom provider API error: {}. This provider might not support model listing or might not be OpenAI-compatible.", e))
-        },
-        _ => {
-            // Default to OpenAI-compatible API for unknown types
-            ai_client.fetch_openai_models(&api_url, &api_key)
-                .await
-                .map_err(|e| format!("Unknown provider type '{}', tried OpenAI-compatible API: {}", provider_type, e))
+    // Keep the listed metadata of models already added, and of the ones added next
+    let stored_listing = listing.clone();
+    let stored_provider_id = provider_id.clone();
+    app_state.with_db(move |conn| {
+        for model in db::get_models_by_provider(conn, &stored_provider_id)? {
+            let listed = stored_listing.iter().find(|m| m.id == model.name);
+            if let Some(listed) = listed.filter(|m| !model.is_managed && !m.metadata.is_empty()) {
+                db::set_model_metadata(conn, &model.id, &listed.metadata)?;
+            }
         }
-    }
+        Ok(())
+    }).await?;
+    app_state.model_listings.lock()?.insert(provider_id, listing);
+
+    Ok(names)
 }
 
-// Helper function to determine provider type
-fn determine_provider_type(provider_id: &str, api_url: &str, provider_name: &str) -> String {
-    // Check ID prefix first (most reliable)
-    if provider_id.starts_with("openai") {
-        return "openai".to_string();
-    }
-    if provider_id.starts_with("deepseek") {
-        return "deepseek".to_string();
-    }
-    if provider_id.starts_with("grok") {
-        return "grok".to_string();
-    }
-    if provider_id.starts_with("gemini") {
-        return "gemini".to_string();
-    }
-    if provider_id.starts_with("custom") {
-        return "custom".to_string();
-    }
+// Bring a provider's stored models in line with its listing: new models are
+// added, unlisted ones marked unavailable and relisted ones restored
+#[tauri::command]
+async fn sync_models(
+    app_state: State<'_, AppState>,
+    provider_id: String
+) -> Result<db::ModelSyncDiff, CommandError> {
+    let listing = list_provider_models(&app_state, &provider_id).await?;
+    app_state.model_listings.lock()?.insert(provider_id.clone(), listing.clone());
 
-    // Check API URL (second priority)
-    let api_url_lower = api_url.to_lowercase();
-    if api_url_lower.contains("openai.com") {
-        return "openai".to_string();
-    }
-    if api_url_lower.contains("deepseek.com") {
-        return "deepseek".to_string();
-    }
-    if api_url_lower.contains("grok.x.ai") || api_url_lower.contains("x.ai") {
-        return "grok".to_string();
-    }
-    if api_url_lower.contains("googleapis.com") || api_url_lower.contains("generativelanguage") {
-        return "gemini".to_string();
-    }
+    app_state.with_db(move |conn| {
+        let provider = db::get_provider_by_id(conn, &provider_id)?
+            .ok_or_else(|| CommandError::not_found("Provider not found"))?;
+        // The team catalog decides which models a managed provider offers
+        db::sync_provider_models(conn, &provider_id, &listing, !provider.is_managed).map_err(CommandError::from)
+    }).await
+}
 
-    // Check provider name as fallback (third priority)
-    let name_lower = provider_name.to_lowercase();
-    if name_lower.contains("openai") {
-        return "openai".to_string();
-    }
-    if name_lower.contains("deepseek") {
-        return "deepseek".to_string();
-    }
-    if name_lower.contains("grok") {
-        return "grok".to_string();
-    }
-    if name_lower.contains("gemini") {
-        return "gemini".to_string();
-    }
+// Fetch a provider's models with whatever metadata its listing offers
+async fn list_provider_models(app_state: &AppState, provider_id: &str) -> Result<Vec<model_info::ModelInfo>, CommandError> {
+    let provider = find_provider(app_state, provider_id).await?;
+    let ai_client = provider_client(app_state, provider_id).await?;
+    let azure_settings = load_azure_settings(app_state, provider_id).await?;
 
-    // Default to custom (which will use OpenAI-compatible API)
-    "custom".to_string()
+    chat::list_models(&ai_client, &provider, &azure_settings)
+        .await
+        .map_err(CommandError::from)
 }
 
 // Tauri commands for chat sessions
 #[tauri::command]
-async fn get_chat_sessions(app_state: State<'_, AppState>) -> Result<Vec<db::ChatSession>, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::get_all_chat_sessions(&conn).map_err(|e| e.to_string())
+async fn get_chat_sessions(app_state: State<'_, AppState>) -> Result<Vec<db::ChatSession>, CommandError> {
+    app_state.with_db(move |conn| db::get_all_chat_sessions(conn).map_err(CommandError::from)).await
 }
 
 #[tauri::command]
 async fn create_chat_session(
     app_state: State<'_, AppState>,
     session: ChatSessionRequest
-) -> Result<String, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::create_chat_session(&conn, &session.name, session.model_id.as_deref(), session.system_prompt.as_deref())
-        .map_err(|e| e.to_string())
+) -> Result<String, CommandError> {
+    let name = session.name.ok_or_else(|| CommandError::invalid_input("Session name is required"))?;
+    app_state.with_db(move |conn| {
+        db::create_chat_session(conn, &name, session.model_id.as_deref(), session.system_prompt.as_deref())
+            .map_err(CommandError::from)
+    }).await
 }
 
 #[tauri::command]
 async fn update_chat_session(
     app_state: State<'_, AppState>,
     session: ChatSessionRequest
-) -> Result<(), String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    
-    if let Some(id) = session.id {
-        db::update_chat_session(&conn, &id, &session.name, session.model_id.as_deref(), session.system_prompt.as_deref())
-            .map_err(|e| e.to_string())?;
-        Ok(())
-    } else {
-        Err("Session ID is required for update".to_string())
-    }
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| {
+        if let Some(id) = session.id {
+            db::update_chat_session(conn, &id, session.name.as_deref(), session.model_id.as_deref(), session.system_prompt.as_deref())?;
+            Ok(())
+        } else {
+            Err(CommandError::invalid_input("Session ID is required for update"))
+        }
+    }).await
 }
 
 #[tauri::command]
 async fn delete_chat_session(
     app_state: State<'_, AppState>,
     id: String
-) -> Result<String, String> {
+) -> Result<String, CommandError> {
     println!("Received delete_chat_session request for ID: {}", id);
     
-    let mut conn = match app_state.db_conn.lock() {
-        Ok(conn) => conn,
-        Err(e) => {
-            let err_msg = format!("Failed to lock database connection: {}", e);
-            println!("{}", err_msg);
-            return Err(err_msg);
-        }
-    };
-    
     // Delete chat session from database
-    match db::delete_chat_session(&mut conn, &id) {
+    let session_id = id.clone();
+    match app_state.with_db(move |conn| db::delete_chat_session(conn, &session_id).map_err(CommandError::from)).await {
         Ok(_) => {
             let msg = format!("Successfully deleted chat session with ID: {}", id);
             println!("{}", msg);
             Ok(msg)
         },
         Err(e) => {
-            let err_msg = format!("Failed to delete chat session: {}", e);
-            println!("{}", err_msg);
-            Err(err_msg)
+            println!("Failed to delete chat session: {}", e);
+            Err(e)
         }
     }
 }
@@ -410,266 +842,2519 @@ async fn delete_chat_session(
 async fn get_chat_session(
     app_state: State<'_, AppState>,
     id: String
-) -> Result<Option<db::ChatSession>, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::get_chat_session_by_id(&conn, &id).map_err(|e| e.to_string())
+) -> Result<Option<db::ChatSession>, CommandError> {
+    app_state.with_db(move |conn| db::get_chat_session_by_id(conn, &id).map_err(CommandError::from)).await
 }
 
-// Tauri commands for chat messages
 #[tauri::command]
-async fn get_chat_messages(
+async fn list_chat_sessions(
     app_state: State<'_, AppState>,
-    session_id: String
-) -> Result<Vec<db::ChatMessage>, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::get_messages_by_session(&conn, &session_id).map_err(|e| e.to_string())
+    filter: db::SessionFilter
+) -> Result<db::SessionPage, CommandError> {
+    app_state.with_db(move |conn| db::list_chat_sessions(conn, &filter).map_err(CommandError::from)).await
 }
 
 #[tauri::command]
-async fn add_chat_message(
+async fn set_session_pinned(
     app_state: State<'_, AppState>,
-    message: ChatMessageRequest
-) -> Result<String, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::add_message(&conn, &message.session_id, &message.role, &message.content, None)
-        .map_err(|e| e.to_string())
+    request: SessionFlagRequest
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::set_session_pinned(conn, &request.session_id, request.value).map_err(CommandError::from)).await
 }
 
-
-
-// Tauri commands for settings
 #[tauri::command]
-async fn get_setting(
+async fn set_session_archived(
     app_state: State<'_, AppState>,
-    key: String
-) -> Result<Option<String>, String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::get_setting(&conn, &key).map_err(|e| e.to_string())
+    request: SessionFlagRequest
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::set_session_archived(conn, &request.session_id, request.value).map_err(CommandError::from)).await
 }
 
 #[tauri::command]
-async fn set_setting(
+async fn move_session_to_folder(
     app_state: State<'_, AppState>,
-    setting: SettingRequest
-) -> Result<(), String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::set_setting(&conn, &setting.key, &setting.value).map_err(|e| e.to_string())
+    request: MoveSessionRequest
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::move_session_to_folder(conn, &request.session_id, request.folder_id.as_deref()).map_err(CommandError::from)).await
 }
 
-// Tauri command for sending AI requests
+// Tauri commands for session folders
 #[tauri::command]
-async fn send_chat_request(
-    app_state: State<'_, AppState>,
-    request: ChatRequest
-) -> Result<ai::AIResponse, String> {
-    // Get provider details
-    let provider = {
-        let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-        db::get_provider_by_id(&conn, &request.provider_id)
-            .map_err(|e| e.to_string())?
-            .ok_or_else(|| "Provider not found".to_string())?
+async fn get_session_folders(app_state: State<'_, AppState>) -> Result<Vec<db::SessionFolder>, CommandError> {
+    app_state.with_db(move |conn| db::get_session_folders(conn).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn create_session_folder(
+    app_state: State<'_, AppState>,
+    folder: SessionFolderRequest
+) -> Result<String, CommandError> {
+    app_state.with_db(move |conn| db::create_session_folder(conn, &folder.name, folder.parent_id.as_deref()).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn update_session_folder(
+    app_state: State<'_, AppState>,
+    folder: SessionFolderRequest
+) -> Result<(), CommandError> {
+    let id = folder.id.ok_or_else(|| CommandError::invalid_input("Folder ID is required for update"))?;
+    app_state.with_db(move |conn| {
+        // A folder cannot be moved into itself or one of its subfolders
+        if let Some(parent_id) = folder.parent_id.as_deref() {
+            if db::is_folder_within(conn, parent_id, &id)? {
+                return Err(CommandError::invalid_input("A folder cannot be moved into itself or one of its subfolders"));
+            }
+        }
+        
+        db::update_session_folder(conn, &id, &folder.name, folder.parent_id.as_deref(), folder.sort_order)
+            .map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn delete_session_folder(
+    app_state: State<'_, AppState>,
+    id: String
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::delete_session_folder(conn, &id).map_err(CommandError::from)).await
+}
+
+// Tauri commands for session tags
+#[tauri::command]
+async fn get_session_tags(app_state: State<'_, AppState>) -> Result<Vec<db::SessionTag>, CommandError> {
+    app_state.with_db(move |conn| db::get_session_tags(conn).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn create_session_tag(
+    app_state: State<'_, AppState>,
+    tag: SessionTagRequest
+) -> Result<String, CommandError> {
+    app_state.with_db(move |conn| db::create_session_tag(conn, tag.name.trim(), tag.color.as_deref()).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn update_session_tag(
+    app_state: State<'_, AppState>,
+    tag: SessionTagRequest
+) -> Result<(), CommandError> {
+    let id = tag.id.ok_or_else(|| CommandError::invalid_input("Tag ID is required for update"))?;
+    app_state.with_db(move |conn| db::update_session_tag(conn, &id, tag.name.trim(), tag.color.as_deref()).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn delete_session_tag(
+    app_state: State<'_, AppState>,
+    id: String
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::delete_session_tag(conn, &id).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn set_session_tags(
+    app_state: State<'_, AppState>,
+    request: SessionTagsRequest
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::set_session_tags(conn, &request.session_id, &request.tag_ids).map_err(CommandError::from)).await
+}
+
+// Tauri commands for chat messages
+#[tauri::command]
+async fn get_chat_messages(
+    app_state: State<'_, AppState>,
+    session_id: String
+) -> Result<Vec<db::ChatMessage>, CommandError> {
+    app_state.with_db(move |conn| db::get_messages_by_session(conn, &session_id).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn add_chat_message(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    message: ChatMessageRequest
+) -> Result<String, CommandError> {
+    let session_id = message.session_id.clone();
+    let (id, first_reply, summarize) = app_state.with_db(move |conn| {
+        let id = db::add_message(conn, &message.session_id, &message.role, &message.content, message.reasoning.as_deref())?;
+        if let Some(response_id) = &message.response_id {
+            db::set_message_response_id(conn, &id, response_id)?;
+        }
+        if let Some(model_id) = &message.model_id {
+            db::set_message_model(conn, &id, model_id)?;
+        }
+        if let Some(citations) = message.citations.as_deref().filter(|c| !c.is_empty()) {
+            db::add_message_citations(conn, &id, citations)?;
+        }
+        
+        let (first_reply, summarize) = match message.role.as_str() {
+            "assistant" => reply_tasks(conn, &message.session_id)?,
+            _ => (false, false),
+        };
+        Ok((id, first_reply, summarize))
+    }).await?;
+    
+    spawn_reply_tasks(app_handle, session_id, first_reply, summarize);
+    Ok(id)
+}
+
+// Whether to title a session and update its summary after a reply was added:
+// the title follows the first exchange and the summary every reply, unless
+// disabled
+fn reply_tasks(conn: &Connection, session_id: &str) -> Result<(bool, bool), CommandError> {
+    let first_reply = db::get_setting(conn, chat::AUTO_TITLE_SETTING)?.as_deref() != Some("false")
+        && db::get_messages_by_session(conn, session_id)?
+            .iter()
+            .filter(|m| m.role == "assistant")
+            .count() == 1;
+    let summarize = chat::load_summary_policy(conn)?.enabled;
+    Ok((first_reply, summarize))
+}
+
+// Title and summarize a session in the background
+fn spawn_reply_tasks(app_handle: tauri::AppHandle, session_id: String, first_reply: bool, summarize: bool) {
+    if !first_reply && !summarize {
+        return;
+    }
+    
+    tauri::async_runtime::spawn(async move {
+        let app_state = app_handle.state::<AppState>();
+        if first_reply {
+            if let Err(e) = title_session(&app_handle, &app_state, &session_id, false).await {
+                eprintln!("Could not generate title for session {}: {}", session_id, e);
+            }
+        }
+        if summarize {
+            if let Err(e) = summarize_session(&app_handle, &app_state, &session_id, false).await {
+                eprintln!("Could not summarize session {}: {}", session_id, e);
+            }
+        }
+    });
+}
+
+// Find a model and its provider for a background task such as titling or
+// summarizing. Returns None if the model does not exist or its provider has
+// no API key.
+async fn task_model(
+    app_state: &AppState,
+    model_id: Option<String>
+) -> Result<Option<(db::AIModel, db::AIProvider)>, CommandError> {
+    app_state.with_db(move |conn| {
+        let Some(model) = model_id.map(|id| db::get_model_by_id(conn, &id)).transpose()?.flatten() else {
+            return Ok(None);
+        };
+        
+        let provider = db::get_provider_by_id(conn, &model.provider_id)?
+            .filter(|p| p.api_key.is_some());
+        Ok(provider.map(|provider| (model, provider)))
+    }).await
+}
+
+// Ask the title model (or the session's own model) for a title based on the
+// session's first exchange, save it as the session name and notify the
+// frontend. Returns None without a complete exchange or a usable model, or
+// when the user renamed the session and `force` is not set.
+async fn title_session(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    session_id: &str,
+    force: bool
+) -> Result<Option<String>, CommandError> {
+    let id = session_id.to_string();
+    let request = app_state.with_db(move |conn| {
+        let session = db::get_chat_session_by_id(conn, &id)?
+            .ok_or_else(|| CommandError::not_found("Chat session not found"))?;
+        if session.name_is_custom && !force {
+            return Ok(None);
+        }
+        
+        let history = db::get_messages_by_session(conn, &id)?;
+        let Some((user, assistant)) = chat::first_exchange(&history) else {
+            return Ok(None);
+        };
+        
+        let model_id = db::get_setting(conn, chat::TITLE_MODEL_SETTING)?
+            .filter(|id| !id.is_empty())
+            .or(session.model_id);
+        Ok(Some((chat::title_request(user, assistant), model_id)))
+    }).await?;
+    let Some((messages, model_id)) = request else {
+        return Ok(None);
+    };
+    let Some((model, provider)) = task_model(app_state, model_id).await? else {
+        return Ok(None);
+    };
+    
+    let api_type = ai::ApiType::parse(&model.api_type).unwrap_or_default();
+    let response = dispatch_chat(app_state, provider, &model.name, api_type, messages, None, &mut |_| {}).await?;
+    let Some(title) = chat::clean_title(&response.content) else {
+        return Ok(None);
+    };
+    
+    let (id, name) = (session_id.to_string(), title.clone());
+    let updated = app_state.with_db(move |conn| {
+        db::set_generated_session_name(conn, &id, &name, force).map_err(CommandError::from)
+    }).await?;
+    if !updated {
+        return Ok(None);
+    }
+    
+    let _ = app_handle.emit("session-title-updated", SessionTitleUpdated {
+        session_id: session_id.to_string(),
+        name: title.clone(),
+    });
+    Ok(Some(title))
+}
+
+// Fold older messages of a session into its summary when the summary policy
+// calls for it, or rewrite the summary from the start with `force`. Returns the
+// new summary, or None if there was nothing to summarize.
+async fn summarize_session(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    session_id: &str,
+    force: bool
+) -> Result<Option<db::SessionSummary>, CommandError> {
+    let id = session_id.to_string();
+    let request = app_state.with_db(move |conn| {
+        let policy = chat::load_summary_policy(conn)?;
+        let session = db::get_chat_session_by_id(conn, &id)?
+            .ok_or_else(|| CommandError::not_found("Chat session not found"))?;
+        let history = db::get_messages_by_session(conn, &id)?;
+        
+        // A summary only counts while its last message is still in the session
+        let previous = if force {
+            None
+        } else {
+            db::get_session_summary(conn, &id)?.and_then(|summary| {
+                let last = history.iter().position(|m| m.id == summary.last_message_id)?;
+                Some((summary, last + 1))
+            })
+        };
+        let covered = previous.as_ref().map_or(0, |(_, covered)| *covered);
+        let Some(range) = chat::summary_range(history.len(), covered, &policy, force) else {
+            return Ok(None);
+        };
+        
+        let previous = previous.map(|(summary, _)| summary);
+        let first_message_id = previous.as_ref()
+            .map_or_else(|| history[range.start].id.clone(), |s| s.first_message_id.clone());
+        let last_message_id = history[range.end - 1].id.clone();
+        let messages = chat::summary_request(previous.as_ref().map(|s| s.content.as_str()), &history[range.clone()]);
+        Ok(Some((messages, first_message_id, last_message_id, range.end as i64, policy.model_id.or(session.model_id))))
+    }).await?;
+    let Some((messages, first_message_id, last_message_id, message_count, model_id)) = request else {
+        return Ok(None);
+    };
+    
+    let Some((model, provider)) = task_model(app_state, model_id).await? else {
+        if force {
+            return Err(CommandError::invalid_input("No model with an API key is available to write the summary"));
+        }
+        return Ok(None);
+    };
+    
+    let api_type = ai::ApiType::parse(&model.api_type).unwrap_or_default();
+    let response = dispatch_chat(app_state, provider, &model.name, api_type, messages, None, &mut |_| {}).await?;
+    let content = response.content.trim().to_string();
+    if content.is_empty() {
+        return Err(ai::AIError::Provider(ai::ProviderError::empty_response("The model returned an empty summary")).into());
+    }
+    
+    let id = session_id.to_string();
+    let summary = app_state.with_db(move |conn| {
+        db::save_session_summary(conn, &id, &content, &first_message_id, &last_message_id, message_count)?;
+        db::get_session_summary(conn, &id).map_err(CommandError::from)
+    }).await?;
+    
+    if let Some(summary) = &summary {
+        let _ = app_handle.emit("session-summary-updated", summary.clone());
+    }
+    Ok(summary)
+}
+
+// Generate a title for a session now, replacing a name set by the user
+#[tauri::command]
+async fn generate_session_title(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    session_id: String
+) -> Result<Option<String>, CommandError> {
+    title_session(&app_handle, &app_state, &session_id, true).await
+}
+
+// Tauri commands for session summaries
+#[tauri::command]
+async fn get_session_summary(
+    app_state: State<'_, AppState>,
+    session_id: String
+) -> Result<Option<db::SessionSummary>, CommandError> {
+    app_state.with_db(move |conn| db::get_session_summary(conn, &session_id).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn update_session_summary(
+    app_state: State<'_, AppState>,
+    request: SessionSummaryRequest
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| {
+        if db::update_session_summary_content(conn, &request.session_id, &request.content)? {
+            Ok(())
+        } else {
+            Err(CommandError::not_found("Session has no summary"))
+        }
+    }).await
+}
+
+#[tauri::command]
+async fn regenerate_session_summary(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    session_id: String
+) -> Result<db::SessionSummary, CommandError> {
+    summarize_session(&app_handle, &app_state, &session_id, true)
+        .await?
+        .ok_or_else(|| CommandError::invalid_input("Not enough messages to summarize"))
+}
+
+#[tauri::command]
+async fn delete_session_summary(
+    app_state: State<'_, AppState>,
+    session_id: String
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::delete_session_summary(conn, &session_id).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn get_summary_policy(app_state: State<'_, AppState>) -> Result<chat::SummaryPolicy, CommandError> {
+    app_state.with_db(move |conn| chat::load_summary_policy(conn).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn set_summary_policy(
+    app_state: State<'_, AppState>,
+    policy: chat::SummaryPolicy
+) -> Result<(), CommandError> {
+    let value = serde_json::to_string(&policy)?;
+    app_state.with_db(move |conn| db::set_setting(conn, chat::SUMMARY_POLICY_SETTING, &value).map_err(CommandError::from)).await
+}
+
+
+// Tauri commands for prompt templates
+#[tauri::command]
+async fn get_prompt_templates(
+    app_state: State<'_, AppState>,
+    category: Option<String>,
+    tag: Option<String>
+) -> Result<Vec<db::PromptTemplate>, CommandError> {
+    app_state.with_db(move |conn| db::get_prompt_templates(conn, category.as_deref(), tag.as_deref()).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn create_prompt_template(
+    app_state: State<'_, AppState>,
+    template: templates::PackTemplate
+) -> Result<String, CommandError> {
+    app_state.with_db(move |conn| db::add_prompt_template(conn, &template).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn update_prompt_template(
+    app_state: State<'_, AppState>,
+    request: PromptTemplateUpdateRequest
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::update_prompt_template(conn, &request.id, &request.template).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn delete_prompt_template(
+    app_state: State<'_, AppState>,
+    id: String
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::delete_prompt_template(conn, &id).map_err(CommandError::from)).await
+}
+
+// Render a stored template with the given variable values
+fn render_stored_template(conn: &Connection, template_id: &str, values: &HashMap<String, String>) -> Result<String, CommandError> {
+    let template = db::get_prompt_template_by_id(conn, template_id)?
+        .ok_or_else(|| CommandError::not_found("Prompt template not found"))?;
+
+    templates::render(&template.content, &template.variables, values).map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn render_prompt_template(
+    app_state: State<'_, AppState>,
+    request: RenderTemplateRequest
+) -> Result<String, CommandError> {
+    app_state.with_db(move |conn| {
+        render_stored_template(conn, &request.template_id, &request.values)
+    }).await
+}
+
+#[tauri::command]
+async fn insert_prompt_template(
+    app_state: State<'_, AppState>,
+    request: InsertTemplateRequest
+) -> Result<String, CommandError> {
+    app_state.with_db(move |conn| {
+        let content = render_stored_template(conn, &request.template_id, &request.values)?;
+        db::add_message(conn, &request.session_id, "user", &content, None)
+            .map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn export_prompt_templates(
+    app_state: State<'_, AppState>,
+    ids: Option<Vec<String>>
+) -> Result<String, CommandError> {
+    app_state.with_db(move |conn| {
+        let pack_templates = db::get_prompt_templates(conn, None, None)?
+            .into_iter()
+            .filter(|t| match &ids {
+                Some(ids) => ids.contains(&t.id),
+                None => true,
+            })
+            .map(|t| templates::PackTemplate {
+                name: t.name,
+                description: t.description,
+                category: t.category,
+                tags: t.tags,
+                content: t.content,
+                variables: t.variables,
+            })
+            .collect();
+
+        templates::export_pack(pack_templates).map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn import_prompt_templates(
+    app_state: State<'_, AppState>,
+    json: String
+) -> Result<Vec<String>, CommandError> {
+    let pack = templates::parse_pack(&json)?;
+
+    app_state.with_db(move |conn| {
+        let tx = conn.transaction()?;
+        let mut ids = Vec::new();
+        for template in &pack.templates {
+            ids.push(db::add_prompt_template(&tx, template)?);
+        }
+        tx.commit()?;
+
+        Ok(ids)
+    }).await
+}
+
+// Tauri commands for exporting and importing the app config
+#[tauri::command]
+async fn export_config(
+    app_state: State<'_, AppState>,
+    format: Option<config_export::ConfigFormat>,
+    // API keys are only exported, encrypted, when a passphrase is given
+    passphrase: Option<String>
+) -> Result<String, CommandError> {
+    app_state.with_db(move |conn| {
+        let export = config_export::export_config(conn, passphrase.as_deref())?;
+        config_export::serialize_config(&export, format.unwrap_or_default()).map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn import_config(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    content: String,
+    passphrase: Option<String>,
+    on_conflict: Option<config_export::ConflictPolicy>
+) -> Result<config_export::ImportSummary, CommandError> {
+    let export = config_export::parse_config(&content)?;
+    let summary = app_state.with_db(move |conn| {
+        config_export::import_config(conn, &export, passphrase.as_deref(), on_conflict.unwrap_or_default())
+            .map_err(CommandError::from)
+    }).await?;
+    
+    // Apply imported settings that are held in memory
+    load_database_settings(&app_handle, &app_state);
+    Ok(summary)
+}
+
+// Tauri commands for the team catalog
+#[tauri::command]
+async fn get_catalog_status(app_state: State<'_, AppState>) -> Result<catalog::CatalogStatus, CommandError> {
+    app_state.with_db(move |conn| catalog::load_status(conn).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn set_catalog_source(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    // A local path, `file://` URL or http(s) URL; empty to stop syncing
+    source: String,
+    sync_minutes: Option<u32>
+) -> Result<catalog::CatalogStatus, CommandError> {
+    let source = source.trim().to_string();
+    let sync_source = source.clone();
+    app_state.with_db(move |conn| {
+        db::set_setting(conn, catalog::SOURCE_SETTING, &source)?;
+        if let Some(minutes) = sync_minutes {
+            db::set_setting(conn, catalog::SYNC_MINUTES_SETTING, &minutes.to_string())?;
+        }
+        Ok(())
+    }).await?;
+
+    // Sync right away so a bad source is reported now; entries of a previous
+    // catalog stay managed until the next successful sync
+    if !sync_source.is_empty() {
+        let summary = sync_catalog_now(&app_state).await?;
+        let _ = app_handle.emit("catalog-synced", summary);
+    }
+    app_state.with_db(move |conn| catalog::load_status(conn).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn sync_catalog(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>
+) -> Result<catalog::CatalogSyncSummary, CommandError> {
+    let summary = sync_catalog_now(&app_state).await?;
+    let _ = app_handle.emit("catalog-synced", &summary);
+    Ok(summary)
+}
+
+// Tauri commands for settings
+// Settings the generic setting commands may not touch: the app lock and the
+// API server token change only through their own commands, which check the
+// passphrase
+fn ensure_setting_not_reserved(key: &str) -> Result<(), CommandError> {
+    if config_export::LOCAL_SETTINGS.contains(&key) || key == app_lock::IDLE_MINUTES_SETTING {
+        return Err(CommandError::invalid_input(format!("The setting '{}' cannot be read or changed here", key)));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_setting(
+    app_state: State<'_, AppState>,
+    key: String
+) -> Result<Option<String>, CommandError> {
+    ensure_setting_not_reserved(&key)?;
+    app_state.with_db(move |conn| db::get_setting(conn, &key).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn set_setting(
+    app_state: State<'_, AppState>,
+    setting: SettingRequest
+) -> Result<(), CommandError> {
+    ensure_setting_not_reserved(&setting.key)?;
+    app_state.with_db(move |conn| db::set_setting(conn, &setting.key, &setting.value).map_err(CommandError::from)).await
+}
+
+// Tauri command for sending AI requests. When the model fails with a retryable
+// error, the models of the session's or assistant's fallback chain are tried
+// in turn, announcing each switch with a `chat-model-fallback` event.
+#[tauri::command]
+async fn send_chat_request(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    request: ChatRequest
+) -> Result<ChatResponse, CommandError> {
+    let (mut provider, mut model_name, mut api_type) = request_model(&app_state, &request.provider_id, &request.model_id).await?;
+    
+    let ChatRequest { provider_id: mut answering_provider_id, model_id: mut answering_model_id, messages, session_id, assistant_id, collection_ids } = request;
+    let delta_session_id = session_id.clone();
+    let fallback_session_id = session_id.clone();
+    let primary_model_id = answering_model_id.clone();
+    let (mut previous_response_id, messages, memory_context, memory_tool, top_k, fallback_models) = app_state.with_db(move |conn| {
+        let fallback_models = chat::fallback_models(conn, &primary_model_id, session_id.as_deref(), assistant_id.as_deref())?;
+        let previous_response_id = match (&session_id, api_type) {
+            (Some(session_id), ai::ApiType::Responses) => db::get_latest_response_id(conn, session_id, Some(&primary_model_id))?,
+            _ => None,
+        };
+        
+        // Send the session's stored conversation, with its summary in place of the turns it covers
+        let messages = match &session_id {
+            Some(session_id) => chat::session_request(conn, session_id, messages)?,
+            None => messages,
+        };
+        
+        // Add memories from earlier conversations to the system prompt
+        let (folder_id, source_message_id) = match &session_id {
+            Some(session_id) => (
+                db::get_chat_session_by_id(conn, session_id)?.and_then(|s| s.folder_id),
+                db::get_messages_by_session(conn, session_id)?
+                    .into_iter()
+                    .rev()
+                    .find(|m| m.role == "user")
+                    .map(|m| m.id),
+            ),
+            None => (None, None),
+        };
+        
+        let messages = if db::get_setting(conn, chat::MEMORY_ENABLED_SETTING)?.as_deref() != Some("false") {
+            let query = messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.clone()).unwrap_or_default();
+            let memories = db::get_memories_for_context(conn, assistant_id.as_deref(), folder_id.as_deref())?;
+            chat::inject_memories(messages, &chat::select_memories(memories, &query, chat::MAX_CONTEXT_MEMORIES))
+        } else {
+            messages
+        };
+        let memory_tool = db::get_setting(conn, chat::MEMORY_TOOL_SETTING)?.as_deref() == Some("true");
+        let context = MemoryContext {
+            assistant_id,
+            folder_id,
+            source_message_id,
+        };
+        
+        let top_k = db::get_setting(conn, knowledge::TOP_K_SETTING)?
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(knowledge::DEFAULT_TOP_K);
+        Ok((previous_response_id, messages, context, memory_tool, top_k, fallback_models))
+    }).await?;
+    
+    // Add excerpts from the selected knowledge bases
+    let collection_ids = collection_ids.unwrap_or_default();
+    let citations = if collection_ids.is_empty() {
+        Vec::new()
+    } else {
+        let query = messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.clone()).unwrap_or_default();
+        retrieve_context(&app_state, &collection_ids, &query, top_k).await?
+    };
+    let messages = knowledge::inject_context(messages, &citations);
+    
+    let delta_handle = app_handle.clone();
+    let mut on_delta = move |delta: ai::StreamDelta| {
+        let _ = delta_handle.emit("chat-stream-delta", ChatStreamDelta {
+            session_id: delta_session_id.clone(),
+            content: delta.content,
+            reasoning: delta.reasoning,
+        });
+    };
+    let mut on_tool_call = |call: &ai::ToolCall| save_memory_from_tool(&app_state, &memory_context, call);
+    
+    let mut fallback_models = fallback_models.into_iter();
+    let mut fallbacks = Vec::new();
+    loop {
+        let params = chat::ChatParams {
+            model_name: &model_name,
+            api_type,
+            messages: messages.clone(),
+            // Stored responses belong to the requested model's provider
+            previous_response_id: previous_response_id.take(),
+            stream: false,
+            tools: if memory_tool { vec![chat::save_memory_tool()] } else { Vec::new() },
+            on_tool_call: Some(&mut on_tool_call),
+        };
+        let error = match send_chat(&app_state, provider, params, &mut on_delta).await {
+            Ok(response) => return Ok(ChatResponse {
+                response,
+                citations,
+                provider_id: answering_provider_id,
+                model_id: answering_model_id,
+                model_name,
+                fallbacks,
+            }),
+            Err(CommandError::Provider(error)) if error.retryable => error,
+            Err(e) => return Err(e),
+        };
+        // Models whose provider went away since the chain was resolved are skipped
+        let mut next_model = None;
+        for next in fallback_models.by_ref() {
+            match request_model(&app_state, &next.provider_id, &next.id).await {
+                Ok(resolved) => {
+                    next_model = Some((next, resolved));
+                    break;
+                },
+                Err(e) => eprintln!("Skipping fallback model {}: {}", next.name, e),
+            }
+        }
+        let Some((next, (next_provider, next_model_name, next_api_type))) = next_model else {
+            return Err(CommandError::Provider(error));
+        };
+        
+        eprintln!("Model {} failed, falling back to {}: {}", model_name, next_model_name, error.message);
+        let fallback = ModelFallback {
+            session_id: fallback_session_id.clone(),
+            from_model_id: answering_model_id,
+            from_model_name: model_name,
+            to_model_id: next.id.clone(),
+            to_model_name: next_model_name.clone(),
+            error,
+        };
+        // Announced before the next model streams, so the frontend can drop the
+        // partial output of the failed one
+        let _ = app_handle.emit("chat-model-fallback", &fallback);
+        fallbacks.push(fallback);
+        
+        (provider, model_name, api_type) = (next_provider, next_model_name, next_api_type);
+        answering_provider_id = next.provider_id;
+        answering_model_id = next.id;
+    }
+}
+
+// Most models compared at once
+const MAX_COMPARISON_CANDIDATES: usize = 8;
+
+// Send a session's history to several models at once. Each candidate streams
+// its answer in `compare-stream-delta` events and is saved with its latency
+// and token usage when it finishes; a failing candidate records its error
+// without affecting the others.
+#[tauri::command]
+async fn compare_models(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    request: CompareModelsRequest
+) -> Result<db::ModelComparison, CommandError> {
+    if request.candidates.is_empty() || request.candidates.len() > MAX_COMPARISON_CANDIDATES {
+        return Err(CommandError::invalid_input(format!("Choose between 1 and {} models to compare", MAX_COMPARISON_CANDIDATES)));
+    }
+    
+    let messages = comparison_history(&app_state, &request.session_id).await?;
+    let mut models = Vec::with_capacity(request.candidates.len());
+    for candidate in &request.candidates {
+        models.push(request_model(&app_state, &candidate.provider_id, &candidate.model_id).await?);
+    }
+    let candidates: Vec<(String, String, String)> = request.candidates.iter()
+        .zip(&models)
+        .map(|(c, (_, model_name, _))| (c.provider_id.clone(), c.model_id.clone(), model_name.clone()))
+        .collect();
+    let session_id = request.session_id;
+    let comparison = app_state.with_db(move |conn| {
+        db::create_model_comparison(conn, &session_id, &candidates).map_err(CommandError::from)
+    }).await?;
+    
+    let tasks: Vec<_> = models.into_iter()
+        .enumerate()
+        .map(|(i, model)| {
+            let app_handle = app_handle.clone();
+            let comparison_id = comparison.id.clone();
+            let messages = messages.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = run_comparison_candidate(&app_handle, &comparison_id, i as i64, model, messages).await {
+                    eprintln!("Could not save candidate {} of comparison {}: {}", i, comparison_id, e);
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        if let Err(e) = task.await {
+            eprintln!("Comparison task failed: {}", e);
+        }
+    }
+    
+    app_state.with_db(move |conn| {
+        db::get_model_comparison(conn, &comparison.id)?
+            .ok_or_else(|| CommandError::not_found("Model comparison not found"))
+    }).await
+}
+
+// The messages sent to compared models: the session's system prompt and
+// history, with older turns replaced by the session's summary
+async fn comparison_history(app_state: &AppState, session_id: &str) -> Result<Vec<ai::ChatMessage>, CommandError> {
+    let session_id = session_id.to_string();
+    app_state.with_db(move |conn| {
+        let session = db::get_chat_session_by_id(conn, &session_id)?
+            .ok_or_else(|| CommandError::not_found("Chat session not found"))?;
+        let messages = chat::session_history(conn, &session)?;
+        if messages.last().map(|m| m.role.as_str()) != Some("user") {
+            return Err(CommandError::invalid_input("The session must end with a user message to compare answers"));
+        }
+        chat::with_session_summary(conn, &session_id, messages).map_err(CommandError::from)
+    }).await
+}
+
+// Stream one candidate's answer, then save its outcome and announce it with
+// a `compare-candidate-finished` event
+async fn run_comparison_candidate(
+    app_handle: &tauri::AppHandle,
+    comparison_id: &str,
+    candidate_index: i64,
+    (provider, model_name, api_type): (db::AIProvider, String, ai::ApiType),
+    messages: Vec<ai::ChatMessage>
+) -> Result<(), CommandError> {
+    let app_state = app_handle.state::<AppState>();
+    let mut on_delta = |delta: ai::StreamDelta| {
+        let _ = app_handle.emit("compare-stream-delta", CompareStreamDelta {
+            comparison_id: comparison_id.to_string(),
+            candidate_index,
+            content: delta.content,
+            reasoning: delta.reasoning,
+        });
+    };
+    let params = chat::ChatParams {
+        model_name: &model_name,
+        api_type,
+        messages,
+        previous_response_id: None,
+        stream: true,
+        tools: Vec::new(),
+        on_tool_call: None,
+    };
+    
+    let started = Instant::now();
+    let response = send_chat(&app_state, provider, params, &mut on_delta).await;
+    let latency_ms = Some(started.elapsed().as_millis() as i64);
+    let result = match response {
+        Ok(response) => {
+            let usage = response.usage;
+            db::CandidateResult {
+                content: Some(response.content),
+                reasoning: response.reasoning,
+                error: None,
+                latency_ms,
+                prompt_tokens: usage.map(|u| u.prompt_tokens as i64),
+                completion_tokens: usage.map(|u| u.completion_tokens as i64),
+                total_tokens: usage.map(|u| u.total_tokens as i64),
+            }
+        },
+        Err(e) => db::CandidateResult { error: Some(e.to_string()), latency_ms, ..Default::default() },
+    };
+    
+    let id = comparison_id.to_string();
+    let candidate = app_state.with_db(move |conn| {
+        db::save_candidate_result(conn, &id, candidate_index, &result)?;
+        let comparison = db::get_model_comparison(conn, &id)?;
+        Ok(comparison.and_then(|c| c.candidates.into_iter().find(|c| c.candidate_index == candidate_index)))
+    }).await?;
+    if let Some(candidate) = candidate {
+        let _ = app_handle.emit("compare-candidate-finished", CompareCandidateFinished {
+            comparison_id: comparison_id.to_string(),
+            candidate,
+        });
+    }
+    Ok(())
+}
+
+// Add a compared answer to its session as the reply to the last message.
+// Returns the id of the new message.
+#[tauri::command]
+async fn promote_comparison_candidate(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    request: PromoteCandidateRequest
+) -> Result<String, CommandError> {
+    let (session_id, message_id, first_reply, summarize) = app_state.with_db(move |conn| {
+        let comparison = db::get_model_comparison(conn, &request.comparison_id)?
+            .ok_or_else(|| CommandError::not_found("Model comparison not found"))?;
+        let candidate = comparison.candidates.iter()
+            .find(|c| c.candidate_index == request.candidate_index)
+            .ok_or_else(|| CommandError::not_found("Comparison candidate not found"))?;
+        if comparison.candidates.iter().any(|c| c.promoted_message_id.is_some()) {
+            return Err(CommandError::invalid_input("An answer of this comparison was already added to the session"));
+        }
+        if candidate.result.content.is_none() {
+            return Err(CommandError::invalid_input("This candidate has no answer to add"));
+        }
+        
+        let message_id = db::promote_comparison_candidate(conn, &comparison, candidate)?;
+        let (first_reply, summarize) = reply_tasks(conn, &comparison.session_id)?;
+        Ok((comparison.session_id, message_id, first_reply, summarize))
+    }).await?;
+    
+    spawn_reply_tasks(app_handle, session_id, first_reply, summarize);
+    Ok(message_id)
+}
+
+#[tauri::command]
+async fn get_session_comparisons(
+    app_state: State<'_, AppState>,
+    session_id: String
+) -> Result<Vec<db::ModelComparison>, CommandError> {
+    app_state.with_db(move |conn| db::get_session_comparisons(conn, &session_id).map_err(CommandError::from)).await
+}
+
+fn ensure_fallback_owner_type(owner_type: &str) -> Result<(), CommandError> {
+    match owner_type {
+        "session" | "assistant" => Ok(()),
+        _ => Err(CommandError::invalid_input(format!("Unknown fallback chain owner '{}'", owner_type))),
+    }
+}
+
+// Get the models tried after a session's or assistant's model fails
+#[tauri::command]
+async fn get_fallback_chain(
+    app_state: State<'_, AppState>,
+    owner_type: String,
+    owner_id: String
+) -> Result<Vec<String>, CommandError> {
+    ensure_fallback_owner_type(&owner_type)?;
+    app_state.with_db(move |conn| db::get_fallback_chain(conn, &owner_type, &owner_id).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn set_fallback_chain(
+    app_state: State<'_, AppState>,
+    request: FallbackChainRequest
+) -> Result<(), CommandError> {
+    ensure_fallback_owner_type(&request.owner_type)?;
+    app_state.with_db(move |conn| {
+        for model_id in &request.model_ids {
+            if db::get_model_by_id(conn, model_id)?.is_none() {
+                return Err(CommandError::not_found(format!("Model '{}' not found", model_id)));
+            }
+        }
+        db::set_fallback_chain(conn, &request.owner_type, &request.owner_id, &request.model_ids).map_err(CommandError::from)
+    }).await
+}
+
+// Get a provider with an API key, and the name and API type of the model to
+// send requests to
+async fn request_model(
+    app_state: &AppState,
+    provider_id: &str,
+    model_id: &str
+) -> Result<(db::AIProvider, String, ai::ApiType), CommandError> {
+    let (provider_id, model_id) = (provider_id.to_string(), model_id.to_string());
+    app_state.with_db(move |conn| {
+        // Get provider details
+        let provider = db::get_provider_by_id(conn, &provider_id)?
+            .ok_or_else(|| CommandError::not_found("Provider not found"))?;
+        
+        // Check if API key is set; Ollama runs locally and needs none
+        let provider_type = chat::determine_provider_type(&provider.id, &provider.api_url, &provider.name);
+        if provider.api_key.is_none() && provider_type != "ollama" {
+            return Err(missing_api_key_error());
+        }
+        
+        // 使用model_id作为参数，查找对应的模型
+        let models = db::get_models_by_provider(conn, &provider_id)?;
+        let (model_name, api_type) = match models.iter().find(|m| m.id == model_id) {
+            Some(found_model) => (
+                found_model.name.clone(),
+                ai::ApiType::parse(&found_model.api_type).unwrap_or_default(),
+            ),
+            None => {
+                // 如果在数据库中找不到这个model_id，直接使用model_id作为模型名
+                // 这解决了自定义API可能将UUID作为模型名的问题
+                (model_id, ai::ApiType::default())
+            }
+        };
+        Ok((provider, model_name, api_type))
+    }).await
+}
+
+// Get a knowledge base collection with the provider that embeds it
+async fn kb_collection_provider(app_state: &AppState, collection_id: &str) -> Result<(db::KbCollection, db::AIProvider), CommandError> {
+    let collection_id = collection_id.to_string();
+    app_state.with_db(move |conn| {
+        let collection = db::get_kb_collection_by_id(conn, &collection_id)?
+            .ok_or_else(|| CommandError::not_found("Knowledge base not found"))?;
+        let provider = db::get_provider_by_id(conn, &collection.provider_id)?
+            .ok_or_else(|| CommandError::not_found(format!("Embedding provider of knowledge base '{}' not found", collection.name)))?;
+        Ok((collection, provider))
+    }).await
+}
+
+// Find the `top_k` chunks of the given collections most similar to `query`
+async fn retrieve_context(
+    app_state: &AppState,
+    collection_ids: &[String],
+    query: &str,
+    top_k: usize
+) -> Result<Vec<db::Citation>, CommandError> {
+    if query.trim().is_empty() || top_k == 0 {
+        return Ok(Vec::new());
+    }
+    
+    // Collections embedded with the same model share the query embedding
+    let mut query_embeddings: HashMap<(String, String), Vec<f32>> = HashMap::new();
+    let mut ranked = Vec::new();
+    for collection_id in collection_ids {
+        let (collection, provider) = kb_collection_provider(app_state, collection_id).await?;
+        let key = (provider.id.clone(), collection.embedding_model.clone());
+        if !query_embeddings.contains_key(&key) {
+            let client = provider_client(app_state, &provider.id).await?;
+            let embedding = knowledge::embed_texts(&client, &provider, &collection.embedding_model, &[query.to_string()])
+                .await?
+                .pop()
+                .unwrap_or_default();
+            query_embeddings.insert(key.clone(), embedding);
+        }
+        
+        let id = collection_id.clone();
+        let chunks = app_state.with_db(move |conn| db::get_kb_chunk_embeddings(conn, &id).map_err(CommandError::from)).await?;
+        ranked.extend(knowledge::rank_chunks(&query_embeddings[&key], chunks, top_k));
+    }
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(top_k);
+    
+    app_state.with_db(move |conn| {
+        let mut citations = Vec::new();
+        for (chunk_id, score) in ranked {
+            if let Some(citation) = db::get_kb_citation(conn, &chunk_id, score as f64)? {
+                citations.push(citation);
+            }
+        }
+        Ok(citations)
+    }).await
+}
+
+// Chunk, embed and store files in a collection. Files whose text has not
+// changed since they were indexed are skipped; failures are reported per file.
+async fn index_kb_files(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    collection_id: &str,
+    paths: &[std::path::PathBuf]
+) -> Result<KbIndexReport, CommandError> {
+    let (collection, provider) = kb_collection_provider(app_state, collection_id).await?;
+    let client = provider_client(app_state, &provider.id).await?;
+    let files = knowledge::collect_files(paths);
+    
+    let mut report = KbIndexReport::default();
+    for (i, file) in files.iter().enumerate() {
+        let path = std::fs::canonicalize(file).unwrap_or_else(|_| file.clone()).to_string_lossy().to_string();
+        let result = index_kb_file(app_state, &collection, &provider, &client, file, &path).await;
+        match result {
+            Ok(true) => report.indexed += 1,
+            Ok(false) => report.unchanged += 1,
+            Err(e) => report.failed.push(KbIndexFailure { path: path.clone(), error: e.to_string() }),
+        }
+        
+        let _ = app_handle.emit("kb-index-progress", KbIndexProgress {
+            collection_id: collection_id.to_string(),
+            path,
+            done: i + 1,
+            total: files.len(),
+        });
+    }
+    Ok(report)
+}
+
+// Index one file, returning false if it is unchanged
+async fn index_kb_file(
+    app_state: &AppState,
+    collection: &db::KbCollection,
+    provider: &db::AIProvider,
+    client: &ai::AIClient,
+    file: &std::path::Path,
+    path: &str
+) -> Result<bool, CommandError> {
+    let text = knowledge::read_text_file(file).map_err(CommandError::invalid_input)?;
+    let hash = knowledge::content_hash(&text);
+    let (collection_id, path) = (collection.id.clone(), path.to_string());
+    let stored_hash = {
+        let (collection_id, path) = (collection_id.clone(), path.clone());
+        app_state.with_db(move |conn| db::get_kb_document_hash(conn, &collection_id, &path).map_err(CommandError::from)).await?
+    };
+    if stored_hash.as_deref() == Some(hash.as_str()) {
+        return Ok(false);
+    }
+    
+    let chunks = knowledge::chunk_text(&text, collection.chunk_size as usize, collection.chunk_overlap as usize);
+    let embeddings = knowledge::embed_texts(client, provider, &collection.embedding_model, &chunks).await?;
+    let chunks: Vec<(String, Vec<f32>)> = chunks.into_iter().zip(embeddings).collect();
+    
+    app_state.with_db(move |conn| {
+        db::replace_kb_document(conn, &collection_id, &path, &hash, text.len() as i64, &chunks)?;
+        Ok(true)
+    }).await
+}
+
+// Tauri commands for knowledge bases
+#[tauri::command]
+async fn get_kb_collections(app_state: State<'_, AppState>) -> Result<Vec<db::KbCollection>, CommandError> {
+    app_state.with_db(move |conn| db::get_kb_collections(conn).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn create_kb_collection(
+    app_state: State<'_, AppState>,
+    collection: KbCollectionRequest
+) -> Result<String, CommandError> {
+    let name = collection.name.trim().to_string();
+    if name.is_empty() {
+        return Err(CommandError::invalid_input("Knowledge base name is required"));
+    }
+    let (Some(provider_id), Some(model)) = (collection.provider_id, collection.embedding_model.map(|m| m.trim().to_string())) else {
+        return Err(CommandError::invalid_input("An embedding provider and model are required"));
+    };
+    if model.is_empty() {
+        return Err(CommandError::invalid_input("An embedding provider and model are required"));
+    }
+    let chunk_size = collection.chunk_size.unwrap_or(knowledge::DEFAULT_CHUNK_SIZE);
+    let chunk_overlap = collection.chunk_overlap.unwrap_or(knowledge::DEFAULT_CHUNK_OVERLAP);
+    if chunk_size < 100 || chunk_overlap >= chunk_size {
+        return Err(CommandError::invalid_input("Chunks must be at least 100 characters and longer than their overlap"));
+    }
+    
+    app_state.with_db(move |conn| {
+        if db::get_provider_by_id(conn, &provider_id)?.is_none() {
+            return Err(CommandError::not_found("Provider not found"));
+        }
+        db::create_kb_collection(conn, &name, collection.description.as_deref(), &provider_id, &model, chunk_size as i64, chunk_overlap as i64)
+            .map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn update_kb_collection(
+    app_state: State<'_, AppState>,
+    collection: KbCollectionRequest
+) -> Result<(), CommandError> {
+    let id = collection.id.clone()
+        .ok_or_else(|| CommandError::invalid_input("Knowledge base ID is required for update"))?;
+    if collection.name.trim().is_empty() {
+        return Err(CommandError::invalid_input("Knowledge base name is required"));
+    }
+    
+    app_state.with_db(move |conn| {
+        if db::update_kb_collection(conn, &id, collection.name.trim(), collection.description.as_deref())? {
+            Ok(())
+        } else {
+            Err(CommandError::not_found("Knowledge base not found"))
+        }
+    }).await
+}
+
+#[tauri::command]
+async fn delete_kb_collection(
+    app_state: State<'_, AppState>,
+    id: String
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::delete_kb_collection(conn, &id).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn get_kb_documents(
+    app_state: State<'_, AppState>,
+    collection_id: String
+) -> Result<Vec<db::KbDocument>, CommandError> {
+    app_state.with_db(move |conn| db::get_kb_documents(conn, &collection_id).map_err(CommandError::from)).await
+}
+
+// Index files and directories into a collection, reporting progress with
+// `kb-index-progress` events
+#[tauri::command]
+async fn add_kb_documents(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    collection_id: String,
+    paths: Vec<String>
+) -> Result<KbIndexReport, CommandError> {
+    let paths: Vec<std::path::PathBuf> = paths.into_iter().map(std::path::PathBuf::from).collect();
+    index_kb_files(&app_handle, &app_state, &collection_id, &paths).await
+}
+
+// Re-read every document of a collection and re-embed the ones that changed
+#[tauri::command]
+async fn reindex_kb_collection(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    collection_id: String
+) -> Result<KbIndexReport, CommandError> {
+    let id = collection_id.clone();
+    let paths: Vec<std::path::PathBuf> = app_state.with_db(move |conn| {
+        let documents = db::get_kb_documents(conn, &id)?;
+        Ok(documents.into_iter().map(|d| std::path::PathBuf::from(d.path)).collect())
+    }).await?;
+    index_kb_files(&app_handle, &app_state, &collection_id, &paths).await
+}
+
+#[tauri::command]
+async fn delete_kb_document(
+    app_state: State<'_, AppState>,
+    id: String
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::delete_kb_document(conn, &id).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn search_kb(
+    app_state: State<'_, AppState>,
+    request: KbSearchRequest
+) -> Result<Vec<db::Citation>, CommandError> {
+    let top_k = request.top_k.unwrap_or(knowledge::DEFAULT_TOP_K);
+    retrieve_context(&app_state, &request.collection_ids, &request.query, top_k).await
+}
+
+#[tauri::command]
+async fn get_session_citations(
+    app_state: State<'_, AppState>,
+    session_id: String
+) -> Result<Vec<db::MessageCitation>, CommandError> {
+    app_state.with_db(move |conn| db::get_session_citations(conn, &session_id).map_err(CommandError::from)).await
+}
+
+// Where memories saved by the model during a request apply
+struct MemoryContext {
+    assistant_id: Option<String>,
+    folder_id: Option<String>,
+    // Latest user message of the session
+    source_message_id: Option<String>,
+}
+
+// Answer a save_memory call from the model. Scopes without an assistant or
+// folder in the current conversation fall back to global. Tool calls are
+// answered synchronously from within the chat request, so the insert runs in
+// `block_in_place` to keep it from holding up other tasks on this worker.
+fn save_memory_from_tool(app_state: &AppState, context: &MemoryContext, call: &ai::ToolCall) -> String {
+    if call.name != chat::SAVE_MEMORY_TOOL {
+        return format!("Unknown tool: {}", call.name);
+    }
+    let args: chat::SaveMemoryArgs = match serde_json::from_str(&call.arguments) {
+        Ok(args) => args,
+        Err(e) => return format!("Invalid arguments: {}", e),
+    };
+    if args.content.trim().is_empty() {
+        return "Nothing to remember".to_string();
+    }
+    
+    let (scope, scope_id) = match args.scope.unwrap_or(db::MemoryScope::Global) {
+        db::MemoryScope::Assistant if context.assistant_id.is_some() => (db::MemoryScope::Assistant, context.assistant_id.as_deref()),
+        db::MemoryScope::Folder if context.folder_id.is_some() => (db::MemoryScope::Folder, context.folder_id.as_deref()),
+        _ => (db::MemoryScope::Global, None),
+    };
+    let saved = tokio::task::block_in_place(|| {
+        let conn = app_state.conn()?;
+        db::add_memory(&conn, args.content.trim(), scope, scope_id, context.source_message_id.as_deref())
+            .map_err(CommandError::from)
+    });
+    match saved {
+        Ok(_) => format!("Saved to {} memories", scope.as_str()),
+        Err(e) => format!("Could not save memory: {}", e),
+    }
+}
+
+// Scope id stored with a memory: required for assistant and folder memories,
+// dropped for global ones
+fn memory_scope_id(scope: db::MemoryScope, scope_id: Option<&str>) -> Result<Option<&str>, CommandError> {
+    match (scope, scope_id.filter(|id| !id.is_empty())) {
+        (db::MemoryScope::Global, _) => Ok(None),
+        (_, Some(id)) => Ok(Some(id)),
+        (_, None) => Err(CommandError::invalid_input(format!("A {} memory needs a scope id", scope.as_str()))),
+    }
+}
+
+// Tauri commands for memories
+#[tauri::command]
+async fn get_memories(
+    app_state: State<'_, AppState>,
+    filter: Option<db::MemoryFilter>
+) -> Result<Vec<db::Memory>, CommandError> {
+    app_state.with_db(move |conn| db::get_memories(conn, &filter.unwrap_or_default()).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn add_memory(
+    app_state: State<'_, AppState>,
+    memory: MemoryRequest
+) -> Result<String, CommandError> {
+    if memory.content.trim().is_empty() {
+        return Err(CommandError::invalid_input("Memory content is required"));
+    }
+    let scope_id = memory_scope_id(memory.scope, memory.scope_id.as_deref())?.map(str::to_string);
+    
+    app_state.with_db(move |conn| {
+        db::add_memory(conn, memory.content.trim(), memory.scope, scope_id.as_deref(), memory.source_message_id.as_deref())
+            .map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn update_memory(
+    app_state: State<'_, AppState>,
+    memory: MemoryRequest
+) -> Result<(), CommandError> {
+    let id = memory.id.clone()
+        .ok_or_else(|| CommandError::invalid_input("Memory ID is required for update"))?;
+    if memory.content.trim().is_empty() {
+        return Err(CommandError::invalid_input("Memory content is required"));
+    }
+    let scope_id = memory_scope_id(memory.scope, memory.scope_id.as_deref())?.map(str::to_string);
+    
+    app_state.with_db(move |conn| {
+        if db::update_memory(conn, &id, memory.content.trim(), memory.scope, scope_id.as_deref())? {
+            Ok(())
+        } else {
+            Err(CommandError::not_found("Memory not found"))
+        }
+    }).await
+}
+
+#[tauri::command]
+async fn delete_memory(
+    app_state: State<'_, AppState>,
+    id: String
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::delete_memory(conn, &id).map_err(CommandError::from)).await
+}
+
+// Send a chat request to a provider using the API matching its type.
+// `on_delta` receives streamed output for models that use the Responses API.
+async fn dispatch_chat(
+    app_state: &AppState,
+    provider: db::AIProvider,
+    model_name: &str,
+    api_type: ai::ApiType,
+    messages: Vec<ai::ChatMessage>,
+    previous_response_id: Option<String>,
+    on_delta: &mut (dyn FnMut(ai::StreamDelta) + Send),
+) -> Result<ai::AIResponse, CommandError> {
+    let params = chat::ChatParams {
+        model_name,
+        api_type,
+        messages,
+        previous_response_id,
+        stream: false,
+        tools: Vec::new(),
+        on_tool_call: None,
+    };
+    send_chat(app_state, provider, params, on_delta).await
+}
+
+// Send a chat request with the AI client configured for the provider
+async fn send_chat(
+    app_state: &AppState,
+    provider: db::AIProvider,
+    params: chat::ChatParams<'_>,
+    on_delta: &mut (dyn FnMut(ai::StreamDelta) + Send),
+) -> Result<ai::AIResponse, CommandError> {
+    // Get a copy of the AI client configured for this provider
+    let ai_client = provider_client(app_state, &provider.id).await?;
+    let azure_settings = load_azure_settings(app_state, &provider.id).await?;
+    
+    chat::dispatch_chat(&ai_client, &provider, &azure_settings, params, on_delta)
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn set_model_api_type(
+    app_state: State<'_, AppState>,
+    request: ModelApiTypeRequest
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| {
+        ensure_model_unmanaged(conn, &request.model_id)?;
+        db::set_model_api_type(conn, &request.model_id, request.api_type).map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn get_retry_policy(app_state: State<'_, AppState>) -> Result<ai::RetryPolicy, CommandError> {
+    let client = app_state.ai_client.lock()?;
+    Ok(client.retry_policy().clone())
+}
+
+#[tauri::command]
+async fn set_retry_policy(
+    app_state: State<'_, AppState>,
+    policy: ai::RetryPolicy
+) -> Result<(), CommandError> {
+    let value = serde_json::to_string(&policy)?;
+    app_state.with_db(move |conn| db::set_setting(conn, chat::RETRY_POLICY_SETTING, &value).map_err(CommandError::from)).await?;
+
+    let mut client = app_state.ai_client.lock()?;
+    client.set_retry_policy(policy);
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ApiServerSettingsRequest {
+    enabled: bool,
+    port: Option<u16>,
+}
+
+#[derive(serde::Serialize)]
+struct ApiServerStatus {
+    enabled: bool,
+    running: bool,
+    port: u16,
+    base_url: Option<String>,
+    token: String,
+}
+
+// Start, restart or stop the local API server to match the saved settings.
+// Also called from setup, before the async runtime serves commands.
+fn apply_api_server_settings(app_handle: &tauri::AppHandle, app_state: &AppState) -> Result<(), CommandError> {
+    let settings = {
+        let conn = app_state.conn()?;
+        api_server::load_settings(&conn)?
+    };
+    
+    let mut server = app_state.api_server.lock()?;
+    if let Some(running) = server.take() {
+        running.stop();
+    }
+    if settings.enabled {
+        let handle = api_server::start(app_handle.clone(), settings.port).map_err(|e| {
+            CommandError::invalid_input(format!("Could not listen on port {}: {}", settings.port, e))
+        })?;
+        *server = Some(handle);
+    }
+    Ok(())
+}
+
+async fn api_server_status(app_state: &AppState) -> Result<ApiServerStatus, CommandError> {
+    let (settings, token) = app_state.with_db(move |conn| {
+        Ok((api_server::load_settings(conn)?, api_server::access_token(conn)?))
+    }).await?;
+    let addr = app_state.api_server.lock()?.as_ref().map(|s| s.addr());
+    
+    Ok(ApiServerStatus {
+        enabled: settings.enabled,
+        running: addr.is_some(),
+        port: addr.map(|a| a.port()).unwrap_or(settings.port),
+        base_url: addr.map(|a| format!("http://{}/v1", a)),
+        token,
+    })
+}
+
+// Tauri commands for the local OpenAI-compatible API server
+#[tauri::command]
+async fn get_api_server_status(app_state: State<'_, AppState>) -> Result<ApiServerStatus, CommandError> {
+    api_server_status(&app_state).await
+}
+
+#[tauri::command]
+async fn set_api_server_settings(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    request: ApiServerSettingsRequest
+) -> Result<ApiServerStatus, CommandError> {
+    app_state.with_db(move |conn| {
+        db::set_setting(conn, api_server::ENABLED_SETTING, if request.enabled { "true" } else { "false" })?;
+        if let Some(port) = request.port {
+            db::set_setting(conn, api_server::PORT_SETTING, &port.to_string())?;
+        }
+        Ok(())
+    }).await?;
+    
+    apply_api_server_settings(&app_handle, &app_state)?;
+    api_server_status(&app_state).await
+}
+
+#[tauri::command]
+async fn regenerate_api_server_token(app_state: State<'_, AppState>) -> Result<String, CommandError> {
+    app_state.with_db(move |conn| {
+        api_server::regenerate_token(conn).map_err(CommandError::from)
+    }).await
+}
+
+#[tauri::command]
+async fn get_api_server_logs(
+    app_state: State<'_, AppState>,
+    limit: Option<u32>
+) -> Result<Vec<db::ApiServerLog>, CommandError> {
+    app_state.with_db(move |conn| db::get_api_server_logs(conn, limit.unwrap_or(100)).map_err(CommandError::from)).await
+}
+
+#[tauri::command]
+async fn clear_api_server_logs(app_state: State<'_, AppState>) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| db::clear_api_server_logs(conn).map_err(CommandError::from)).await
+}
+
+// Load what depends on the database once it is open, at startup or after unlocking
+fn load_database_settings(app_handle: &tauri::AppHandle, app_state: &AppState) {
+    if let Err(e) = restore_retry_policy(app_state) {
+        eprintln!("Could not restore retry policy: {}", e);
+    }
+    if let Err(e) = restore_app_lock(app_state) {
+        eprintln!("Could not restore app lock: {}", e);
+    }
+    
+    // Start the local API server if it was left enabled
+    if let Err(e) = apply_api_server_settings(app_handle, app_state) {
+        eprintln!("Could not start local API server: {}", e);
+    }
+}
+
+// Restore the saved retry policy, falling back to defaults if it is missing or invalid
+fn restore_retry_policy(app_state: &AppState) -> Result<(), CommandError> {
+    let value = {
+        let conn = app_state.conn()?;
+        db::get_setting(&conn, chat::RETRY_POLICY_SETTING)?
+    };
+    let policy = match value.map(|v| serde_json::from_str::<ai::RetryPolicy>(&v)) {
+        Some(Ok(policy)) => policy,
+        Some(Err(e)) => {
+            eprintln!("Ignoring invalid retry policy setting: {}", e);
+            ai::RetryPolicy::default()
+        }
+        None => ai::RetryPolicy::default(),
+    };
+    app_state.ai_client.lock()?.set_retry_policy(policy);
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct EncryptionStatus {
+    encrypted: bool,
+    // "keyring" or "passphrase" when encrypted
+    key_source: Option<String>,
+    locked: bool,
+}
+
+#[derive(Deserialize)]
+struct EnableEncryptionRequest {
+    // Omitted to encrypt with a generated key kept in the OS keyring
+    passphrase: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChangeDatabaseKeyRequest {
+    // Needed unless the current key is in the OS keyring
+    current_passphrase: Option<String>,
+    // Omitted to switch to a generated key kept in the OS keyring
+    new_passphrase: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DisableEncryptionRequest {
+    current_passphrase: Option<String>,
+}
+
+fn encryption_status(app_state: &AppState) -> Result<EncryptionStatus, CommandError> {
+    let encrypted = db::is_encrypted(&app_state.data_dir()?);
+    let key_source = encrypted.then(|| {
+        if keyring_database_key().is_some() { "keyring" } else { "passphrase" }.to_string()
+    });
+    Ok(EncryptionStatus {
+        encrypted,
+        key_source,
+        locked: app_state.db.read()?.is_none(),
+    })
+}
+
+// New key from an optional passphrase, generating one for the keyring when omitted
+fn new_database_key(passphrase: Option<String>) -> Result<db::DatabaseKey, CommandError> {
+    match passphrase {
+        Some(passphrase) if passphrase.is_empty() => Err(CommandError::invalid_input("Passphrase cannot be empty")),
+        Some(passphrase) => Ok(db::DatabaseKey::Passphrase(passphrase)),
+        None => Ok(db::DatabaseKey::generate()),
+    }
+}
+
+// Key the database is currently encrypted with, from the keyring or the
+// passphrase the user confirmed
+async fn current_database_key(app_state: &AppState, passphrase: Option<String>) -> Result<db::DatabaseKey, CommandError> {
+    let key = match (keyring_database_key(), passphrase) {
+        (Some(key), _) => key,
+        (None, Some(passphrase)) => db::DatabaseKey::Passphrase(passphrase),
+        (None, None) => return Err(CommandError::invalid_input("Enter the current passphrase")),
     };
     
-    // Check if API key is set
-    if provider.api_key.is_none() {
-        return Err("API key not set for this provider. Please set an API key in the Providers page.".to_string());
+    let data_dir = app_state.data_dir()?;
+    let candidate = key.clone();
+    let valid = tauri::async_runtime::spawn_blocking(move || db::verify_key(&data_dir, &candidate))
+        .await
+        .map_err(|e| CommandError::Internal { message: format!("Database task failed: {}", e) })?;
+    if !valid {
+        return Err(CommandError::invalid_input("Incorrect passphrase"));
+    }
+    Ok(key)
+}
+
+// Re-key the database, encrypting or decrypting it when `old_key` or
+// `new_key` is None, then reopen the pool with the key in effect. The caller
+// makes sure no other connection is in use (see `rekey_open_database`).
+fn rekey_pool(
+    data_dir: &Path,
+    pool: db::DbPool,
+    old_key: Option<db::DatabaseKey>,
+    new_key: Option<db::DatabaseKey>,
+) -> (Option<db::DbPool>, Result<(), CommandError>) {
+    // An encrypted database is re-keyed in place; adding or removing
+    // encryption needs an exported copy
+    let in_place = old_key.is_some() && new_key.is_some();
+    let changed = pool.get().map_err(CommandError::from).and_then(|conn| {
+        let result = match &new_key {
+            Some(key) if in_place => db::rekey_database(&conn, key),
+            _ => db::export_database(&conn, data_dir, new_key.as_ref()),
+        };
+        result.map_err(CommandError::from)
+    });
+    if let Err(e) = changed {
+        return (Some(pool), Err(e));
     }
+    drop(pool);
     
-    // Get the actual model name from the database
-    let model_name = {
-        let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-        
-        // 使用model_id作为参数，查找对应的模型
-        let models = db::get_models_by_provider(&conn, &request.provider_id)
-            .map_err(|e| e.to_string())?;
-        
-        let model = models.iter()
-            .find(|m| m.id == request.model_id);
-            
-        match model {
-            Some(found_model) => found_model.name.clone(),
-            None => {
-                // 如果在数据库中找不到这个model_id，直接使用model_id作为模型名
-                // 这解决了自定义API可能将UUID作为模型名的问题
-                request.model_id.clone()
-            }
-        }
+    let replaced = if in_place {
+        Ok(())
+    } else {
+        db::replace_with_export(data_dir).map_err(|e| CommandError::Internal {
+            message: format!("Could not replace the database file: {}", e),
+        })
     };
+    let key = if replaced.is_ok() { new_key } else { old_key };
+    match db::open_pool(data_dir, key) {
+        Ok(pool) => (Some(pool), replaced),
+        Err(message) => (None, replaced.and(Err(CommandError::Database { message }))),
+    }
+}
+
+// Re-key the open database once every running database task has finished.
+// Tasks started meanwhile wait and then use the reopened pool.
+async fn rekey_open_database(
+    app_state: &AppState,
+    old_key: Option<db::DatabaseKey>,
+    new_key: Option<db::DatabaseKey>,
+) -> Result<(), CommandError> {
+    let _exclusive = app_state.db_access.write().await;
+    let pool = app_state.pool()?;
+    *app_state.db.write()? = None;
+    let data_dir = app_state.data_dir()?;
+    let (pool, result) = tauri::async_runtime::spawn_blocking(move || rekey_pool(&data_dir, pool, old_key, new_key))
+        .await
+        .map_err(|e| CommandError::Internal { message: format!("Database task failed: {}", e) })?;
+    *app_state.db.write()? = pool;
+    result
+}
+
+// Move the open database from `old_key` to `new_key`, keeping the keyring in
+// step: a generated key is stored before it is used, and the previous entry
+// comes back if re-keying fails
+async fn change_database_key(
+    app_state: &AppState,
+    old_key: Option<db::DatabaseKey>,
+    new_key: Option<db::DatabaseKey>,
+) -> Result<(), CommandError> {
+    let previous = credentials::get_database_key().ok();
+    if let Some(db::DatabaseKey::Raw(hex)) = &new_key {
+        credentials::store_database_key(hex)
+            .map_err(|e| CommandError::Internal { message: format!("Could not store the key in the keyring: {}", e) })?;
+    }
     
-    // Get information needed for API call
-    let api_url = provider.api_url.clone();
-    let api_key = provider.api_key.unwrap();
-    let provider_name = provider.name.clone();
-    let messages = request.messages.clone();
+    let keep_keyring_entry = matches!(new_key, Some(db::DatabaseKey::Raw(_)));
+    let result = rekey_open_database(app_state, old_key, new_key).await;
     
-    // Get a cloned copy of the AI client
-    let ai_client = {
-        let guard = app_state.ai_client.lock().map_err(|e| e.to_string())?;
-        guard.clone()
+    let entry = match (&result, keep_keyring_entry) {
+        (Err(_), _) => previous,
+        (Ok(()), true) => return Ok(()),
+        (Ok(()), false) => None,
+    };
+    let restored = match entry {
+        Some(key) => credentials::store_database_key(&key),
+        None => credentials::delete_database_key(),
+    };
+    if let Err(e) = restored {
+        eprintln!("Could not update the database key in the keyring: {}", e);
+    }
+    result
+}
+
+// Tauri commands for database encryption
+#[tauri::command]
+async fn get_database_encryption(app_state: State<'_, AppState>) -> Result<EncryptionStatus, CommandError> {
+    encryption_status(&app_state)
+}
+
+#[tauri::command]
+async fn unlock_database(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    passphrase: String
+) -> Result<EncryptionStatus, CommandError> {
+    if app_state.db.read()?.is_none() {
+        let key = current_database_key(&app_state, Some(passphrase)).await?;
+        let data_dir = app_state.data_dir()?;
+        let pool = tauri::async_runtime::spawn_blocking(move || db::open_pool(&data_dir, Some(key)))
+            .await
+            .map_err(|e| CommandError::Internal { message: format!("Database task failed: {}", e) })?
+            .map_err(|message| CommandError::Database { message })?;
+        *app_state.db.write()? = Some(pool);
+        load_database_settings(&app_handle, &app_state);
+    }
+    encryption_status(&app_state)
+}
+
+#[tauri::command]
+async fn enable_database_encryption(
+    app_state: State<'_, AppState>,
+    request: EnableEncryptionRequest
+) -> Result<EncryptionStatus, CommandError> {
+    if db::is_encrypted(&app_state.data_dir()?) {
+        return Err(CommandError::invalid_input("The database is already encrypted"));
+    }
+    let key = new_database_key(request.passphrase)?;
+    change_database_key(&app_state, None, Some(key)).await?;
+    encryption_status(&app_state)
+}
+
+#[tauri::command]
+async fn change_database_passphrase(
+    app_state: State<'_, AppState>,
+    request: ChangeDatabaseKeyRequest
+) -> Result<EncryptionStatus, CommandError> {
+    if !db::is_encrypted(&app_state.data_dir()?) {
+        return Err(CommandError::invalid_input("The database is not encrypted"));
+    }
+    let old_key = current_database_key(&app_state, request.current_passphrase).await?;
+    let new_key = new_database_key(request.new_passphrase)?;
+    change_database_key(&app_state, Some(old_key), Some(new_key)).await?;
+    encryption_status(&app_state)
+}
+
+#[tauri::command]
+async fn disable_database_encryption(
+    app_state: State<'_, AppState>,
+    request: DisableEncryptionRequest
+) -> Result<EncryptionStatus, CommandError> {
+    if !db::is_encrypted(&app_state.data_dir()?) {
+        return Err(CommandError::invalid_input("The database is not encrypted"));
+    }
+    let old_key = current_database_key(&app_state, request.current_passphrase).await?;
+    change_database_key(&app_state, Some(old_key), None).await?;
+    encryption_status(&app_state)
+}
+
+// How often the idle timer checks whether the app should lock
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Apply the saved app lock settings
+fn restore_app_lock(app_state: &AppState) -> Result<(), CommandError> {
+    let settings = {
+        let conn = app_state.conn()?;
+        app_lock::load_settings(&conn)?
     };
+    app_state.app_lock.lock()?.configure(&settings);
+    Ok(())
+}
+
+// Lock the app once it has been idle too long and tell the frontend
+fn spawn_idle_lock_timer(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let app_state = app_handle.state::<AppState>();
+            let locked = match app_state.app_lock.lock() {
+                Ok(mut lock) => lock.lock_if_idle(Instant::now()),
+                Err(_) => false,
+            };
+            if locked {
+                let _ = app_handle.emit("app-locked", ());
+            }
+        }
+    });
+}
+
+// How often the catalog timer checks whether a sync is due
+const CATALOG_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Fetch the team catalog and merge it, recording the outcome for the status view
+async fn sync_catalog_now(app_state: &AppState) -> Result<catalog::CatalogSyncSummary, CommandError> {
+    let settings = app_state.with_db(move |conn| catalog::load_settings(conn).map_err(CommandError::from)).await?;
+    let source = settings.source.ok_or_else(|| CommandError::invalid_input("No team catalog source is set"))?;
+
+    let result = async {
+        let text = catalog::load_catalog_text(&source).await?;
+        let parsed = catalog::parse_catalog(&text)?;
+        app_state.with_db(move |conn| catalog::apply_catalog(conn, &parsed).map_err(CommandError::from)).await
+    }.await;
+
+    let error = result.as_ref().err().map(|e| e.to_string());
+    app_state.with_db(move |conn| {
+        catalog::record_sync(conn, db::get_current_timestamp(), error.as_deref()).map_err(CommandError::from)
+    }).await?;
+    result
+}
+
+// Re-sync the team catalog at its configured interval and tell the frontend
+fn spawn_catalog_sync_timer(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(CATALOG_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let app_state = app_handle.state::<AppState>();
+            // Nothing to do while the database is locked
+            let Ok(status) = app_state.with_db(move |conn| catalog::load_status(conn).map_err(CommandError::from)).await else {
+                continue;
+            };
+            if !catalog::sync_due(&status, db::get_current_timestamp()) {
+                continue;
+            }
+            match sync_catalog_now(&app_state).await {
+                Ok(summary) => {
+                    let _ = app_handle.emit("catalog-synced", summary);
+                }
+                Err(e) => eprintln!("Team catalog sync failed: {}", e),
+            }
+        }
+    });
+}
+
+// Check a passphrase against the saved hash, off the async runtime since
+// Argon2 is deliberately slow
+async fn verify_app_passphrase(app_state: &AppState, passphrase: String) -> Result<bool, CommandError> {
+    app_state.with_db(move |conn| {
+        let settings = app_lock::load_settings(conn)?;
+        Ok(settings.passphrase_hash.is_some_and(|hash| app_lock::verify_passphrase(&passphrase, &hash)))
+    }).await
+}
+
+#[derive(Deserialize)]
+struct AppLockPassphraseRequest {
+    // Needed when a passphrase is already set
+    current_passphrase: Option<String>,
+    passphrase: String,
+}
+
+// Tauri commands for the app lock
+#[tauri::command]
+async fn get_app_lock_status(app_state: State<'_, AppState>) -> Result<app_lock::LockStatus, CommandError> {
+    Ok(app_state.app_lock.lock()?.status())
+}
+
+#[tauri::command]
+async fn set_app_lock_passphrase(
+    app_state: State<'_, AppState>,
+    request: AppLockPassphraseRequest
+) -> Result<app_lock::LockStatus, CommandError> {
+    if request.passphrase.is_empty() {
+        return Err(CommandError::invalid_input("Passphrase cannot be empty"));
+    }
+    
+    app_state.with_db(move |conn| {
+        if let Some(hash) = app_lock::load_settings(conn)?.passphrase_hash {
+            let current = request.current_passphrase.unwrap_or_default();
+            if !app_lock::verify_passphrase(&current, &hash) {
+                return Err(CommandError::invalid_input("Incorrect passphrase"));
+            }
+        }
+        let hash = app_lock::hash_passphrase(&request.passphrase)?;
+        db::set_setting(conn, app_lock::PASSPHRASE_HASH_SETTING, &hash).map_err(CommandError::from)
+    }).await?;
     
-    // Determine provider type using the same logic as fetch_models_from_provider
-    let provider_type = determine_provider_type(&request.provider_id, &api_url, &provider_name);
+    restore_app_lock(&app_state)?;
+    Ok(app_state.app_lock.lock()?.status())
+}
 
-    // Call appropriate API based on provider type
-    match provider_type.as_str() {
-        "openai" => {
-            ai_client.openai_chat(&api_url, &api_key, &model_name, messages).await
-        },
-        "deepseek" => {
-            // DeepSeek uses OpenAI-compatible API
-            ai_client.openai_chat(&api_url, &api_key, &model_name, messages).await
-        },
-        "grok" => {
-            // Grok uses OpenAI-compatible API
-            ai_client.openai_chat(&api_url, &api_key, &model_name, messages).await
-        },
-        "gemini" => {
-            ai_client.gemini_chat(&api_url, &api_key, &model_name, messages).await
-        },
-        "custom" => {
-            // For custom providers, default to OpenAI-compatible API
-            ai_client.openai_chat(&api_url, &api_key, &model_name, messages).await
-        },
-        _ => {
-            // Default to OpenAI-compatible API for unknown types
-            ai_client.openai_chat(&api_url, &api_key, &model_name, messages).await
+#[tauri::command]
+async fn disable_app_lock(
+    app_state: State<'_, AppState>,
+    passphrase: String
+) -> Result<app_lock::LockStatus, CommandError> {
+    if !verify_app_passphrase(&app_state, passphrase).await? {
+        return Err(CommandError::invalid_input("Incorrect passphrase"));
+    }
+    app_state.with_db(move |conn| {
+        db::delete_setting(conn, app_lock::PASSPHRASE_HASH_SETTING).map_err(CommandError::from)
+    }).await?;
+    
+    restore_app_lock(&app_state)?;
+    Ok(app_state.app_lock.lock()?.status())
+}
+
+#[tauri::command]
+async fn set_app_lock_idle_timeout(
+    app_state: State<'_, AppState>,
+    minutes: u32
+) -> Result<app_lock::LockStatus, CommandError> {
+    app_state.with_db(move |conn| {
+        db::set_setting(conn, app_lock::IDLE_MINUTES_SETTING, &minutes.to_string()).map_err(CommandError::from)
+    }).await?;
+    
+    restore_app_lock(&app_state)?;
+    Ok(app_state.app_lock.lock()?.status())
+}
+
+#[tauri::command]
+async fn lock_app(app_state: State<'_, AppState>) -> Result<app_lock::LockStatus, CommandError> {
+    let mut lock = app_state.app_lock.lock()?;
+    if !lock.status().enabled {
+        return Err(CommandError::invalid_input("Set an app lock passphrase first"));
+    }
+    lock.lock();
+    Ok(lock.status())
+}
+
+#[tauri::command]
+async fn unlock_app(
+    app_state: State<'_, AppState>,
+    passphrase: String
+) -> Result<app_lock::LockStatus, CommandError> {
+    {
+        let mut lock = app_state.app_lock.lock()?;
+        if !lock.is_locked() {
+            return Ok(lock.status());
+        }
+        match lock.begin_unlock(Instant::now()) {
+            Ok(()) => {}
+            Err(app_lock::UnlockRefused::InProgress) => {
+                return Err(CommandError::invalid_input("Another unlock attempt is being checked"));
+            }
+            Err(app_lock::UnlockRefused::Delayed(delay)) => {
+                return Err(CommandError::invalid_input(format!(
+                    "Too many incorrect passphrases, try again in {} seconds",
+                    delay.as_secs().max(1)
+                )));
+            }
+        }
+    }
+    
+    let verified = verify_app_passphrase(&app_state, passphrase).await;
+    let mut lock = app_state.app_lock.lock()?;
+    match verified {
+        Ok(true) => lock.unlock(Instant::now()),
+        Ok(false) => {
+            lock.record_failed_unlock(Instant::now());
+            return Err(CommandError::invalid_input("Incorrect passphrase"));
+        }
+        Err(e) => {
+            lock.cancel_unlock();
+            return Err(e);
+        }
+    }
+    Ok(lock.status())
+}
+
+#[derive(serde::Serialize)]
+struct DataLocationStatus {
+    #[serde(flatten)]
+    location: profiles::DataLocation,
+    profiles: Vec<String>,
+    // The data directory setting, when one is saved
+    data_dir_setting: Option<PathBuf>,
+    // Set when --data-dir or AICHAT_DATA_DIR overrides the setting
+    overridden: bool,
+    locked: bool,
+}
+
+fn data_location_status(app_state: &AppState) -> Result<DataLocationStatus, CommandError> {
+    let location = app_state.location.read()?.clone();
+    let config = profiles::load_config(&profiles::config_path()?)?;
+    Ok(DataLocationStatus {
+        profiles: profiles::list_profiles(&location.root),
+        location,
+        data_dir_setting: config.data_dir,
+        overridden: app_state.data_root_overridden,
+        locked: app_state.db.read()?.is_none(),
+    })
+}
+
+// Update the saved config
+fn update_config(update: impl FnOnce(&mut profiles::Config)) -> Result<(), CommandError> {
+    let path = profiles::config_path()?;
+    let mut config = profiles::load_config(&path)?;
+    update(&mut config);
+    profiles::save_config(&path, &config).map_err(CommandError::from)
+}
+
+// Close the open database and open the one of another profile or data root.
+// An encrypted database whose key is not in the profile's keyring stays
+// locked until the user unlocks it.
+async fn open_location(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    location: profiles::DataLocation,
+) -> Result<(), CommandError> {
+    // The database key is read from the new profile's credentials; the old
+    // profile stays in use if the database cannot be opened
+    let previous_profile = app_state.location.read()?.profile.clone();
+    credentials::set_profile(&location.profile);
+    let dir = location.dir.clone();
+    let pool = tauri::async_runtime::spawn_blocking(move || init_database(&dir))
+        .await
+        .map_err(|e| CommandError::Internal { message: format!("Database task failed: {}", e) })
+        .and_then(|pool| pool.map_err(|message| CommandError::Database { message }));
+    let pool = match pool {
+        Ok(pool) => pool,
+        Err(e) => {
+            credentials::set_profile(&previous_profile);
+            return Err(e);
+        }
+    };
+    
+    let opened = pool.is_some();
+    *app_state.db.write()? = pool;
+    *app_state.location.write()? = location;
+    // Cached listings belong to the previous profile's providers
+    app_state.model_listings.lock()?.clear();
+    
+    if opened {
+        load_database_settings(app_handle, app_state);
+    } else {
+        // Nothing is loaded from a locked database until it is unlocked
+        if let Some(running) = app_state.api_server.lock()?.take() {
+            running.stop();
         }
-    }.map_err(|e| e.to_string())
+        *app_state.app_lock.lock()? = app_lock::AppLock::default();
+    }
+    Ok(())
+}
+
+// Tauri commands for data directories and profiles
+#[tauri::command]
+async fn get_data_location(app_state: State<'_, AppState>) -> Result<DataLocationStatus, CommandError> {
+    data_location_status(&app_state)
+}
+
+#[tauri::command]
+async fn create_profile(
+    app_state: State<'_, AppState>,
+    name: String
+) -> Result<DataLocationStatus, CommandError> {
+    let root = app_state.location.read()?.root.clone();
+    profiles::validate_profile_name(&name).map_err(CommandError::invalid_input)?;
+    if profiles::profile_exists(&root, &name) {
+        return Err(CommandError::invalid_input(format!("Profile '{}' already exists", name)));
+    }
+    profiles::locate(&root, &name)?;
+    data_location_status(&app_state)
+}
+
+#[tauri::command]
+async fn switch_profile(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    name: String
+) -> Result<DataLocationStatus, CommandError> {
+    let root = app_state.location.read()?.root.clone();
+    if !profiles::profile_exists(&root, &name) {
+        return Err(CommandError::not_found(format!("Profile '{}' not found", name)));
+    }
+    let location = profiles::locate(&root, &name)?;
+    open_location(&app_handle, &app_state, location).await?;
+    update_config(|config| config.profile = Some(name))?;
+    data_location_status(&app_state)
+}
+
+#[tauri::command]
+async fn delete_profile(
+    app_state: State<'_, AppState>,
+    name: String
+) -> Result<DataLocationStatus, CommandError> {
+    let location = app_state.location.read()?.clone();
+    if location.profile == name {
+        return Err(CommandError::invalid_input("Switch to another profile before deleting this one"));
+    }
+    if !profiles::profile_exists(&location.root, &name) {
+        return Err(CommandError::not_found(format!("Profile '{}' not found", name)));
+    }
+    profiles::delete_profile(&location.root, &name).map_err(CommandError::invalid_input)?;
+    if let Err(e) = credentials::delete_profile_credentials(&name) {
+        eprintln!("Could not delete the credentials of profile '{}': {}", name, e);
+    }
+    data_location_status(&app_state)
+}
+
+// Save the data directory and reopen the current profile from it. Existing
+// data is not moved; an empty directory starts with a new database.
+#[tauri::command]
+async fn set_data_directory(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+    path: Option<String>
+) -> Result<DataLocationStatus, CommandError> {
+    if app_state.data_root_overridden {
+        return Err(CommandError::invalid_input(
+            "The data directory is set by --data-dir or AICHAT_DATA_DIR and cannot be changed here"
+        ));
+    }
+    let data_dir = path.filter(|p| !p.trim().is_empty()).map(PathBuf::from);
+    if data_dir.as_ref().is_some_and(|p| !p.is_absolute()) {
+        return Err(CommandError::invalid_input("The data directory must be an absolute path"));
+    }
+    
+    let root = match &data_dir {
+        Some(root) => root.clone(),
+        None => profiles::default_data_root()?,
+    };
+    let profile = app_state.location.read()?.profile.clone();
+    let location = profiles::locate(&root, &profile)?;
+    open_location(&app_handle, &app_state, location).await?;
+    update_config(|config| config.data_dir = data_dir)?;
+    data_location_status(&app_state)
 }
 
 #[tauri::command]
 async fn toggle_model_favorite(
     app_state: State<'_, AppState>,
     request: ToggleFavoriteRequest,
-) -> Result<(), String> {
-    let conn = app_state.db_conn.lock().map_err(|e| e.to_string())?;
-    db::toggle_model_favorite(&conn, &request.model_id, request.is_favorite)
-        .map_err(|e| e.to_string())
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| {
+        db::toggle_model_favorite(conn, &request.model_id, request.is_favorite)
+            .map_err(CommandError::from)
+    }).await
 }
 
+// Check that a model answers with a one-token chat request, recording the
+// result in the health history. Failures are returned with their cause.
 #[tauri::command]
 async fn verify_model(
     app_state: State<'_, AppState>,
     request: VerifyModelRequest,
-) -> Result<bool, String> {
-    // Get provider information
-    let provider = {
-        let conn = app_state.db_conn.lock().unwrap();
-        db::get_provider_by_id(&conn, &request.provider_id)
-            .map_err(|e| e.to_string())?
-            .ok_or("Provider not found")?
-    };
+) -> Result<bool, CommandError> {
+    let outcome = probe_provider(&app_state, &request.provider_id, health::Probe::Chat, Some(request.model_name)).await?;
+    match outcome.error {
+        None => Ok(true),
+        Some(error) => Err(CommandError::Provider(error)),
+    }
+}
+
+// Tauri commands for provider health checks
+#[tauri::command]
+async fn run_health_check(
+    app_state: State<'_, AppState>,
+    provider_id: String,
+    probe: Option<health::Probe>,
+    // Model for the chat probe
+    model: Option<String>
+) -> Result<db::HealthCheck, CommandError> {
+    let probe = probe.unwrap_or(health::Probe::Models);
+    if probe == health::Probe::Chat && model.is_none() {
+        return Err(CommandError::invalid_input("The chat probe needs a model"));
+    }
+    Ok(probe_provider(&app_state, &provider_id, probe, model).await?.check)
+}
+
+#[tauri::command]
+async fn run_all_health_checks(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>
+) -> Result<Vec<db::HealthCheck>, CommandError> {
+    let checks = check_all_providers(&app_state).await?;
+    let _ = app_handle.emit("health-checked", &checks);
+    Ok(checks)
+}
 
-    // Check if API key is available
-    let api_key = provider.api_key.as_ref().ok_or("API key not found for provider")?;
+#[tauri::command]
+async fn get_provider_health(app_state: State<'_, AppState>) -> Result<Vec<health::ProviderHealth>, CommandError> {
+    app_state.with_db(move |conn| health::load_status(conn).map_err(CommandError::from)).await
+}
 
-    // Clone necessary data before async operations
-    let api_url = provider.api_url.clone();
-    let api_key = api_key.clone();
-    let provider_name = provider.name.clone();
+#[tauri::command]
+async fn get_health_history(
+    app_state: State<'_, AppState>,
+    provider_id: Option<String>,
+    limit: Option<u32>
+) -> Result<Vec<db::HealthCheck>, CommandError> {
+    app_state.with_db(move |conn| {
+        db::get_health_checks(conn, provider_id.as_deref(), limit.unwrap_or(100)).map_err(CommandError::from)
+    }).await
+}
 
-    // Determine provider type
-    let provider_type = determine_provider_type(&request.provider_id, &api_url, &provider_name);
+#[tauri::command]
+async fn get_health_check_schedule(app_state: State<'_, AppState>) -> Result<u32, CommandError> {
+    app_state.with_db(move |conn| health::schedule_minutes(conn).map_err(CommandError::from)).await
+}
 
-    // Try to send a simple test message to verify the model works
-    let test_messages = vec![
-        ai::ChatMessage {
-            role: "user".to_string(),
-            content: "Hello".to_string(),
-        }
-    ];
+// Minutes between scheduled checks of all providers; 0 turns them off
+#[tauri::command]
+async fn set_health_check_schedule(
+    app_state: State<'_, AppState>,
+    minutes: u32
+) -> Result<(), CommandError> {
+    app_state.with_db(move |conn| {
+        db::set_setting(conn, health::SCHEDULE_MINUTES_SETTING, &minutes.to_string()).map_err(CommandError::from)
+    }).await
+}
 
-    // Get AI client and clone it for async use
-    let ai_client = {
-        let client = app_state.ai_client.lock().unwrap();
-        client.clone()
-    };
+// A recorded health check and the provider error behind a failed one
+struct ProbeOutcome {
+    check: db::HealthCheck,
+    error: Option<ai::ProviderError>,
+}
 
-    // Call appropriate API based on provider type
-    let result = match provider_type.as_str() {
-        "openai" => {
-            ai_client.openai_chat(&api_url, &api_key, &request.model_name, test_messages).await
-        },
-        "deepseek" => {
-            // DeepSeek uses OpenAI-compatible API
-            ai_client.openai_chat(&api_url, &api_key, &request.model_name, test_messages).await
-        },
-        "grok" => {
-            // Grok uses OpenAI-compatible API
-            ai_client.openai_chat(&api_url, &api_key, &request.model_name, test_messages).await
-        },
-        "gemini" => {
-            ai_client.gemini_chat(&api_url, &api_key, &request.model_name, test_messages).await
-        },
-        "custom" => {
-            // For custom providers, default to OpenAI-compatible API
-            ai_client.openai_chat(&api_url, &api_key, &request.model_name, test_messages).await
-        },
-        _ => {
-            // Default to OpenAI-compatible API for unknown types
-            ai_client.openai_chat(&api_url, &api_key, &request.model_name, test_messages).await
-        }
-    };
+// Probe a provider and record the result
+async fn probe_provider(
+    app_state: &AppState,
+    provider_id: &str,
+    probe: health::Probe,
+    model: Option<String>
+) -> Result<ProbeOutcome, CommandError> {
+    let provider = find_provider(app_state, provider_id).await?;
+    let ai_client = provider_client(app_state, provider_id).await?;
+    let azure_settings = load_azure_settings(app_state, provider_id).await?;
 
-    // Return true if the request was successful, error message if failed
-    match result {
-        Ok(_) => Ok(true),
-        Err(e) => {
-            // Log the error for debugging
-            eprintln!("Model verification failed: {}", e);
-            // Return false for verification failure, but don't expose sensitive error details
-            Ok(false)
+    let result = health::run_probe(ai_client, &provider, &azure_settings, probe, model.as_deref()).await;
+    app_state.with_db(move |conn| {
+        health::record(conn, &provider.id, probe, model.as_deref(), &result)
+            .map(|check| ProbeOutcome { check, error: result.error })
+            .map_err(CommandError::from)
+    }).await
+}
+
+// Run the models probe against every provider that can be reached: those with
+// an API key, and local Ollama servers. A provider that cannot be probed, e.g.
+// because of invalid connection settings, is logged and the run goes on.
+async fn check_all_providers(app_state: &AppState) -> Result<Vec<db::HealthCheck>, CommandError> {
+    let providers = app_state.with_db(move |conn| db::get_all_providers(conn).map_err(CommandError::from)).await?;
+    let mut checks = Vec::new();
+    for provider in providers.into_iter().filter(chat::is_reachable) {
+        match probe_provider(app_state, &provider.id, health::Probe::Models, None).await {
+            Ok(outcome) => checks.push(outcome.check),
+            Err(e) => eprintln!("Could not check provider {}: {}", provider.name, e),
         }
     }
+    Ok(checks)
+}
+
+// How often the health check timer looks whether scheduled checks are due
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// Check all providers at the configured interval and tell the frontend
+fn spawn_health_check_timer(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let app_state = app_handle.state::<AppState>();
+            let now = db::get_current_timestamp();
+            // Nothing to do while the database is locked
+            let due = app_state.with_db(move |conn| health::start_scheduled_run(conn, now).map_err(CommandError::from)).await;
+            if !matches!(due, Ok(true)) {
+                continue;
+            }
+            match check_all_providers(&app_state).await {
+                Ok(checks) => {
+                    let _ = app_handle.emit("health-checked", checks);
+                }
+                Err(e) => eprintln!("Scheduled health check failed: {}", e),
+            }
+        }
+    });
 }
 
 #[tokio::main]
 async fn main() {
     // Initialize database and AI client before creating the app
-    let db_conn = init_database().expect("Could not initialize database");
-    let ai_client = ai::AIClient::new();
+    let (data_dir_flag, profile_flag) = profiles::parse_args(std::env::args().skip(1));
+    let data_root_overridden = profiles::data_root_override(data_dir_flag.clone()).is_some();
+    let location = profiles::resolve(data_dir_flag, profile_flag).expect("Could not find app data directory");
+    credentials::set_profile(&location.profile);
+    let db = init_database(&location.dir).expect("Could not initialize database");
+    
+    let handler = tauri::generate_handler![
+        // Provider commands
+        get_providers,
+        add_provider,
+        update_provider,
+        delete_provider,
+        get_provider_connection_settings,
+        set_provider_connection_settings,
+        get_azure_settings,
+        set_azure_settings,
+        fetch_azure_deployments,
+        diagnose_provider,
+        
+        // Model commands
+        get_models,
+        add_model,
+        delete_model,
+        fetch_models_from_provider,
+        sync_models,
+        toggle_model_favorite,
+        set_model_api_type,
+        
+        // Chat session commands
+        get_chat_sessions,
+        create_chat_session,
+        update_chat_session,
+        delete_chat_session,
+        get_chat_session,
+        list_chat_sessions,
+        set_session_pinned,
+        set_session_archived,
+        move_session_to_folder,
+        
+        // Session folder and tag commands
+        get_session_folders,
+        create_session_folder,
+        update_session_folder,
+        delete_session_folder,
+        get_session_tags,
+        create_session_tag,
+        update_session_tag,
+        delete_session_tag,
+        set_session_tags,
+        
+        // Chat message commands
+        get_chat_messages,
+        add_chat_message,
+        generate_session_title,
+        // Session summary commands
+        get_session_summary,
+        update_session_summary,
+        regenerate_session_summary,
+        delete_session_summary,
+        get_summary_policy,
+        set_summary_policy,
+        // Memory commands
+        get_memories,
+        add_memory,
+        update_memory,
+        delete_memory,
+        // Knowledge base commands
+        get_kb_collections,
+        create_kb_collection,
+        update_kb_collection,
+        delete_kb_collection,
+        get_kb_documents,
+        add_kb_documents,
+        reindex_kb_collection,
+        delete_kb_document,
+        search_kb,
+        get_session_citations,
+        compare_models,
+        promote_comparison_candidate,
+        get_session_comparisons,
+        get_fallback_chain,
+        set_fallback_chain,
+        
+        // Prompt template commands
+        get_prompt_templates,
+        create_prompt_template,
+        update_prompt_template,
+        delete_prompt_template,
+        render_prompt_template,
+        insert_prompt_template,
+        export_prompt_templates,
+        import_prompt_templates,
+        
+        // Settings commands
+        get_setting,
+        set_setting,
+        
+        // AI commands
+        send_chat_request,
+        verify_model,
+        run_health_check,
+        run_all_health_checks,
+        get_provider_health,
+        get_health_history,
+        get_health_check_schedule,
+        set_health_check_schedule,
+        get_retry_policy,
+        set_retry_policy,
+        
+        // Local API server commands
+        get_api_server_status,
+        set_api_server_settings,
+        regenerate_api_server_token,
+        get_api_server_logs,
+        clear_api_server_logs,
+        
+        // Database encryption commands
+        get_database_encryption,
+        unlock_database,
+        enable_database_encryption,
+        change_database_passphrase,
+        disable_database_encryption,
+        
+        // App lock commands
+        get_app_lock_status,
+        set_app_lock_passphrase,
+        disable_app_lock,
+        set_app_lock_idle_timeout,
+        lock_app,
+        unlock_app,
+        
+        // Data directory and profile commands
+        get_data_location,
+        create_profile,
+        switch_profile,
+        delete_profile,
+        set_data_directory,
+        
+        // Config export commands
+        export_config,
+        import_config,
+        get_catalog_status,
+        set_catalog_source,
+        sync_catalog,
+    ];
     
     tauri::Builder::default()
         .manage(AppState {
-            db_conn: Mutex::new(db_conn),
-            ai_client: Mutex::new(ai_client),
+            db: RwLock::new(db),
+            db_access: Arc::new(tokio::sync::RwLock::new(())),
+            location: RwLock::new(location),
+            data_root_overridden,
+            ai_client: Mutex::new(ai::AIClient::new()),
+            api_server: Mutex::new(None),
+            app_lock: Mutex::new(app_lock::AppLock::default()),
+            model_listings: Mutex::new(HashMap::new()),
         })
-        .invoke_handler(tauri::generate_handler![
-            // Provider commands
-            get_providers,
-            add_provider,
-            update_provider,
-            delete_provider,
-            
-            // Model commands
-            get_models,
-            add_model,
-            delete_model,
-            fetch_models_from_provider,
-            toggle_model_favorite,
+        .setup(|app| {
+            // Report provider retries to the frontend
+            let handle = app.handle().clone();
+            let state = app.state::<AppState>();
+            let mut client = state.ai_client.lock().map_err(|e| e.to_string())?;
+            client.set_retry_listener(Arc::new(move |attempt: &ai::RetryAttempt| {
+                let _ = handle.emit("ai-retry", attempt.clone());
+            }));
+            drop(client);
             
-            // Chat session commands
-            get_chat_sessions,
-            create_chat_session,
-            update_chat_session,
-            delete_chat_session,
-            get_chat_session,
-            
-            // Chat message commands
-            get_chat_messages,
-            add_chat_message,
-            
-
-            
-            // Settings commands
-            get_setting,
-            set_setting,
-            
-            // AI commands
-            send_chat_request,
-            verify_model,
-        ])
+            // A locked database is loaded once the user unlocks it. Unlocking
+            // the database stands in for the app lock, so the app only starts
+            // locked when the database opened on its own.
+            if state.db.read().map_err(|e| e.to_string())?.is_some() {
+                load_database_settings(app.handle(), &state);
+                state.app_lock.lock().map_err(|e| e.to_string())?.lock();
+            }
+            spawn_idle_lock_timer(app.handle().clone());
+            spawn_catalog_sync_timer(app.handle().clone());
+            spawn_health_check_timer(app.handle().clone());
+            Ok(())
+        })
+        .invoke_handler(move |invoke| {
+            // Refuse commands while the app is locked
+            let webview = invoke.message.webview();
+            let app_state = webview.state::<AppState>();
+            let allowed = match app_state.app_lock.lock() {
+                Ok(mut lock) => lock.allow(invoke.message.command(), Instant::now()),
+                Err(_) => false,
+            };
+            if !allowed {
+                invoke.resolver.reject(CommandError::AppLocked {
+                    message: "AIChat Pro is locked".to_string(),
+                });
+                return true;
+            }
+            handler(invoke)
+        })
         .run(tauri::generate_context!())
         .expect("Error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_encryption_waits_for_running_writes() {
+        let dir = std::env::temp_dir().join(format!("aichat-pro-rekey-{}", Uuid::new_v4()));
+        let app_state = AppState {
+            db: RwLock::new(Some(db::open_pool(&dir, None).unwrap())),
+            db_access: Arc::new(tokio::sync::RwLock::new(())),
+            location: RwLock::new(profiles::DataLocation {
+                root: dir.clone(),
+                profile: "default".to_string(),
+                dir: dir.clone(),
+            }),
+            data_root_overridden: false,
+            ai_client: Mutex::new(ai::AIClient::new()),
+            api_server: Mutex::new(None),
+            app_lock: Mutex::new(app_lock::AppLock::default()),
+            model_listings: Mutex::new(HashMap::new()),
+        };
+
+        // The write is still running when encryption starts, and would land after
+        // the export if encryption did not wait for it (deriving the key is slow)
+        let write = app_state.with_db(|conn| {
+            std::thread::sleep(Duration::from_secs(2));
+            db::set_setting(conn, "rekey_test", "1").map_err(CommandError::from)
+        });
+        let key = db::DatabaseKey::Passphrase("secret".to_string());
+        let encrypt = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            rekey_open_database(&app_state, None, Some(key.clone())).await
+        };
+        let (written, encrypted) = tokio::join!(write, encrypt);
+        written.unwrap();
+        encrypted.unwrap();
+
+        assert!(db::is_encrypted(&dir));
+        let value = app_state.with_db(|conn| db::get_setting(conn, "rekey_test").map_err(CommandError::from)).await;
+        assert_eq!(value.unwrap().as_deref(), Some("1"));
+        let reopened = db::open_pool(&dir, Some(key)).unwrap();
+        assert_eq!(db::get_setting(&reopened.get().unwrap(), "rekey_test").unwrap().as_deref(), Some("1"));
+
+        drop((app_state, reopened));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+// What is known about a model beyond its name: limits, prices, capabilities
+// and modalities. Values come from the team catalog or the provider's model
+// listing; a built-in table of well-known models fills in what those leave out.
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelMetadata {
+    // Context window in tokens
+    pub context_length: Option<i64>,
+    // Most tokens the model produces in one response
+    pub max_output_tokens: Option<i64>,
+    // Prices in USD per million tokens
+    pub input_price: Option<f64>,
+    pub output_price: Option<f64>,
+    // Capabilities, None when unknown
+    pub supports_vision: Option<bool>,
+    pub supports_tools: Option<bool>,
+    pub supports_streaming: Option<bool>,
+    pub supports_reasoning: Option<bool>,
+    // e.g. ["text", "image"], empty when unknown
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub input_modalities: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub output_modalities: Vec<String>,
+}
+
+impl ModelMetadata {
+    // Fill the fields left unknown from `fallback`
+    pub fn or(self, fallback: ModelMetadata) -> ModelMetadata {
+        let or_vec = |value: Vec<String>, fallback: Vec<String>| if value.is_empty() { fallback } else { value };
+        ModelMetadata {
+            context_length: self.context_length.or(fallback.context_length),
+            max_output_tokens: self.max_output_tokens.or(fallback.max_output_tokens),
+            input_price: self.input_price.or(fallback.input_price),
+            output_price: self.output_price.or(fallback.output_price),
+            supports_vision: self.supports_vision.or(fallback.supports_vision),
+            supports_tools: self.supports_tools.or(fallback.supports_tools),
+            supports_streaming: self.supports_streaming.or(fallback.supports_streaming),
+            supports_reasoning: self.supports_reasoning.or(fallback.supports_reasoning),
+            input_modalities: or_vec(self.input_modalities, fallback.input_modalities),
+            output_modalities: or_vec(self.output_modalities, fallback.output_modalities),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == ModelMetadata::default()
+    }
+}
+
+// A model offered by a provider's listing endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(flatten)]
+    pub metadata: ModelMetadata,
+}
+
+impl ModelInfo {
+    pub fn named(id: impl Into<String>) -> Self {
+        ModelInfo { id: id.into(), metadata: ModelMetadata::default() }
+    }
+}
+
+// Built-in entry: name prefix, context length, max output tokens, input and
+// output price, vision, tools, reasoning
+type KnownModel = (&'static str, i64, Option<i64>, f64, f64, bool, bool, bool);
+
+// List prices at the time of writing. The longest matching prefix wins, so
+// "gpt-4o-mini" is not priced as "gpt-4o".
+const KNOWN_MODELS: &[KnownModel] = &[
+    ("gpt-5-nano", 400_000, Some(128_000), 0.05, 0.4, true, true, true),
+    ("gpt-5-mini", 400_000, Some(128_000), 0.25, 2.0, true, true, true),
+    ("gpt-5", 400_000, Some(128_000), 1.25, 10.0, true, true, true),
+    ("gpt-4.1-nano", 1_047_576, Some(32_768), 0.1, 0.4, true, true, false),
+    ("gpt-4.1-mini", 1_047_576, Some(32_768), 0.4, 1.6, true, true, false),
+    ("gpt-4.1", 1_047_576, Some(32_768), 2.0, 8.0, true, true, false),
+    ("gpt-4o-mini", 128_000, Some(16_384), 0.15, 0.6, true, true, false),
+    ("gpt-4o", 128_000, Some(16_384), 2.5, 10.0, true, true, false),
+    ("gpt-4-turbo", 128_000, Some(4_096), 10.0, 30.0, true, true, false),
+    ("gpt-3.5-turbo", 16_385, Some(4_096), 0.5, 1.5, false, true, false),
+    ("o4-mini", 200_000, Some(100_000), 1.1, 4.4, true, true, true),
+    ("o3-mini", 200_000, Some(100_000), 1.1, 4.4, false, true, true),
+    ("o3", 200_000, Some(100_000), 2.0, 8.0, true, true, true),
+    ("o1-mini", 128_000, Some(65_536), 1.1, 4.4, false, false, true),
+    ("o1", 200_000, Some(100_000), 15.0, 60.0, true, true, true),
+    ("deepseek-chat", 128_000, Some(8_192), 0.27, 1.1, false, true, false),
+    ("deepseek-reasoner", 128_000, Some(64_000), 0.55, 2.19, false, false, true),
+    ("gemini-2.5-pro", 1_048_576, Some(65_536), 1.25, 10.0, true, true, true),
+    ("gemini-2.5-flash", 1_048_576, Some(65_536), 0.3, 2.5, true, true, true),
+    ("gemini-2.0-flash", 1_048_576, Some(8_192), 0.1, 0.4, true, true, false),
+    ("gemini-1.5-pro", 2_097_152, Some(8_192), 1.25, 5.0, true, true, false),
+    ("gemini-1.5-flash", 1_048_576, Some(8_192), 0.075, 0.3, true, true, false),
+    ("gemini-1.0-pro", 32_760, Some(8_192), 0.5, 1.5, false, true, false),
+    ("grok-4", 256_000, None, 3.0, 15.0, true, true, true),
+    ("grok-3-mini", 131_072, None, 0.3, 0.5, false, true, true),
+    ("grok-3", 131_072, None, 3.0, 15.0, false, true, false),
+    ("claude-opus-4", 200_000, Some(32_000), 15.0, 75.0, true, true, true),
+    ("claude-sonnet-4", 200_000, Some(64_000), 3.0, 15.0, true, true, true),
+    ("claude-3-7-sonnet", 200_000, Some(64_000), 3.0, 15.0, true, true, true),
+    ("claude-3-5-sonnet", 200_000, Some(8_192), 3.0, 15.0, true, true, false),
+    ("claude-3-5-haiku", 200_000, Some(8_192), 0.8, 4.0, false, true, false),
+];
+
+// Metadata of a well-known model, matched on the name without any vendor
+// prefix such as OpenRouter's "openai/"
+pub fn builtin_metadata(model: &str) -> ModelMetadata {
+    let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+    let known = KNOWN_MODELS
+        .iter()
+        .filter(|entry| name.starts_with(entry.0))
+        .max_by_key(|entry| entry.0.len());
+
+    let Some(&(_, context_length, max_output_tokens, input_price, output_price, vision, tools, reasoning)) = known else {
+        return ModelMetadata::default();
+    };
+    let mut input_modalities = vec!["text".to_string()];
+    if vision {
+        input_modalities.push("image".to_string());
+    }
+    ModelMetadata {
+        context_length: Some(context_length),
+        max_output_tokens,
+        input_price: Some(input_price),
+        output_price: Some(output_price),
+        supports_vision: Some(vision),
+        supports_tools: Some(tools),
+        supports_streaming: Some(true),
+        supports_reasoning: Some(reasoning),
+        input_modalities,
+        output_modalities: vec!["text".to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_metadata_prefers_longest_prefix() {
+        let mini = builtin_metadata("gpt-4o-mini-2024-07-18");
+        assert_eq!(mini.input_price, Some(0.15));
+        assert_eq!(builtin_metadata("gpt-4o").input_price, Some(2.5));
+
+        let routed = builtin_metadata("deepseek/deepseek-reasoner");
+        assert_eq!(routed.supports_reasoning, Some(true));
+        assert_eq!(routed.supports_vision, Some(false));
+        assert_eq!(routed.input_modalities, vec!["text"]);
+
+        assert!(builtin_metadata("llama3.2:latest").is_empty());
+    }
+
+    #[test]
+    fn test_listing_values_take_precedence() {
+        let listed = ModelMetadata {
+            context_length: Some(64_000),
+            supports_tools: Some(false),
+            ..ModelMetadata::default()
+        };
+        let merged = listed.or(builtin_metadata("deepseek-chat"));
+        assert_eq!(merged.context_length, Some(64_000));
+        assert_eq!(merged.supports_tools, Some(false));
+        assert_eq!(merged.output_price, Some(1.1));
+    }
+}
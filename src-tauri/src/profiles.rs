@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Where the app keeps its data. The data root comes from the --data-dir flag,
+// the AICHAT_DATA_DIR variable, the saved setting or the platform data
+// directory, in that order. Each named profile has its own directory under the
+// root with its own database; the default profile uses the root itself.
+
+pub const DATA_DIR_ENV: &str = "AICHAT_DATA_DIR";
+pub const PROFILE_ENV: &str = "AICHAT_PROFILE";
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Directory name under the platform data and config directories, the same as
+// Tauri's app data path for the bundle identifier
+const APP_DIR_NAME: &str = "com.aichatpro.desktop";
+
+// Data directory used before the platform directories were
+const LEGACY_DIR_NAME: &str = ".aichat-pro";
+
+const CONFIG_FILE_NAME: &str = "config.json";
+const PROFILES_DIR_NAME: &str = "profiles";
+const MAX_PROFILE_NAME_LEN: usize = 64;
+
+// Settings kept outside the data directory, since they locate it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub data_dir: Option<PathBuf>,
+    // Profile opened at startup
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+// Data directory of the open profile
+#[derive(Debug, Clone, Serialize)]
+pub struct DataLocation {
+    pub root: PathBuf,
+    pub profile: String,
+    pub dir: PathBuf,
+}
+
+// Path of the config file in the platform config directory
+pub fn config_path() -> Result<PathBuf, String> {
+    let config_dir = dirs::config_dir().ok_or_else(|| "Could not find config directory".to_string())?;
+    Ok(config_dir.join(APP_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+// Load the config, treating a missing file as empty
+pub fn load_config(path: &Path) -> Result<Config, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(format!("Could not read config file {}: {}", path.display(), e)),
+    }
+}
+
+pub fn save_config(path: &Path, config: &Config) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Could not create config directory: {}", e))?;
+    }
+    let text = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    fs::write(path, text).map_err(|e| format!("Could not write config file {}: {}", path.display(), e))
+}
+
+// Platform data directory for the app, e.g. `$XDG_DATA_HOME/com.aichatpro.desktop`
+pub fn default_data_root() -> Result<PathBuf, String> {
+    let data_dir = dirs::data_dir().ok_or_else(|| "Could not find data directory".to_string())?;
+    Ok(data_dir.join(APP_DIR_NAME))
+}
+
+// Data directory flag and profile flag from the app's command line, accepting
+// both `--flag value` and `--flag=value`
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> (Option<PathBuf>, Option<String>) {
+    let (mut data_dir, mut profile) = (None, None);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        match flag.as_str() {
+            "--data-dir" => data_dir = value.or_else(|| args.next()).map(PathBuf::from),
+            "--profile" => profile = value.or_else(|| args.next()),
+            _ => {}
+        }
+    }
+    (data_dir, profile)
+}
+
+// Data root given by a flag or the environment, which take precedence over
+// the saved setting
+pub fn data_root_override(flag: Option<PathBuf>) -> Option<PathBuf> {
+    flag.or_else(|| std::env::var_os(DATA_DIR_ENV).filter(|v| !v.is_empty()).map(PathBuf::from))
+}
+
+// Find the data directory to open. Without an override or saved setting the
+// platform directory is used, after moving data over from `~/.aichat-pro`.
+pub fn resolve(data_dir_flag: Option<PathBuf>, profile_flag: Option<String>) -> Result<DataLocation, String> {
+    let config = load_config(&config_path()?)?;
+    let root = match data_root_override(data_dir_flag).or(config.data_dir) {
+        Some(root) => root,
+        None => {
+            let root = default_data_root()?;
+            if let Some(home) = dirs::home_dir() {
+                migrate_legacy_dir(&home.join(LEGACY_DIR_NAME), &root)?;
+            }
+            root
+        }
+    };
+    let profile = profile_flag
+        .or_else(|| std::env::var(PROFILE_ENV).ok().filter(|v| !v.is_empty()))
+        .or(config.profile)
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    locate(&root, &profile)
+}
+
+// Data directory of a profile under `root`, created if needed
+pub fn locate(root: &Path, profile: &str) -> Result<DataLocation, String> {
+    validate_profile_name(profile)?;
+    let dir = profile_dir(root, profile);
+    fs::create_dir_all(&dir).map_err(|e| format!("Could not create app data directory: {}", e))?;
+    Ok(DataLocation {
+        root: root.to_path_buf(),
+        profile: profile.to_string(),
+        dir,
+    })
+}
+
+pub fn profile_dir(root: &Path, profile: &str) -> PathBuf {
+    if profile == DEFAULT_PROFILE {
+        root.to_path_buf()
+    } else {
+        root.join(PROFILES_DIR_NAME).join(profile)
+    }
+}
+
+// Profile names become directory names, so only letters, digits, spaces,
+// dashes and underscores are allowed
+pub fn validate_profile_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name.trim() != name {
+        return Err("Profile name cannot be empty or start or end with spaces".to_string());
+    }
+    if name.chars().count() > MAX_PROFILE_NAME_LEN {
+        return Err(format!("Profile name cannot be longer than {} characters", MAX_PROFILE_NAME_LEN));
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_')) {
+        return Err("Profile name can only contain letters, digits, spaces, dashes and underscores".to_string());
+    }
+    Ok(())
+}
+
+// Profiles under `root`, the default one first
+pub fn list_profiles(root: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(root.join(PROFILES_DIR_NAME))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .filter(|name| validate_profile_name(name).is_ok() && name != DEFAULT_PROFILE)
+        .collect();
+    names.sort();
+    names.insert(0, DEFAULT_PROFILE.to_string());
+    names
+}
+
+pub fn profile_exists(root: &Path, profile: &str) -> bool {
+    profile == DEFAULT_PROFILE || profile_dir(root, profile).is_dir()
+}
+
+// Delete a profile's directory and everything in it
+pub fn delete_profile(root: &Path, profile: &str) -> Result<(), String> {
+    validate_profile_name(profile)?;
+    if profile == DEFAULT_PROFILE {
+        return Err("The default profile cannot be deleted".to_string());
+    }
+    fs::remove_dir_all(profile_dir(root, profile)).map_err(|e| format!("Could not delete profile: {}", e))
+}
+
+// Move the contents of the old data directory into `target`, unless `target`
+// already holds a database. Returns whether anything was moved.
+pub fn migrate_legacy_dir(legacy: &Path, target: &Path) -> Result<bool, String> {
+    if !legacy.is_dir() || crate::db::database_path(target).exists() {
+        return Ok(false);
+    }
+
+    let error = |e: std::io::Error| format!("Could not move data from {}: {}", legacy.display(), e);
+    fs::create_dir_all(target).map_err(error)?;
+    for entry in fs::read_dir(legacy).map_err(error)? {
+        let entry = entry.map_err(error)?;
+        fs::rename(entry.path(), target.join(entry.file_name())).map_err(error)?;
+    }
+    fs::remove_dir(legacy).map_err(error)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("aichat-pro-{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = ["aichat-pro", "--data-dir", "/data", "--profile=work"].map(String::from);
+        assert_eq!(parse_args(args), (Some(PathBuf::from("/data")), Some("work".to_string())));
+        assert_eq!(parse_args(["aichat-pro".to_string()]), (None, None));
+    }
+
+    #[test]
+    fn test_profiles_under_root() {
+        let root = temp_dir("profiles");
+        let work = locate(&root, "work").unwrap();
+        assert_eq!(work.dir, root.join("profiles").join("work"));
+        assert_eq!(locate(&root, DEFAULT_PROFILE).unwrap().dir, root);
+
+        assert_eq!(list_profiles(&root), vec!["default", "work"]);
+        assert!(profile_exists(&root, "work"));
+        assert!(!profile_exists(&root, "personal"));
+
+        assert!(locate(&root, "../escape").is_err());
+        assert!(validate_profile_name(" padded").is_err());
+        assert!(validate_profile_name("Personal 2").is_ok());
+        assert!(delete_profile(&root, DEFAULT_PROFILE).is_err());
+
+        delete_profile(&root, "work").unwrap();
+        assert_eq!(list_profiles(&root), vec!["default"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_legacy_dir_and_config() {
+        let legacy = temp_dir("legacy");
+        let target = temp_dir("target");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(crate::db::database_path(&legacy), b"data").unwrap();
+
+        assert!(migrate_legacy_dir(&legacy, &target).unwrap());
+        assert!(!legacy.exists());
+        assert_eq!(fs::read(crate::db::database_path(&target)).unwrap(), b"data");
+        // Nothing to move the second time
+        assert!(!migrate_legacy_dir(&legacy, &target).unwrap());
+
+        let path = target.join(CONFIG_FILE_NAME);
+        assert_eq!(load_config(&path).unwrap(), Config::default());
+        let config = Config { data_dir: Some(PathBuf::from("/data")), profile: Some("work".to_string()) };
+        save_config(&path, &config).unwrap();
+        assert_eq!(load_config(&path).unwrap(), config);
+        fs::remove_dir_all(&target).unwrap();
+    }
+}
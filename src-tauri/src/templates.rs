@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+// Current version of the template pack file format
+pub const TEMPLATE_PACK_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum TemplateError {
+    MissingVariables(Vec<String>),
+    InvalidValue { name: String, reason: String },
+    UnsupportedPackVersion(u32),
+    SerdeError(serde_json::Error),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::MissingVariables(names) => write!(f, "Missing template variables: {}", names.join(", ")),
+            TemplateError::InvalidValue { name, reason } => write!(f, "Invalid value for variable '{}': {}", name, reason),
+            TemplateError::UnsupportedPackVersion(v) => write!(f, "Unsupported template pack version: {}", v),
+            TemplateError::SerdeError(e) => write!(f, "Template pack JSON error: {}", e),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+impl From<serde_json::Error> for TemplateError {
+    fn from(error: serde_json::Error) -> Self {
+        TemplateError::SerdeError(error)
+    }
+}
+
+// Type of a template variable, used to validate supplied values
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    #[default]
+    Text,
+    Number,
+    Boolean,
+    Choice,
+}
+
+// Definition of a single `{{variable}}` placeholder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateVariable {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub var_type: VariableType,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub default: Option<String>,
+    // Allowed values for `choice` variables
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+// A template as stored in an exported pack (ids and timestamps are not portable)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackTemplate {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub content: String,
+    #[serde(default)]
+    pub variables: Vec<TemplateVariable>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplatePack {
+    pub version: u32,
+    pub templates: Vec<PackTemplate>,
+}
+
+// Extract the unique placeholder names used in a template, in order of appearance
+pub fn extract_placeholders(content: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                if is_valid_name(name) && !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+                rest = &after[end + 2..];
+            }
+            None => break,
+        }
+    }
+
+    names
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+// Check a supplied value against the variable's declared type
+fn validate_value(variable: &TemplateVariable, value: &str) -> Result<(), TemplateError> {
+    let invalid = |reason: &str| TemplateError::InvalidValue {
+        name: variable.name.clone(),
+        reason: reason.to_string(),
+    };
+
+    match variable.var_type {
+        VariableType::Text => Ok(()),
+        VariableType::Number => value.trim().parse::<f64>().map(|_| ()).map_err(|_| invalid("expected a number")),
+        VariableType::Boolean => match value.trim() {
+            "true" | "false" => Ok(()),
+            _ => Err(invalid("expected true or false")),
+        },
+        VariableType::Choice => {
+            if variable.options.iter().any(|o| o == value) {
+                Ok(())
+            } else {
+                Err(invalid(&format!("expected one of: {}", variable.options.join(", "))))
+            }
+        }
+    }
+}
+
+// Render a template, substituting each placeholder with the supplied value or the
+// variable's default. All missing variables are reported together.
+pub fn render(
+    content: &str,
+    variables: &[TemplateVariable],
+    values: &HashMap<String, String>,
+) -> Result<String, TemplateError> {
+    let mut resolved: HashMap<String, String> = HashMap::new();
+    let mut missing = Vec::new();
+
+    for name in extract_placeholders(content) {
+        let definition = variables.iter().find(|v| v.name == name);
+        let value = values
+            .get(&name)
+            .cloned()
+            .or_else(|| definition.and_then(|d| d.default.clone()));
+
+        match value {
+            Some(value) => {
+                if let Some(definition) = definition {
+                    validate_value(definition, &value)?;
+                }
+                resolved.insert(name, value);
+            }
+            None => missing.push(name),
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(TemplateError::MissingVariables(missing));
+    }
+
+    // Substitute in a single pass so values containing `{{...}}` are not expanded again
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                output.push_str(&rest[..start]);
+                match resolved.get(name) {
+                    Some(value) => output.push_str(value),
+                    None => output.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => break,
+        }
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+// Parse a template pack exported by `export_pack`
+pub fn parse_pack(json: &str) -> Result<TemplatePack, TemplateError> {
+    let pack: TemplatePack = serde_json::from_str(json)?;
+    if pack.version > TEMPLATE_PACK_VERSION {
+        return Err(TemplateError::UnsupportedPackVersion(pack.version));
+    }
+    Ok(pack)
+}
+
+// Serialize templates into a portable pack
+pub fn export_pack(templates: Vec<PackTemplate>) -> Result<String, TemplateError> {
+    let pack = TemplatePack {
+        version: TEMPLATE_PACK_VERSION,
+        templates,
+    };
+    Ok(serde_json::to_string_pretty(&pack)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str, var_type: VariableType, default: Option<&str>) -> TemplateVariable {
+        TemplateVariable {
+            name: name.to_string(),
+            var_type,
+            description: None,
+            default: default.map(|d| d.to_string()),
+            options: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_extract_placeholders() {
+        let names = extract_placeholders("Translate {{ text }} into {{language}}. Keep {{text}} short. {{}} {{bad name}}");
+        assert_eq!(names, vec!["text", "language"]);
+    }
+
+    #[test]
+    fn test_render_uses_values_and_defaults() {
+        let variables = vec![variable("language", VariableType::Text, Some("English"))];
+        let mut values = HashMap::new();
+        values.insert("text".to_string(), "bonjour {{language}}".to_string());
+
+        let rendered = render("Translate {{text}} into {{ language }}.", &variables, &values).unwrap();
+        assert_eq!(rendered, "Translate bonjour {{language}} into English.");
+    }
+
+    #[test]
+    fn test_render_reports_all_missing_variables() {
+        let result = render("{{a}} {{b}} {{c}}", &[variable("b", VariableType::Text, Some("x"))], &HashMap::new());
+        match result {
+            Err(TemplateError::MissingVariables(names)) => assert_eq!(names, vec!["a", "c"]),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_render_validates_types() {
+        let mut choice = variable("tone", VariableType::Choice, None);
+        choice.options = vec!["formal".to_string(), "casual".to_string()];
+        let variables = vec![variable("count", VariableType::Number, None), choice];
+
+        let mut values = HashMap::new();
+        values.insert("count".to_string(), "three".to_string());
+        values.insert("tone".to_string(), "casual".to_string());
+        assert!(matches!(
+            render("{{count}} {{tone}}", &variables, &values),
+            Err(TemplateError::InvalidValue { .. })
+        ));
+
+        values.insert("count".to_string(), "3".to_string());
+        assert_eq!(render("{{count}} {{tone}}", &variables, &values).unwrap(), "3 casual");
+    }
+
+    #[test]
+    fn test_pack_round_trip() {
+        let json = export_pack(vec![PackTemplate {
+            name: "Summarize".to_string(),
+            description: None,
+            category: Some("writing".to_string()),
+            tags: vec!["summary".to_string()],
+            content: "Summarize {{text}}".to_string(),
+            variables: vec![variable("text", VariableType::Text, None)],
+        }])
+        .unwrap();
+
+        let pack = parse_pack(&json).unwrap();
+        assert_eq!(pack.version, TEMPLATE_PACK_VERSION);
+        assert_eq!(pack.templates.len(), 1);
+        assert_eq!(pack.templates[0].tags, vec!["summary"]);
+
+        assert!(matches!(
+            parse_pack(r#"{"version": 99, "templates": []}"#),
+            Err(TemplateError::UnsupportedPackVersion(99))
+        ));
+    }
+}
@@ -0,0 +1,30 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+
+// Helpers shared by the tests of several modules
+
+// Serve `requests` HTTP requests on a local port and return its base URL.
+// `respond` picks the status line and JSON body from the raw request.
+pub(crate) fn serve<F>(requests: usize, respond: F) -> String
+where
+    F: Fn(&str) -> (&'static str, String) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for _ in 0..requests {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4096];
+            let n = stream.read(&mut request).unwrap_or(0);
+            let (status, body) = respond(&String::from_utf8_lossy(&request[..n]));
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    url
+}
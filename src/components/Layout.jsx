@@ -1,6 +1,7 @@
 import { useState, useEffect, useRef } from "react";
 import { Outlet, NavLink, useLocation, useNavigate } from "react-router-dom";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { useI18n } from "../i18n/index.jsx";
 import { Button } from "@/components/retroui/Button";
 import { Card } from "@/components/retroui/Card";
@@ -38,6 +39,18 @@ function Sidebar() {
     loadChatSessions();
   }, [location.pathname]); // Reload when path changes
 
+  // Apply titles generated by the backend after a session's first exchange
+  useEffect(() => {
+    const unlisten = listen("session-title-updated", ({ payload }) => {
+      setChatSessions((sessions) =>
+        sessions.map((s) => (s.id === payload.session_id ? { ...s, name: payload.name } : s))
+      );
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
   // Focus input when editing starts
   useEffect(() => {
     if (editingSessionId && editInputRef.current) {
@@ -49,6 +49,7 @@ const translations = {
     chat: {
       newChat: "New Chat",
       thinking: "Thinking...",
+      fallingBack: "Model unavailable, trying {name}...",
       typeMessage: "Type your message...",
       send: "Send",
       stopGeneration: "Stop Generation",
@@ -216,6 +217,7 @@ const translations = {
     chat: {
       newChat: "新建聊天",
       thinking: "思考中...",
+      fallingBack: "模型不可用，正在尝试 {name}...",
       typeMessage: "输入您的消息...",
       send: "发送",
       stopGeneration: "停止生成",
@@ -1,6 +1,7 @@
 import { useState, useEffect, useRef, useCallback, useMemo } from "react";
 import { useParams } from "react-router-dom";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { useI18n } from "../i18n/index.jsx";
 import Message from "../components/Message";
 // Import UI components
@@ -110,6 +111,31 @@ export default function ChatPage() {
     };
   }, [loadProviders]);
   
+  // Show which model takes over when the selected one fails
+  useEffect(() => {
+    const unlisten = listen("chat-model-fallback", ({ payload }) => {
+      if (payload.session_id !== sessionId) return;
+      setStreamingMessage({
+        role: "assistant",
+        content: t('chat.fallingBack', { name: payload.to_model_name }),
+      });
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [sessionId]);
+
+  // Keep the header in sync with titles generated by the backend
+  useEffect(() => {
+    const unlisten = listen("session-title-updated", ({ payload }) => {
+      if (payload.session_id !== sessionId) return;
+      setChatSession((session) => (session ? { ...session, name: payload.name } : session));
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [sessionId]);
+
   // Load models when provider is selected
   useEffect(() => {
     const loadModels = async () => {
@@ -346,7 +372,8 @@ export default function ChatPage() {
         request: {
           provider_id: selectedProviderId,
           model_id: selectedModelId,
-          messages: [...messages.map(m => ({ role: m.role, content: m.content })), userMessage]
+          messages: [...messages.map(m => ({ role: m.role, content: m.content })), userMessage],
+          session_id: sessionId
         }
       });
       
@@ -366,6 +393,9 @@ export default function ChatPage() {
           session_id: sessionId,
           role: "assistant",
           content: response.content,
+          response_id: response.response_id,
+          model_id: response.model_id,
+          citations: response.citations,
         },
       });
       
@@ -377,6 +407,7 @@ export default function ChatPage() {
           role: "assistant", 
           content: response.content,
           reasoning: response.reasoning,
+          model_id: response.model_id,
           timestamp: Date.now(),
           isNew: true // 添加新回复标记
         }
@@ -397,7 +428,7 @@ export default function ChatPage() {
         { 
           id: `error-${Date.now()}`, 
           role: "assistant", 
-          content: `Error: ${error.toString()}`,
+          content: `Error: ${error?.message ?? String(error)}`,
           timestamp: Date.now()
         }
       ]);
@@ -420,7 +451,6 @@ export default function ChatPage() {
       await invoke("update_chat_session", {
         session: {
           id: sessionId,
-          name: chatSession.name,
           model_id: selectedModelId,
           system_prompt: chatSession.system_prompt
         }
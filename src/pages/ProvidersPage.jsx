@@ -311,23 +311,8 @@ export default function ProvidersPage() {
   async function fetchModelsFromProvider(providerId) {
     try {
       setLoadingModels(prev => ({ ...prev, [providerId]: true }));
-      const models = await invoke("fetch_models_from_provider", { providerId });
-      
-      // For each fetched model, add it if it doesn't already exist
-      for (const modelName of models) {
-        // Check if model already exists
-        const existingModels = modelsMap[providerId] || [];
-        const exists = existingModels.some(m => m.name === modelName);
-        
-        if (!exists) {
-          await invoke("add_model", {
-            model: {
-              provider_id: providerId,
-              name: modelName
-            }
-          });
-        }
-      }
+      // Adds new models and marks the ones the provider dropped as unavailable
+      await invoke("sync_models", { providerId });
       
       // Reload models for this provider
       await loadModelsForProvider(providerId);
@@ -371,7 +356,7 @@ export default function ProvidersPage() {
       console.error(`Failed to verify model ${model.name}:`, error);
       setVerificationStatus(prev => ({
         ...prev,
-        [model.id]: { status: 'error', message: error }
+        [model.id]: { status: 'error', message: error?.message ?? String(error), kind: error?.kind }
       }));
       return false;
     }
@@ -621,7 +606,7 @@ export default function ProvidersPage() {
                       </span>
                     )}
                     {(status.status === 'error' || status.status === 'failed') && (
-                      <span title={`Verification failed: ${status.message || 'Unknown error'}`} className="text-red-500 cursor-help">
+                      <span title={`Verification failed: ${status.message || 'Unknown error'}${status.kind ? ` (${status.kind.replace(/_/g, ' ')})` : ''}`} className="text-red-500 cursor-help">
                         <svg xmlns="http://www.w3.org/2000/svg" className="h-5 w-5" fill="none" viewBox="0 0 24 24" stroke="currentColor" strokeWidth={2}>
                           <path strokeLinecap="round" strokeLinejoin="round" d="M10 14l2-2m0 0l2-2m-2 2l-2-2m2 2l2 2m7-2a9 9 0 11-18 0 9 9 0 0118 0z" />
                         </svg>